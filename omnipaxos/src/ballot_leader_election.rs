@@ -10,10 +10,10 @@ use crate::{
 use crate::utils::logger::create_logger;
 use crate::{
     messages::ballot_leader_election::{
-        BLEMessage, HeartbeatMsg, HeartbeatReply, HeartbeatRequest,
+        BLEMessage, HeartbeatMsg, HeartbeatReply, HeartbeatRequest, PreVoteReply, PreVoteRequest,
     },
     util::NodeId,
-    OmniPaxosConfig,
+    ClusterConfig, OmniPaxosConfig,
 };
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -73,8 +73,10 @@ pub(crate) struct BallotLeaderElection {
     configuration_id: ConfigurationId,
     /// Process identifier used to uniquely identify this instance.
     pid: NodeId,
-    /// Vector that holds the pids of all the other servers.
+    /// Vector that holds the pids of all the other voting servers.
     peers: Vec<NodeId>,
+    /// Whether this server is a non-voting learner. Learners never take part in the leader election.
+    is_learner: bool,
     /// The current round of the heartbeat cycle.
     hb_round: u32,
     /// The heartbeat replies this instance received during the current round.
@@ -93,6 +95,25 @@ pub(crate) struct BallotLeaderElection {
     quorum: Quorum,
     /// Vector which holds all the outgoing messages of the BLE instance.
     outgoing: Vec<BLEMessage>,
+    /// The number of ticks that a leader lease lasts. `None` if leader leases are disabled.
+    lease_duration: Option<u64>,
+    /// The logical time of this instance, i.e., the number of ticks since it was created.
+    time: u64,
+    /// The logical time at which the current heartbeat round was started.
+    hb_round_start: u64,
+    /// The lease held by this instance as leader, i.e., its ballot and the time the lease expires.
+    lease: Option<(Ballot, u64)>,
+    /// The lease granted by this instance to its leader, i.e., the leader and the time the lease expires.
+    granted_lease: Option<(Ballot, u64)>,
+    /// Whether a quorum must grant a pre-vote before this instance increases its ballot to take over the leadership.
+    pre_vote: bool,
+    /// The ongoing pre-vote of this instance, i.e., the heartbeat round it was started in, the ballot it would take over
+    /// with, and the servers that granted it.
+    pre_vote_state: Option<(u32, Ballot, Vec<NodeId>)>,
+    /// Whether this instance steps down as leader when it does not receive heartbeat replies from a quorum.
+    check_quorum: bool,
+    /// Whether this instance has stepped down as leader without another leader being elected yet.
+    stepped_down: bool,
     /// Logger used to output the status of the component.
     #[cfg(feature = "logging")]
     logger: Logger,
@@ -103,7 +124,9 @@ impl BallotLeaderElection {
     pub(crate) fn with(config: BLEConfig, recovered_leader: Option<Ballot>) -> Self {
         let config_id = config.configuration_id;
         let pid = config.pid;
-        let peers = config.peers;
+        let is_learner = config.is_learner;
+        // Learners do not exchange heartbeats
+        let peers = if is_learner { vec![] } else { config.peers };
         let num_nodes = &peers.len() + 1;
         let quorum = Quorum::with(config.flexible_quorum, num_nodes);
         let mut initial_ballot = Ballot::with(config_id, INITIAL_ROUND, config.priority, pid);
@@ -113,12 +136,22 @@ impl BallotLeaderElection {
                 initial_ballot.n = RECOVERY_ROUND;
                 b
             }
+            _ if is_learner => Ballot::default(),
             _ => initial_ballot,
         };
+        // We might have granted a lease to the recovered leader before crashing, so we must
+        // assume that it is still valid.
+        let granted_lease = match config.lease_duration {
+            Some(duration) if initial_leader.pid != pid && !is_learner => {
+                Some((initial_leader, duration))
+            }
+            _ => None,
+        };
         let mut ble = BallotLeaderElection {
             configuration_id: config_id,
             pid,
             peers,
+            is_learner,
             hb_round: 0,
             heartbeat_replies: Vec::with_capacity(num_nodes),
             prev_replies: Vec::with_capacity(num_nodes),
@@ -127,6 +160,15 @@ impl BallotLeaderElection {
             happy: true,
             quorum,
             outgoing: Vec::with_capacity(config.buffer_size),
+            lease_duration: config.lease_duration,
+            time: 0,
+            hb_round_start: 0,
+            lease: None,
+            granted_lease,
+            pre_vote: config.pre_vote,
+            pre_vote_state: None,
+            check_quorum: config.check_quorum,
+            stepped_down: false,
             #[cfg(feature = "logging")]
             logger: {
                 if let Some(logger) = config.custom_logger {
@@ -168,6 +210,39 @@ impl BallotLeaderElection {
         match m.msg {
             HeartbeatMsg::Request(req) => self.handle_request(m.from, req),
             HeartbeatMsg::Reply(rep) => self.handle_reply(rep),
+            HeartbeatMsg::PreVoteRequest(req) => self.handle_pre_vote_request(m.from, req),
+            HeartbeatMsg::PreVoteReply(rep) => self.handle_pre_vote_reply(m.from, rep),
+        }
+    }
+
+    /// Increments the logical time used for leader leases.
+    pub(crate) fn tick(&mut self) {
+        self.time += 1;
+    }
+
+    /// Returns whether this server holds a valid leader lease for the ballot `n`.
+    pub(crate) fn has_lease(&self, n: Ballot) -> bool {
+        match self.lease {
+            Some((lease_ballot, expiry)) => {
+                lease_ballot == n
+                    && self.current_ballot == n
+                    && self.leader == n
+                    && self.time < expiry
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether this server has stepped down as leader because it lost contact with a quorum.
+    pub(crate) fn has_stepped_down(&self) -> bool {
+        self.stepped_down
+    }
+
+    /// Returns the leader that this server has granted a lease to, if the lease has not expired.
+    pub(crate) fn get_granted_lease(&self) -> Option<Ballot> {
+        match self.granted_lease {
+            Some((leader, expiry)) if self.time < expiry => Some(leader),
+            _ => None,
         }
     }
 
@@ -175,6 +250,7 @@ impl BallotLeaderElection {
     pub(crate) fn new_hb_round(&mut self) {
         self.prev_replies = std::mem::take(&mut self.heartbeat_replies);
         self.hb_round += 1;
+        self.hb_round_start = self.time;
         #[cfg(feature = "logging")]
         trace!(
             self.logger,
@@ -199,8 +275,28 @@ impl BallotLeaderElection {
         seq_paxos_state: &(Role, Phase),
         seq_paxos_promise: Ballot,
     ) -> Option<Ballot> {
+        if self.is_learner {
+            // Learners follow whichever leader contacts them in Sequence Paxos
+            return None;
+        }
+        if let Some(leased_leader) = self.get_granted_lease() {
+            // The lease is only kept as long as the leader has not given up on its leadership.
+            let leader_abdicated = self.heartbeat_replies.iter().any(|r| {
+                r.ballot.pid == leased_leader.pid
+                    && (r.ballot != leased_leader || r.leader != leased_leader)
+            });
+            if !leader_abdicated {
+                // We have promised to not elect a new leader until the lease expires.
+                self.leader = leased_leader;
+                self.happy = true;
+                self.new_hb_round();
+                return None;
+            }
+            self.granted_lease = None;
+        }
         self.update_leader();
         self.update_happiness(seq_paxos_state);
+        self.check_quorum();
         self.check_takeover();
         self.new_hb_round();
         if seq_paxos_promise > self.leader {
@@ -208,7 +304,7 @@ impl BallotLeaderElection {
             self.leader = seq_paxos_promise;
             self.happy = true;
         }
-        if self.leader == self.current_ballot {
+        if self.leader == self.current_ballot && !self.stepped_down {
             Some(self.current_ballot)
         } else {
             None
@@ -220,12 +316,15 @@ impl BallotLeaderElection {
         if let Some(max) = max_reply_ballot {
             if max > self.leader {
                 self.leader = max;
+                self.stepped_down = false;
             }
         }
     }
 
     fn update_happiness(&mut self, seq_paxos_state: &(Role, Phase)) {
-        self.happy = if self.leader == self.current_ballot {
+        self.happy = if self.stepped_down {
+            false
+        } else if self.leader == self.current_ballot {
             let potential_followers = self
                 .heartbeat_replies
                 .iter()
@@ -253,6 +352,22 @@ impl BallotLeaderElection {
         };
     }
 
+    /// Steps down if this server is the leader but did not receive heartbeat replies from a quorum in this round.
+    fn check_quorum(&mut self) {
+        if self.check_quorum && self.leader == self.current_ballot && !self.stepped_down {
+            let im_quorum_connected = self
+                .quorum
+                .is_accept_quorum(self.heartbeat_replies.len() + 1);
+            if !im_quorum_connected {
+                #[cfg(feature = "logging")]
+                info!(self.logger, "Lost contact with a quorum, stepping down");
+                self.stepped_down = true;
+                self.happy = false;
+                self.lease = None;
+            }
+        }
+    }
+
     fn check_takeover(&mut self) {
         if !self.happy {
             let all_neighbors_unhappy = self.heartbeat_replies.iter().all(|r| !r.happy);
@@ -262,14 +377,81 @@ impl BallotLeaderElection {
             if all_neighbors_unhappy && im_quorum_connected {
                 // We increment past our leader instead of max of unhappy ballots because we
                 // assume we have already checked leader for this round so they should be equal
-                self.current_ballot.n = self.leader.n + 1;
+                let mut ballot = self.current_ballot;
+                ballot.n = self.leader.n + 1;
+                if self.pre_vote && !self.won_pre_vote(ballot) {
+                    self.start_pre_vote(ballot);
+                    return;
+                }
+                self.current_ballot = ballot;
                 self.leader = self.current_ballot;
                 self.happy = true;
+                self.stepped_down = false;
+            }
+        }
+        self.pre_vote_state = None;
+    }
+
+    /// Returns whether a quorum granted the pre-vote for taking over with `ballot` in the previous heartbeat round.
+    fn won_pre_vote(&self, ballot: Ballot) -> bool {
+        match &self.pre_vote_state {
+            Some((round, pre_vote_ballot, granted)) => {
+                *round == self.hb_round
+                    && *pre_vote_ballot == ballot
+                    && self.quorum.is_prepare_quorum(granted.len() + 1)
+            }
+            None => false,
+        }
+    }
+
+    /// Asks the other servers whether they would follow this server if it took over with `ballot`.
+    fn start_pre_vote(&mut self, ballot: Ballot) {
+        // The heartbeat round is incremented right after, so replies arrive during the next round
+        self.pre_vote_state = Some((self.hb_round + 1, ballot, vec![]));
+        for peer in &self.peers {
+            self.outgoing.push(BLEMessage {
+                from: self.pid,
+                to: *peer,
+                msg: HeartbeatMsg::PreVoteRequest(PreVoteRequest {
+                    round: self.hb_round + 1,
+                    ballot,
+                }),
+            });
+        }
+    }
+
+    fn handle_pre_vote_request(&mut self, from: NodeId, req: PreVoteRequest) {
+        // Only grant the pre-vote if we also see the need for a new leader
+        let granted = !self.happy
+            && req.ballot > self.leader
+            && req.ballot.config_id == self.configuration_id
+            && self.get_granted_lease().is_none();
+        self.outgoing.push(BLEMessage {
+            from: self.pid,
+            to: from,
+            msg: HeartbeatMsg::PreVoteReply(PreVoteReply {
+                round: req.round,
+                granted,
+            }),
+        });
+    }
+
+    fn handle_pre_vote_reply(&mut self, from: NodeId, rep: PreVoteReply) {
+        if let Some((round, _, granted)) = &mut self.pre_vote_state {
+            if rep.granted && rep.round == *round && !granted.contains(&from) {
+                granted.push(from);
             }
         }
     }
 
     fn handle_request(&mut self, from: NodeId, req: HeartbeatRequest) {
+        if let Some(duration) = self.lease_duration {
+            if from == self.leader.pid && from != self.pid {
+                // By replying to our leader we grant it a lease. The lease is measured from when
+                // the request was received, which is after the leader started measuring it.
+                self.granted_lease = Some((self.leader, self.time + duration));
+            }
+        }
         let hb_reply = HeartbeatReply {
             round: req.round,
             ballot: self.current_ballot,
@@ -286,6 +468,9 @@ impl BallotLeaderElection {
     fn handle_reply(&mut self, rep: HeartbeatReply) {
         if rep.round == self.hb_round && rep.ballot.config_id == self.configuration_id {
             self.heartbeat_replies.push(rep);
+            if let Some(duration) = self.lease_duration {
+                self.check_lease(duration);
+            }
         } /*else {
               #[cfg(feature = "logging")]
               warn!(
@@ -295,6 +480,50 @@ impl BallotLeaderElection {
           }*/
     }
 
+    /// Renews the lease of this server if a quorum follows it in the current heartbeat round.
+    fn check_lease(&mut self, duration: u64) {
+        if self.leader == self.current_ballot {
+            let num_followers = self
+                .heartbeat_replies
+                .iter()
+                .filter(|r| r.leader == self.current_ballot)
+                .count();
+            if self.quorum.is_accept_quorum(num_followers + 1) {
+                self.lease = Some((self.current_ballot, self.hb_round_start + duration));
+            }
+        }
+    }
+
+    /// Takes over the leadership from `leader` with a higher ballot. Returns the new ballot of this server.
+    pub(crate) fn take_over(&mut self, leader: Ballot) -> Ballot {
+        self.current_ballot.n = self.leader.n.max(leader.n) + 1;
+        self.leader = self.current_ballot;
+        self.happy = true;
+        self.stepped_down = false;
+        self.granted_lease = None;
+        self.current_ballot
+    }
+
+    /// Changes the voting servers after an in-place membership change. A server that is no longer
+    /// a voting server stops taking part in the leader election.
+    pub(crate) fn set_cluster_config(&mut self, config: &ClusterConfig) {
+        self.is_learner = !config.nodes.contains(&self.pid);
+        self.peers = if self.is_learner {
+            vec![]
+        } else {
+            config
+                .nodes
+                .iter()
+                .filter(|x| **x != self.pid)
+                .copied()
+                .collect()
+        };
+        self.quorum = Quorum::with(config.flexible_quorum, config.nodes.len());
+        if self.is_learner {
+            self.granted_lease = None;
+        }
+    }
+
     pub(crate) fn get_current_ballot(&self) -> Ballot {
         self.current_ballot
     }
@@ -308,19 +537,27 @@ impl BallotLeaderElection {
 /// # Fields
 /// * `configuration_id`: The identifier for the configuration that this node is part of.
 /// * `pid`: The unique identifier of this node. Must not be 0.
-/// * `peers`: The peers of this node i.e. the `pid`s of the other servers in the configuration.
+/// * `peers`: The peers of this node i.e. the `pid`s of the other voting servers in the configuration.
+/// * `is_learner`: Whether this node is not one of the voting servers in the configuration.
 /// * `priority`: Set custom priority for this node to be elected as the leader.
 /// * `flexible_quorum` : Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
 /// * `buffer_size`: The buffer size for outgoing messages.
+/// * `lease_duration`: The number of ticks a leader lease lasts. `None` disables leader leases.
+/// * `pre_vote`: Whether a candidate must win a pre-vote before increasing its ballot.
+/// * `check_quorum`: Whether a leader steps down when it loses contact with a quorum.
 /// * `logger_file_path`: The path where the default logger logs events.
 #[derive(Clone, Debug)]
 pub(crate) struct BLEConfig {
     configuration_id: ConfigurationId,
     pid: NodeId,
     peers: Vec<NodeId>,
+    is_learner: bool,
     priority: u32,
     flexible_quorum: Option<FlexibleQuorum>,
     buffer_size: usize,
+    lease_duration: Option<u64>,
+    pre_vote: bool,
+    check_quorum: bool,
     #[cfg(feature = "logging")]
     logger_file_path: Option<String>,
     #[cfg(feature = "logging")]
@@ -330,6 +567,7 @@ pub(crate) struct BLEConfig {
 impl From<OmniPaxosConfig> for BLEConfig {
     fn from(config: OmniPaxosConfig) -> Self {
         let pid = config.server_config.pid;
+        let is_learner = !config.cluster_config.nodes.contains(&pid);
         let peers = config
             .cluster_config
             .nodes
@@ -341,9 +579,13 @@ impl From<OmniPaxosConfig> for BLEConfig {
             configuration_id: config.cluster_config.configuration_id,
             pid,
             peers,
+            is_learner,
             priority: config.server_config.leader_priority,
             flexible_quorum: config.cluster_config.flexible_quorum,
             buffer_size: BLE_BUFFER_SIZE,
+            lease_duration: config.server_config.leader_lease_tick_duration,
+            pre_vote: config.server_config.pre_vote,
+            check_quorum: config.server_config.check_quorum,
             #[cfg(feature = "logging")]
             logger_file_path: config.server_config.logger_file_path,
             #[cfg(feature = "logging")]
@@ -1,3 +1,4 @@
+use crate::storage::StorageResult;
 #[cfg(feature = "toml_config")]
 use std::io;
 use std::{error, fmt};
@@ -55,6 +56,42 @@ impl From<toml::de::Error> for ConfigError {
     }
 }
 
+/// Error type for a failed operation on the storage of an OmniPaxos server.
+#[derive(Debug)]
+pub struct StorageError {
+    /// The operation that failed, e.g., "write log entries".
+    pub operation: &'static str,
+    /// The message of the error returned by the storage. Only the message is kept so that the error, and the
+    /// `OmniPaxos` server that stores it, can be sent between threads.
+    pub error: String,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "storage error while trying to {}: {}",
+            self.operation, self.error
+        )
+    }
+}
+
+impl error::Error for StorageError {}
+
+/// Adds the failed operation to the error of a storage result.
+pub(crate) trait StorageContext<R> {
+    fn context(self, operation: &'static str) -> Result<R, StorageError>;
+}
+
+impl<R> StorageContext<R> for StorageResult<R> {
+    fn context(self, operation: &'static str) -> Result<R, StorageError> {
+        self.map_err(|error| StorageError {
+            operation,
+            error: error.to_string(),
+        })
+    }
+}
+
 #[allow(missing_docs)]
 macro_rules! valid_config {
     ($pred:expr,$err_str:expr) => {
@@ -10,8 +10,9 @@ use serde::{Deserialize, Serialize};
 pub mod sequence_paxos {
     use crate::{
         ballot_leader_election::Ballot,
-        storage::{Entry, SnapshotType, StopSign},
-        util::{NodeId, SequenceNumber},
+        storage::{Entry, Membership, SnapshotMetadata, SnapshotType, StopSign},
+        util::{NodeId, ProposalId, ProposalOutcome, ReadId, SequenceNumber},
+        ClusterConfig,
     };
     #[cfg(feature = "serde")]
     use serde::{Deserialize, Serialize};
@@ -60,6 +61,8 @@ pub mod sequence_paxos {
         pub accepted_idx: u64,
         /// The StopSign accepted by this follower
         pub stopsign: Option<StopSign>,
+        /// The membership accepted by this follower
+        pub membership: Option<Box<Membership>>,
     }
 
     /// AcceptSync message sent by the leader to synchronize the logs of all replicas in the prepare phase.
@@ -83,11 +86,74 @@ pub mod sequence_paxos {
         pub decided_idx: u64,
         /// StopSign to be accepted
         pub stopsign: Option<StopSign>,
+        /// Membership to be accepted
+        pub membership: Option<Box<Membership>>,
         #[cfg(feature = "unicache")]
         /// The UniCache of the leader
         pub unicache: T::UniCache,
     }
 
+    /// A chunk of the log sent by the leader to synchronize a follower that is far behind. The [`AcceptSync`] has the
+    /// first chunk, and every further chunk is sent once the follower has accepted the previous one.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct AcceptSyncChunk<T>
+    where
+        T: Entry,
+    {
+        /// The current round.
+        pub n: Ballot,
+        /// The sequence number of this message in the leader-to-follower accept sequence
+        pub seq_num: SequenceNumber,
+        /// The decided snapshot, if the leader has compacted the entries of this chunk.
+        pub decided_snapshot: Option<SnapshotType<T>>,
+        /// The entries of this chunk.
+        pub entries: Vec<T>,
+        /// The index of the log where `entries` should be applied at (also the compacted idx of `decided_snapshot` if it exists)
+        pub sync_idx: u64,
+        /// The decided index
+        pub decided_idx: u64,
+        /// StopSign to be accepted, only included in the last chunk
+        pub stopsign: Option<StopSign>,
+    }
+
+    /// Message sent by the leader to a follower whose missing entries are compacted, so that it pulls the snapshot of the
+    /// application state with [`SnapshotChunkRequest`]s instead of being sent a snapshot in an [`AcceptSync`].
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SnapshotOffer {
+        /// The current round.
+        pub n: Ballot,
+        /// The snapshot to be transferred.
+        pub metadata: SnapshotMetadata,
+    }
+
+    /// Message sent by a follower to the leader to request the bytes of an offered snapshot from `offset`.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SnapshotChunkRequest {
+        /// The current round.
+        pub n: Ballot,
+        /// The id of the requested snapshot.
+        pub id: u64,
+        /// The offset in bytes of the requested chunk.
+        pub offset: u64,
+    }
+
+    /// A chunk of a snapshot sent by the leader in response to a [`SnapshotChunkRequest`].
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SnapshotChunk {
+        /// The current round.
+        pub n: Ballot,
+        /// The id of the snapshot.
+        pub id: u64,
+        /// The offset in bytes of `data` in the snapshot.
+        pub offset: u64,
+        /// The bytes of this chunk.
+        pub data: Vec<u8>,
+    }
+
     /// Message with entries to be replicated and the latest decided index sent by the leader in the accept phase.
     #[derive(Clone, Debug)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -157,6 +223,28 @@ pub mod sequence_paxos {
         pub ss: StopSign,
     }
 
+    /// Message sent by leader to followers to accept an in-place membership change
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct AcceptMembership {
+        /// The current round.
+        pub n: Ballot,
+        /// The sequence number of this message in the leader-to-follower accept sequence
+        pub seq_num: SequenceNumber,
+        /// The membership to be accepted.
+        pub membership: Membership,
+    }
+
+    /// Message sent by follower to leader when a membership change has been accepted.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct AcceptedMembership {
+        /// The current round.
+        pub n: Ballot,
+        /// The version of the accepted membership.
+        pub version: u64,
+    }
+
     /// Message sent by follower to leader when accepting an entry is rejected.
     /// This happens when the follower is promised to a greater leader.
     #[derive(Clone, Debug)]
@@ -166,6 +254,59 @@ pub mod sequence_paxos {
         pub n: Ballot,
     }
 
+    /// Message sent by the leader to check that it is still the leader before answering a read request.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ReadIndexCheck {
+        /// The current round.
+        pub n: Ballot,
+        /// The read round this check belongs to.
+        pub round: u64,
+    }
+
+    /// Message sent by follower to leader to confirm that it is still promised to the leader's round.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ReadIndexAck {
+        /// The current round.
+        pub n: Ballot,
+        /// The read round that is acknowledged.
+        pub round: u64,
+    }
+
+    /// Message sent by leader to the server that forwarded a read request once the read has been confirmed.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ReadIndexReply {
+        /// The id of the read request.
+        pub id: ReadId,
+        /// The index that must be decided before the read can be served.
+        pub read_idx: u64,
+    }
+
+    /// An entry proposed on a server that is forwarded to the leader.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Proposal<T>
+    where
+        T: Entry,
+    {
+        /// The server that the entry was proposed on.
+        pub origin: NodeId,
+        /// The id of the proposal at `origin`.
+        pub id: ProposalId,
+        /// The proposed entry.
+        pub entry: T,
+    }
+
+    /// Message sent by leader to a caught-up follower to make it take over the leadership immediately.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct TransferLeadership {
+        /// The current round.
+        pub n: Ballot,
+    }
+
     /// Compaction Request
     #[allow(missing_docs)]
     #[derive(Clone, Debug)]
@@ -189,15 +330,31 @@ pub mod sequence_paxos {
         Prepare(Prepare),
         Promise(Promise<T>),
         AcceptSync(AcceptSync<T>),
+        AcceptSyncChunk(AcceptSyncChunk<T>),
+        SnapshotOffer(SnapshotOffer),
+        SnapshotChunkRequest(SnapshotChunkRequest),
+        SnapshotChunk(SnapshotChunk),
         AcceptDecide(AcceptDecide<T>),
         Accepted(Accepted),
         NotAccepted(NotAccepted),
         Decide(Decide),
         /// Forward client proposals to the leader.
-        ProposalForward(Vec<T>),
+        ProposalForward(Vec<Proposal<T>>),
+        /// Notify the server that made the proposals of their outcomes.
+        ProposalOutcomes(Vec<ProposalOutcome>),
         Compaction(Compaction),
         AcceptStopSign(AcceptStopSign),
         ForwardStopSign(StopSign),
+        /// Forward a membership change to the leader.
+        ForwardMembership(ClusterConfig),
+        AcceptMembership(AcceptMembership),
+        AcceptedMembership(AcceptedMembership),
+        /// Forward a read request to the leader.
+        ReadIndexForward(ReadId),
+        ReadIndexCheck(ReadIndexCheck),
+        ReadIndexAck(ReadIndexAck),
+        ReadIndexReply(ReadIndexReply),
+        TransferLeadership(TransferLeadership),
         #[cfg(feature = "unicache")]
         EncodedAcceptDecide(EncodedAcceptDecide<T>),
     }
@@ -232,6 +389,8 @@ pub mod ballot_leader_election {
     pub enum HeartbeatMsg {
         Request(HeartbeatRequest),
         Reply(HeartbeatReply),
+        PreVoteRequest(PreVoteRequest),
+        PreVoteReply(PreVoteReply),
     }
 
     /// Requests a reply from all the other servers.
@@ -256,6 +415,26 @@ pub mod ballot_leader_election {
         pub happy: bool,
     }
 
+    /// Asks the other servers whether they would follow a new leader, without increasing the ballot of the candidate.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct PreVoteRequest {
+        /// Number of the heartbeat round in which the pre-vote was started.
+        pub round: u32,
+        /// The ballot that the candidate would take over the leadership with.
+        pub ballot: Ballot,
+    }
+
+    /// Reply to a `PreVoteRequest`.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct PreVoteReply {
+        /// Number of the heartbeat round in which the pre-vote was started.
+        pub round: u32,
+        /// Whether the replying server would follow the candidate.
+        pub granted: bool,
+    }
+
     /// A struct for a Paxos message that also includes sender and receiver.
     #[derive(Clone, Debug)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -269,6 +448,49 @@ pub mod ballot_leader_election {
     }
 }
 
+/// Messages for moving the decided log of a stopped configuration to the next configuration
+pub mod migration {
+    use crate::{
+        storage::{Entry, StopSign},
+        util::NodeId,
+    };
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+    use std::fmt::Debug;
+
+    /// Request for the decided log of a stopped configuration. Sent by a server of the next configuration
+    /// that was not part of the stopped configuration to a server that was.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct LogPullRequest {
+        /// Sender of the request.
+        pub from: NodeId,
+        /// Receiver of the request.
+        pub to: NodeId,
+    }
+
+    /// The decided log of a stopped configuration. Used to start the `OmniPaxos` server of the next configuration.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct LogPullResponse<T>
+    where
+        T: Entry,
+    {
+        /// Sender of the response.
+        pub from: NodeId,
+        /// Receiver of the response.
+        pub to: NodeId,
+        /// The decided StopSign of the stopped configuration.
+        pub stopsign: StopSign,
+        /// The index up to which the log has been compacted.
+        pub compacted_idx: u64,
+        /// The snapshot of the compacted entries, if the log was snapshotted.
+        pub snapshot: Option<T::Snapshot>,
+        /// The decided entries after `compacted_idx`.
+        pub entries: Vec<T>,
+    }
+}
+
 #[allow(missing_docs)]
 /// Message in OmniPaxos. Can be either a `SequencePaxos` message (for log replication) or `BLE` message (for leader election)
 #[derive(Clone, Debug)]
@@ -1,12 +1,17 @@
 use crate::{
-    ballot_leader_election::{Ballot, BallotLeaderElection},
-    errors::{valid_config, ConfigError},
-    messages::Message,
-    sequence_paxos::SequencePaxos,
-    storage::{Entry, StopSign, Storage},
+    ballot_leader_election::{BLEConfig, Ballot, BallotLeaderElection},
+    errors::{valid_config, ConfigError, StorageError},
+    messages::{
+        migration::{LogPullRequest, LogPullResponse},
+        sequence_paxos::PaxosMsg,
+        Message,
+    },
+    sequence_paxos::{Phase, Role, SequencePaxos},
+    storage::{Entry, Snapshot, SnapshotInstaller, SnapshotProvider, StopSign, Storage},
     util::{
         defaults::{BUFFER_SIZE, ELECTION_TIMEOUT, RESEND_MESSAGE_TIMEOUT},
-        ConfigurationId, FlexibleQuorum, LogEntry, LogicalClock, NodeId,
+        ClientSessions, ConfigurationId, FlexibleQuorum, LogEntry, LogicalClock, NodeId,
+        ProposalId, ProposalOutcome, ReadId, ReadIndex, SyncMetrics,
     },
     utils::{ui, ui::ClusterState},
 };
@@ -42,8 +47,12 @@ impl OmniPaxosConfig {
         self.cluster_config.validate()?;
         self.server_config.validate()?;
         valid_config!(
-            self.cluster_config.nodes.contains(&self.server_config.pid),
-            "Nodes must include own server pid"
+            self.cluster_config.nodes.contains(&self.server_config.pid)
+                || self
+                    .cluster_config
+                    .learners
+                    .contains(&self.server_config.pid),
+            "Nodes or learners must include own server pid"
         );
         Ok(())
     }
@@ -58,23 +67,34 @@ impl OmniPaxosConfig {
     }
 
     /// Checks all configuration fields and returns the local OmniPaxos node if successful.
-    pub fn build<T, B>(self, storage: B) -> Result<OmniPaxos<T, B>, ConfigError>
+    pub fn build<T, B>(mut self, storage: B) -> Result<OmniPaxos<T, B>, ConfigError>
     where
         T: Entry,
         B: Storage<T>,
     {
         self.validate()?;
+        // Use the stored membership if the configuration has been changed in place
+        if let Some(membership) = storage
+            .get_membership()
+            .expect("storage error while trying to read membership")
+        {
+            self.cluster_config = membership.config;
+        }
         // Use stored ballot as initial BLE leader
         let recovered_leader = storage
             .get_promise()
             .expect("storage error while trying to read promise");
+        let ble_config: BLEConfig = self.clone().into();
         Ok(OmniPaxos {
-            ble: BallotLeaderElection::with(self.clone().into(), recovered_leader),
+            ble: BallotLeaderElection::with(ble_config.clone(), recovered_leader),
+            ble_config,
             election_clock: LogicalClock::with(self.server_config.election_tick_timeout),
             resend_message_clock: LogicalClock::with(
                 self.server_config.resend_message_tick_timeout,
             ),
             seq_paxos: SequencePaxos::with(self.into(), storage),
+            applied_idx: 0,
+            applied_sessions: ClientSessions::default(),
         })
     }
 }
@@ -84,6 +104,7 @@ impl OmniPaxosConfig {
 /// * `configuration_id`: The identifier for the cluster configuration that this OmniPaxos server is part of.
 /// * `nodes`: The nodes in the cluster i.e. the `pid`s of the other servers in the configuration.
 /// * `flexible_quorum` : Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
+/// * `learners`: The non-voting nodes in the cluster i.e. the `pid`s of the servers that replicate the log but are not part of any quorum.
 #[derive(Clone, Debug, PartialEq, Default)]
 #[cfg_attr(any(feature = "serde", feature = "toml_config"), derive(Deserialize))]
 #[cfg_attr(feature = "toml_config", serde(default))]
@@ -96,6 +117,10 @@ pub struct ClusterConfig {
     pub nodes: Vec<NodeId>,
     /// Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
     pub flexible_quorum: Option<FlexibleQuorum>,
+    /// The non-voting nodes in the cluster i.e. the `pid`s of the servers that receive the log from the leader but
+    /// are never counted in a quorum nor elected as leader. A learner can be promoted to a voter with a reconfiguration.
+    #[cfg_attr(any(feature = "serde", feature = "toml_config"), serde(default))]
+    pub learners: Vec<NodeId>,
 }
 
 impl ClusterConfig {
@@ -104,6 +129,10 @@ impl ClusterConfig {
         let num_nodes = self.nodes.len();
         valid_config!(num_nodes > 1, "Need more than 1 node");
         valid_config!(self.configuration_id != 0, "Configuration ID cannot be 0");
+        valid_config!(
+            self.learners.iter().all(|l| !self.nodes.contains(l)),
+            "Learners cannot also be voting nodes"
+        );
         if let Some(FlexibleQuorum {
             read_quorum_size,
             write_quorum_size,
@@ -129,6 +158,47 @@ impl ClusterConfig {
         Ok(())
     }
 
+    /// Checks that `new_config` can replace this configuration in place, i.e. that it keeps the
+    /// configuration id and adds or removes at most one voting node.
+    pub(crate) fn validate_membership_change(
+        &self,
+        new_config: &ClusterConfig,
+    ) -> Result<(), ConfigError> {
+        valid_config!(
+            new_config.configuration_id == self.configuration_id,
+            "Membership changes must keep the configuration ID"
+        );
+        valid_config!(
+            self.flexible_quorum.is_none() && new_config.flexible_quorum.is_none(),
+            "Membership changes are not supported with flexible quorums"
+        );
+        let added = new_config
+            .nodes
+            .iter()
+            .filter(|pid| !self.nodes.contains(pid))
+            .count();
+        let removed = self
+            .nodes
+            .iter()
+            .filter(|pid| !new_config.nodes.contains(pid))
+            .count();
+        valid_config!(
+            added + removed <= 1,
+            "Membership changes can only add or remove one voting node at a time"
+        );
+        Ok(())
+    }
+
+    /// Returns the `pid`s of the nodes and learners other than `pid`.
+    pub(crate) fn peers_of(&self, pid: NodeId) -> Vec<NodeId> {
+        self.nodes
+            .iter()
+            .chain(self.learners.iter())
+            .filter(|x| **x != pid)
+            .copied()
+            .collect()
+    }
+
     /// Checks all configuration fields and builds a local OmniPaxos node with settings for this
     /// node defined in `server_config` and using storage `with_storage`.
     pub fn build_for_server<T, B>(
@@ -158,6 +228,17 @@ impl ClusterConfig {
 /// * `batch_size`: The size of the buffer for log batching. The default is 1, which means no batching.
 /// * `logger_file_path`: The path where the default logger logs events.
 /// * `leader_priority` : Custom priority for this node to be elected as the leader.
+/// * `leader_lease_tick_duration`: The number of calls to `tick()` that a leader lease lasts. `None` disables leader leases. Must not be 0.
+/// * `pre_vote`: Whether a server must win a pre-vote before increasing its ballot to become the leader.
+/// * `check_quorum`: Whether the leader steps down when it loses contact with a quorum.
+/// * `max_pending_proposals`: The maximum number of proposals of this server whose outcome is not known yet, and of proposals of each other server that this server buffers. `None` means no limit. Must not be 0.
+/// * `halt_on_storage_error`: Whether this server halts instead of panicking when a write to the storage fails.
+/// * `async_flush`: Whether followers write accepted entries asynchronously and only accept them once the storage has flushed them.
+/// * `max_sync_entries`: The maximum number of entries that the leader sends in one message to synchronize a follower. The `Promise` to a new leader is not limited. `None` means no limit. Must not be 0.
+/// * `max_sync_bytes`: The maximum size in bytes, according to `Entry::size_hint()`, of the entries that the leader sends in one message to synchronize a follower. The `Promise` to a new leader is not limited. `None` means no limit. Must not be 0.
+/// * `compaction_interval`: The number of decided entries that are not compacted after which the log is compacted automatically in `tick()`. `None` disables it. Must not be 0.
+/// * `compaction_log_bytes`: The size in bytes, according to `Entry::size_hint()`, of the decided entries that are not compacted after which the log is compacted automatically in `tick()`. `None` disables it. Must not be 0.
+/// * `compaction_safety_margin`: The number of the latest decided entries that are kept when the log is compacted automatically.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "toml_config", derive(Deserialize), serde(default))]
 pub struct ServerConfig {
@@ -173,6 +254,57 @@ pub struct ServerConfig {
     pub batch_size: usize,
     /// Custom priority for this node to be elected as the leader.
     pub leader_priority: u32,
+    /// The number of calls to `tick()` that a leader lease lasts. A leader that has received heartbeat replies from a quorum
+    /// within the lease confirms reads locally without a round trip to its followers. This assumes that `tick()` is called at
+    /// the same rate on all servers. Followers will not elect a new leader until the lease expires, so this should be larger
+    /// than `election_tick_timeout` for the lease to be renewed before it expires. Defaults to `None`, which disables leader leases.
+    pub leader_lease_tick_duration: Option<u64>,
+    /// If enabled, a server that wants to become the leader first asks the other servers whether they would follow it, and only
+    /// increases its ballot if a quorum agrees. This prevents a server that rejoins after a partition from disrupting a working
+    /// leader. Defaults to `false`.
+    pub pre_vote: bool,
+    /// If enabled, a leader that does not receive heartbeat replies from a quorum within an election timeout steps down and stops
+    /// accepting proposals until it is elected again. Defaults to `false`.
+    pub check_quorum: bool,
+    /// The maximum number of proposals of this server whose outcome is not known yet, i.e., that are buffered while there is
+    /// no leader or have been sent to the leader but are not decided yet. Further calls to `append()` fail with
+    /// `ProposeErr::NoLeader` or `ProposeErr::Overloaded` until outcomes of earlier proposals are known. This server also
+    /// buffers at most this many proposals forwarded by each other server, e.g., while it is being elected, and reports
+    /// the rest to them as `ProposalStatus::Dropped`. Defaults to `None`, which means no limit.
+    pub max_pending_proposals: Option<usize>,
+    /// If enabled, a failed write to the storage halts this server instead of panicking. A halted server does not handle
+    /// messages, vote or propose until [`OmniPaxos::resume()`] is called, e.g., after the disk has been fixed. Writes that
+    /// consist of multiple storage operations are rolled back before halting, so the storage stays consistent. Reading the
+    /// storage and creating the server still panic on errors. Defaults to `false`.
+    pub halt_on_storage_error: bool,
+    /// If enabled, followers write the entries from the leader with [`Storage::write_atomically_async()`] and only reply
+    /// `Accepted` once [`Storage::poll_flushed()`] reports that the entries are durable. The storage is polled in
+    /// [`OmniPaxos::outgoing_messages()`], so a backend can flush in the background while the follower keeps receiving
+    /// entries. Defaults to `false`.
+    pub async_flush: bool,
+    /// The maximum number of log entries in a message that synchronizes a follower. If the follower is further behind, the
+    /// leader sends the entries in chunks and only sends the next chunk once the follower has accepted the previous one.
+    /// New entries are sent to the follower once it has caught up. Defaults to `None`, which sends all missing entries in
+    /// one message. This does not limit the `Promise` that a follower sends to a newly elected leader, which holds all
+    /// the entries that the follower has accepted in a later round or beyond the log of the leader.
+    pub max_sync_entries: Option<usize>,
+    /// The maximum size in bytes of the log entries in a message that synchronizes a follower, as estimated by
+    /// [`Entry::size_hint()`]. A chunk has at least one entry. Like `max_sync_entries`, this makes the leader synchronize
+    /// the follower in chunks. Like `max_sync_entries`, it does not limit the `Promise` to a newly elected leader.
+    /// Defaults to `None`, which means no limit.
+    pub max_sync_bytes: Option<usize>,
+    /// The number of decided entries that are not compacted after which the log is compacted automatically in `tick()`.
+    /// The leader compacts the entries that all servers have accepted and tells the followers to compact them too, i.e., a
+    /// follower that is down stops the log from being compacted. The entries are snapshotted if [`Snapshot`]s are used
+    /// and trimmed otherwise. Defaults to `None`, which means that the log is only compacted with `trim()` and `snapshot()`.
+    pub compaction_interval: Option<u64>,
+    /// The size in bytes of the decided entries that are not compacted after which the log is compacted automatically in
+    /// `tick()`, as estimated by [`Entry::size_hint()`]. If both this and `compaction_interval` are set, the log is
+    /// compacted once either of them is exceeded. Defaults to `None`.
+    pub compaction_log_bytes: Option<usize>,
+    /// The number of the latest decided entries that are kept when the log is compacted automatically, so that followers
+    /// that are slightly behind can still be synchronized with entries instead of a snapshot. Defaults to 0.
+    pub compaction_safety_margin: u64,
     /// The path where the default logger logs events.
     #[cfg(feature = "logging")]
     pub logger_file_path: Option<String>,
@@ -196,6 +328,30 @@ impl ServerConfig {
             self.resend_message_tick_timeout != 0,
             "Resend message tick timeout must be greater than 0"
         );
+        valid_config!(
+            self.leader_lease_tick_duration != Some(0),
+            "Leader lease tick duration must be greater than 0"
+        );
+        valid_config!(
+            self.max_pending_proposals != Some(0),
+            "Max pending proposals must be greater than 0"
+        );
+        valid_config!(
+            self.max_sync_entries != Some(0),
+            "Max sync entries must be greater than 0"
+        );
+        valid_config!(
+            self.max_sync_bytes != Some(0),
+            "Max sync bytes must be greater than 0"
+        );
+        valid_config!(
+            self.compaction_interval != Some(0),
+            "Compaction interval must be greater than 0"
+        );
+        valid_config!(
+            self.compaction_log_bytes != Some(0),
+            "Compaction log bytes must be greater than 0"
+        );
         Ok(())
     }
 }
@@ -209,6 +365,17 @@ impl Default for ServerConfig {
             buffer_size: BUFFER_SIZE,
             batch_size: 1,
             leader_priority: 0,
+            leader_lease_tick_duration: None,
+            pre_vote: false,
+            check_quorum: false,
+            max_pending_proposals: None,
+            halt_on_storage_error: false,
+            async_flush: false,
+            max_sync_entries: None,
+            max_sync_bytes: None,
+            compaction_interval: None,
+            compaction_log_bytes: None,
+            compaction_safety_margin: 0,
             #[cfg(feature = "logging")]
             logger_file_path: None,
             #[cfg(feature = "logging")]
@@ -226,8 +393,14 @@ where
 {
     seq_paxos: SequencePaxos<T, B>,
     ble: BallotLeaderElection,
+    // Used to restart the leader election when resuming after a storage error
+    ble_config: BLEConfig,
     election_clock: LogicalClock,
     resend_message_clock: LogicalClock,
+    /// The index up to which the decided entries have been returned by `poll_decided`.
+    applied_idx: u64,
+    // The client sessions of the entries returned by `poll_decided()`
+    applied_sessions: ClientSessions,
 }
 
 impl<T, B> OmniPaxos<T, B>
@@ -239,6 +412,9 @@ where
     /// # Arguments
     /// * `trim_index` - Deletes all entries up to [`trim_index`], if the [`trim_index`] is `None` then the minimum index accepted by **ALL** servers will be used as the [`trim_index`].
     pub fn trim(&mut self, trim_index: Option<u64>) -> Result<(), CompactionErr> {
+        if self.seq_paxos.is_halted() {
+            return Err(CompactionErr::Halted);
+        }
         self.seq_paxos.trim(trim_index)
     }
 
@@ -251,9 +427,54 @@ where
         compact_idx: Option<u64>,
         local_only: bool,
     ) -> Result<(), CompactionErr> {
+        if self.seq_paxos.is_halted() {
+            return Err(CompactionErr::Halted);
+        }
         self.seq_paxos.snapshot(compact_idx, local_only)
     }
 
+    /// Enables the out-of-band transfer of snapshots of the application state, e.g., if the state is too large to be sent
+    /// as a [`Snapshot`] in a message. If a follower needs entries that have been compacted, the leader only sends it the
+    /// metadata of the latest snapshot of `provider`, and the follower pulls the snapshot in chunks and hands them to
+    /// `installer`. Afterwards, the leader synchronizes the follower with the log from the index of the snapshot, and
+    /// `poll_decided()` on the follower returns a `LogEntry::Trimmed` in place of the entries of the snapshot. The transfer
+    /// should be enabled on all servers, as any of them can become the leader.
+    pub fn set_snapshot_transfer<P, I>(&mut self, provider: P, installer: I)
+    where
+        P: SnapshotProvider + 'static,
+        I: SnapshotInstaller + 'static,
+    {
+        self.seq_paxos
+            .set_snapshot_transfer(Box::new(provider), Box::new(installer));
+    }
+
+    /// Compacts the log with a snapshot of the application state that the application took after applying the decided
+    /// entries before `idx`, e.g., by serializing its state machine. Unlike `snapshot()`, this does not require
+    /// [`Snapshot`] to be implemented for the entries. The entries before `idx` are trimmed, and if a follower needs
+    /// entries that have been compacted, the leader transfers the latest snapshot to it in chunks, see
+    /// [`poll_application_snapshot()`](OmniPaxos::poll_application_snapshot). The snapshot is only kept in memory, so it
+    /// should be installed again after a restart. Until then, a leader does not synchronize followers that need
+    /// compacted entries. If a provider is set with `set_snapshot_transfer()`, its snapshots
+    /// are transferred instead.
+    pub fn install_application_snapshot(
+        &mut self,
+        idx: u64,
+        snapshot: Vec<u8>,
+    ) -> Result<(), CompactionErr> {
+        if self.seq_paxos.is_halted() {
+            return Err(CompactionErr::Halted);
+        }
+        self.seq_paxos.install_application_snapshot(idx, snapshot)
+    }
+
+    /// Returns the index and the bytes of a snapshot of the application state that has been transferred from the leader
+    /// since the last call, see [`install_application_snapshot()`](OmniPaxos::install_application_snapshot). The
+    /// application should restore its state from the snapshot before it applies the entries from `poll_decided()`, which
+    /// returns a `LogEntry::Trimmed` in place of the entries of the snapshot.
+    pub fn poll_application_snapshot(&mut self) -> Option<(u64, Vec<u8>)> {
+        self.seq_paxos.take_installed_application_snapshot()
+    }
+
     /// Return the decided index.
     pub fn get_decided_idx(&self) -> u64 {
         self.seq_paxos.get_decided_idx()
@@ -264,6 +485,11 @@ where
         self.seq_paxos.get_compacted_idx()
     }
 
+    /// Returns how often this server synchronized a follower with a snapshot or with log entries while it was the leader.
+    pub fn get_sync_metrics(&self) -> SyncMetrics {
+        self.seq_paxos.get_sync_metrics()
+    }
+
     /// Returns the id of the current leader.
     pub fn get_current_leader(&self) -> Option<NodeId> {
         let promised_pid = self.seq_paxos.get_promise().pid;
@@ -280,7 +506,11 @@ where
     }
 
     /// Returns the outgoing messages from this server. The messages should then be sent via the network implementation.
+    /// No messages are returned while this server is halted due to a storage error.
     pub fn outgoing_messages(&mut self) -> Vec<Message<T>> {
+        if self.seq_paxos.is_halted() {
+            return vec![];
+        }
         let paxos_msgs = self
             .seq_paxos
             .get_outgoing_msgs()
@@ -326,12 +556,93 @@ where
             .expect("storage error while trying to read decided log suffix")
     }
 
-    /// Handle an incoming message
+    /// Returns the entries that have been decided since the last call, so that each decided entry is returned exactly once.
+    /// If the entries have been compacted since the last call, the first returned entry is a `LogEntry::Snapshotted` or
+    /// `LogEntry::Trimmed` in their place. Afterwards, the applied index is the decided index. Decided entries that are
+    /// duplicates of earlier entries of the same [`ClientSession`](crate::util::ClientSession) are skipped.
+    pub fn poll_decided(&mut self) -> Vec<LogEntry<T>> {
+        self.poll_decided_with_idx()
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// Like [`poll_decided()`](OmniPaxos::poll_decided), but returns each entry together with its index in the log. For a
+    /// `LogEntry::Snapshotted` or `LogEntry::Trimmed`, this is the index of the last entry that it replaces. The indices
+    /// of the entries after a skipped duplicate are not shifted.
+    pub fn poll_decided_with_idx(&mut self) -> Vec<(u64, LogEntry<T>)> {
+        let from_idx = self.applied_idx;
+        match self.read_decided_suffix(from_idx) {
+            Some(entries) => {
+                self.applied_idx = self.get_decided_idx();
+                let sessions = &mut self.applied_sessions;
+                let mut next_idx = from_idx;
+                entries
+                    .into_iter()
+                    .map(|e| {
+                        let idx = match &e {
+                            LogEntry::Trimmed(compacted_idx) => compacted_idx.saturating_sub(1),
+                            LogEntry::Snapshotted(s) => s.trimmed_idx.saturating_sub(1),
+                            _ => next_idx,
+                        };
+                        next_idx = idx + 1;
+                        (idx, e)
+                    })
+                    .filter(|(_, e)| match e {
+                        LogEntry::Decided(entry) => sessions.update(entry),
+                        LogEntry::Snapshotted(s) => {
+                            if let Some(snapshot_sessions) = s.snapshot.client_sessions() {
+                                sessions.merge(snapshot_sessions);
+                            }
+                            true
+                        }
+                        _ => true,
+                    })
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    /// Returns the index up to which the decided entries have been returned by `poll_decided()`. The application can persist it
+    /// together with its state machine and restore it with `set_applied_idx()` after recovering.
+    pub fn get_applied_idx(&self) -> u64 {
+        self.applied_idx
+    }
+
+    /// Sets the index up to which the decided entries have already been applied by the application, e.g., after it recovered its
+    /// state machine. The next call to `poll_decided()` returns the decided entries from this index. The client sessions of
+    /// the entries before this index are restored from the log and the snapshot.
+    pub fn set_applied_idx(&mut self, applied_idx: u64) {
+        self.applied_idx = applied_idx;
+        self.applied_sessions = self
+            .seq_paxos
+            .internal_storage
+            .get_client_sessions(applied_idx)
+            .expect("storage error while trying to read client sessions");
+    }
+
+    /// Handle an incoming message. Messages are ignored while this server is halted due to a storage error.
     pub fn handle_incoming(&mut self, m: Message<T>) {
+        if self.seq_paxos.is_halted() {
+            return;
+        }
         match m {
-            Message::SequencePaxos(p) => self.seq_paxos.handle(p),
+            Message::SequencePaxos(p) => {
+                if let PaxosMsg::Prepare(prep) = &p.msg {
+                    match self.ble.get_granted_lease() {
+                        // The lease we granted to the current leader has not expired yet
+                        Some(leader) if leader.pid != prep.n.pid => return,
+                        _ => {}
+                    }
+                }
+                self.seq_paxos.handle(p);
+            }
             Message::BLE(b) => self.ble.handle(b),
         }
+        self.update_membership();
+        self.take_over_leadership();
+        self.update_leader_lease();
     }
 
     /// Returns whether this Sequence Paxos has been reconfigured
@@ -339,11 +650,40 @@ where
         self.seq_paxos.is_reconfigured()
     }
 
-    /// Append an entry to the replicated log.
-    pub fn append(&mut self, entry: T) -> Result<(), ProposeErr<T>> {
+    /// Append an entry to the replicated log. Returns the id of the proposal, which is used to report whether the entry
+    /// was decided with [`proposal_outcomes()`](OmniPaxos::proposal_outcomes). If this server is not the leader, the entry
+    /// is forwarded to the leader. If `max_pending_proposals` of the `ServerConfig` is set and reached, the entry is
+    /// returned with `ProposeErr::NoLeader` if there is currently no leader and `ProposeErr::Overloaded` otherwise.
+    pub fn append(&mut self, entry: T) -> Result<ProposalId, ProposeErr<T>> {
+        if self.seq_paxos.is_halted() {
+            return Err(ProposeErr::HaltedEntry(entry));
+        }
         self.seq_paxos.append(entry)
     }
 
+    /// Returns the outcomes of the proposals of this server that have been decided or dropped since the last call.
+    /// A proposal is dropped if the leader that it was sent to is replaced before deciding it or if this server has to
+    /// reconnect to the leader or is resumed after a storage error, since the proposal might have been lost. Each
+    /// proposal has at most one outcome, i.e., a dropped proposal that is decided nonetheless is not reported again.
+    pub fn proposal_outcomes(&mut self) -> Vec<ProposalOutcome> {
+        self.seq_paxos.get_proposal_outcomes()
+    }
+
+    /// Request a linearizable read. Returns the id of the read request.
+    /// The leader confirms that it is still the leader with a quorum, after which a [`ReadIndex`] with the same id
+    /// is returned by [`confirmed_reads()`](OmniPaxos::confirmed_reads). The read is linearizable if it is served
+    /// from the application state after the decided index of this server has reached the `read_idx` of the `ReadIndex`.
+    /// If this server is not the leader, the request is forwarded to the leader. A read might never be confirmed
+    /// if the leader changes in the meantime, in which case it should be retried.
+    pub fn read_index(&mut self) -> ReadId {
+        self.seq_paxos.read_index()
+    }
+
+    /// Returns the read requests of this server that have been confirmed since the last call. See [`read_index()`](OmniPaxos::read_index).
+    pub fn confirmed_reads(&mut self) -> Vec<ReadIndex> {
+        self.seq_paxos.get_confirmed_reads()
+    }
+
     /// Propose a cluster reconfiguration. Returns an error if the current configuration has already been stopped
     /// by a previous reconfiguration request or if the `new_configuration` is invalid.
     /// `new_configuration` defines the cluster-wide configuration settings for the **next** cluster.
@@ -360,24 +700,190 @@ where
                 metadata,
             ));
         }
+        if self.seq_paxos.is_halted() {
+            return Err(ProposeErr::HaltedConfig(new_configuration, metadata));
+        }
         self.seq_paxos.reconfigure(new_configuration, metadata)
     }
 
+    /// Propose an in-place membership change. Unlike [`reconfigure()`](OmniPaxos::reconfigure), the servers keep
+    /// the same log and ballots and no new OmniPaxos instance has to be started. `new_configuration` must have the
+    /// same `configuration_id` as the current configuration and add or remove at most one voting node, while learners
+    /// can be changed freely. Flexible quorums are not supported. A new server must be started with `new_configuration`
+    /// and is synchronized by the leader once the change is accepted. Returns an error if `new_configuration` is invalid
+    /// or if another membership change is still pending at this server. A newly elected leader first proposes the
+    /// current membership again in its round, and returns [`ProposeErr::PendingMembershipChange`] until a quorum has
+    /// accepted it.
+    pub fn change_membership(
+        &mut self,
+        new_configuration: ClusterConfig,
+    ) -> Result<(), ProposeErr<T>> {
+        if let Err(config_error) = new_configuration.validate().and_then(|_| {
+            self.seq_paxos
+                .get_cluster_config()
+                .validate_membership_change(&new_configuration)
+        }) {
+            return Err(ProposeErr::ConfigError(
+                config_error,
+                new_configuration,
+                None,
+            ));
+        }
+        if self.seq_paxos.is_halted() {
+            return Err(ProposeErr::HaltedConfig(new_configuration, None));
+        }
+        self.seq_paxos.change_membership(new_configuration)
+    }
+
+    /// Returns the current cluster configuration of this server, including any in-place membership changes.
+    pub fn get_cluster_config(&self) -> ClusterConfig {
+        self.seq_paxos.get_cluster_config().clone()
+    }
+
+    /// Creates the `OmniPaxos` server of the next configuration after this configuration has been stopped by a decided
+    /// `StopSign`. The new server uses `server_config` and `storage` and starts with the decided log of this server.
+    /// Returns an error if this configuration has not been stopped yet, if the server is not part of the next configuration,
+    /// or if the log was trimmed without a snapshot. See [`from_log_pull()`](OmniPaxos::from_log_pull).
+    pub fn migrate<S>(
+        &self,
+        server_config: ServerConfig,
+        storage: S,
+    ) -> Result<OmniPaxos<T, S>, ConfigError>
+    where
+        S: Storage<T>,
+    {
+        let pid = server_config.pid;
+        match self.handle_log_pull(LogPullRequest { from: pid, to: pid }) {
+            Some(log) => OmniPaxos::from_log_pull(log, server_config, storage),
+            None => Err(ConfigError::InvalidConfig(
+                "The configuration has not been stopped by a decided StopSign".to_owned(),
+            )),
+        }
+    }
+
+    /// Handles a request for the decided log of this configuration from a server of the next configuration. Returns
+    /// the response that should be sent back to the requesting server, or `None` if this configuration has not been stopped yet.
+    pub fn handle_log_pull(&self, request: LogPullRequest) -> Option<LogPullResponse<T>> {
+        let stopsign = self.seq_paxos.is_reconfigured()?;
+        let internal_storage = &self.seq_paxos.internal_storage;
+        let compacted_idx = internal_storage.get_compacted_idx();
+        let snapshot = internal_storage
+            .get_snapshot()
+            .expect("storage error while trying to read snapshot");
+        // All entries are decided since the StopSign is decided
+        let entries = internal_storage
+            .get_suffix(compacted_idx)
+            .expect("storage error while trying to read log suffix");
+        Some(LogPullResponse {
+            from: request.to,
+            to: request.from,
+            stopsign,
+            compacted_idx,
+            snapshot,
+            entries,
+        })
+    }
+
+    /// Creates the `OmniPaxos` server of the next configuration from the decided log of the stopped configuration,
+    /// e.g., on a server that was not part of the stopped configuration. See [`handle_log_pull()`](OmniPaxos::handle_log_pull).
+    /// If `storage` already contains the state of the next configuration, e.g., when recovering, the log is not
+    /// written again. Returns an error if the server is not part of the next configuration, or if the log of the stopped
+    /// configuration was compacted without a snapshot, since the compacted entries can then not be restored.
+    pub fn from_log_pull(
+        log: LogPullResponse<T>,
+        server_config: ServerConfig,
+        mut storage: B,
+    ) -> Result<Self, ConfigError> {
+        let config = OmniPaxosConfig {
+            cluster_config: log.stopsign.next_config,
+            server_config,
+        };
+        config.validate()?;
+        let started = matches!(
+            storage
+                .get_promise()
+                .expect("storage error while trying to read promise"),
+            Some(b) if b != Ballot::default()
+        );
+        let decided_idx = log.compacted_idx + log.entries.len() as u64;
+        if !started && decided_idx > 0 {
+            if log.compacted_idx > 0 && log.snapshot.is_none() {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "The log was trimmed up to {} without a snapshot",
+                    log.compacted_idx
+                )));
+            }
+            if log.compacted_idx > 0 {
+                storage
+                    .set_snapshot(log.snapshot)
+                    .expect("storage error while trying to write snapshot");
+                storage
+                    .set_compacted_idx(log.compacted_idx)
+                    .expect("storage error while trying to write compacted index");
+            }
+            storage
+                .append_on_prefix(0, log.entries)
+                .expect("storage error while trying to write log entries");
+            storage
+                .set_decided_idx(decided_idx)
+                .expect("storage error while trying to write decided index");
+        }
+        config.build(storage)
+    }
+
     /// Handles re-establishing a connection to a previously disconnected peer.
     /// This should only be called if the underlying network implementation indicates that a connection has been re-established.
     pub fn reconnected(&mut self, pid: NodeId) {
-        self.seq_paxos.reconnected(pid)
+        if !self.seq_paxos.is_halted() {
+            self.seq_paxos.reconnected(pid)
+        }
     }
 
     /// Increments the internal logical clock. Will trigger leader changes and resend dropped messages (if required)
     /// after every `election_tick_timeout` and `resend_message_tick_timeout` number of calls to this function (See how to set these in `ServerConfig`).
+    /// Does nothing while this server is halted due to a storage error.
     pub fn tick(&mut self) {
+        if self.seq_paxos.is_halted() {
+            return;
+        }
         if self.election_clock.tick_and_check_timeout() {
             self.election_timeout();
         }
         if self.resend_message_clock.tick_and_check_timeout() {
             self.seq_paxos.resend_message_timeout();
         }
+        self.seq_paxos.auto_compact();
+        self.update_membership();
+        self.ble.tick();
+        self.update_leader_lease();
+    }
+
+    /// Returns the storage error that halted this server, if any. See `halt_on_storage_error` in [`ServerConfig`].
+    pub fn storage_error(&self) -> Option<&StorageError> {
+        self.seq_paxos.get_storage_error()
+    }
+
+    /// Resumes this server after it was halted due to a storage error, e.g., once the disk has been fixed. The state is
+    /// loaded from the storage again and the server rejoins the cluster like after a crash: the leader election is restarted
+    /// and the unresolved proposals of this server are reported as dropped. Returns the error if the storage still fails,
+    /// in which case the server stays halted.
+    pub fn resume(&mut self) -> Result<(), &StorageError> {
+        if !self.seq_paxos.is_halted() {
+            return Ok(());
+        }
+        self.seq_paxos.recover();
+        if self.seq_paxos.is_halted() {
+            return Err(self.seq_paxos.get_storage_error().unwrap());
+        }
+        let priority = self.ble.get_current_ballot().priority;
+        self.ble =
+            BallotLeaderElection::with(self.ble_config.clone(), Some(self.seq_paxos.get_promise()));
+        self.ble.set_priority(priority);
+        self.ble
+            .set_cluster_config(self.seq_paxos.get_cluster_config());
+        self.seq_paxos.take_membership_change();
+        self.update_leader_lease();
+        Ok(())
     }
 
     /*** BLE calls ***/
@@ -396,9 +902,47 @@ where
             .hb_timeout(self.seq_paxos.get_state(), self.seq_paxos.get_promise())
         {
             self.seq_paxos.handle_leader(new_leader);
+        } else if self.ble.has_stepped_down() {
+            self.seq_paxos.step_down();
+        }
+    }
+
+    fn update_membership(&mut self) {
+        if let Some(config) = self.seq_paxos.take_membership_change() {
+            self.ble.set_cluster_config(&config);
         }
     }
 
+    fn update_leader_lease(&mut self) {
+        let valid = self.has_leader_lease();
+        self.seq_paxos.set_leader_lease(valid);
+    }
+
+    /// Transfers the leadership to the server `to`. The leader waits until `to` has accepted all its entries and
+    /// then tells it to take over with a higher ballot. New proposals are held back in the meantime and are forwarded
+    /// to the new leader. If `to` has not taken over within two `resend_message_tick_timeout`s, the transfer is given up
+    /// and this server proposes the held back entries itself. Returns an error if this server is not the leader or if
+    /// `to` is not a voting server of the configuration.
+    pub fn transfer_leadership(&mut self, to: NodeId) -> Result<(), TransferLeadershipErr> {
+        if self.seq_paxos.is_halted() {
+            return Err(TransferLeadershipErr::Halted);
+        }
+        self.seq_paxos.transfer_leadership(to)
+    }
+
+    fn take_over_leadership(&mut self) {
+        if let Some(leader) = self.seq_paxos.take_takeover_request() {
+            let ballot = self.ble.take_over(leader);
+            self.seq_paxos.handle_leader(ballot);
+        }
+    }
+
+    /// Returns whether this server is the leader and holds a valid leader lease. See `leader_lease_tick_duration` in [`ServerConfig`].
+    pub fn has_leader_lease(&self) -> bool {
+        self.ble.has_lease(self.seq_paxos.get_promise())
+            && self.seq_paxos.get_state() == &(Role::Leader, Phase::Accept)
+    }
+
     /// Returns the current states of the OmniPaxos instance for OmniPaxos UI to display.
     pub fn get_ui_states(&self) -> ui::OmniPaxosStates {
         let mut cluster_state = ClusterState::from(self.seq_paxos.get_leader_state());
@@ -429,6 +973,38 @@ where
     /// Couldn't propose reconfiguration because of an invalid cluster config. Contains the config
     /// error and the failed, proposed cluster config and metadata.
     ConfigError(ConfigError, ClusterConfig, Option<Vec<u8>>),
+    /// Couldn't propose membership change because a membership change is already pending or the leader
+    /// is not yet synchronized. Returns the failed, proposed `ClusterConfig`.
+    PendingMembershipChange(ClusterConfig),
+    /// Couldn't propose entry because `max_pending_proposals` is reached and there is no leader. Returns the failed, proposed entry.
+    NoLeader(T),
+    /// Couldn't propose entry because `max_pending_proposals` is reached while the leader has not decided the earlier
+    /// proposals of this server yet. Returns the failed, proposed entry.
+    Overloaded(T),
+    /// Couldn't propose entry because this server is halted due to a storage error. Returns the failed, proposed entry.
+    HaltedEntry(T),
+    /// Couldn't propose reconfiguration or membership change because this server is halted due to a storage error.
+    /// Returns the failed, proposed `ClusterConfig` and the metadata.
+    HaltedConfig(ClusterConfig, Option<Vec<u8>>),
+}
+
+/// An error indicating a failed leadership transfer.
+#[derive(Copy, Clone, Debug)]
+pub enum TransferLeadershipErr {
+    /// The transfer was requested at a server that is not the leader or not yet synchronized with its followers.
+    /// Returns the current leader as known by this server.
+    NotCurrentLeader(NodeId),
+    /// The target of the transfer is not a voting server of the configuration. Returns the target.
+    InvalidTarget(NodeId),
+    /// This server is halted due to a storage error.
+    Halted,
+}
+
+impl Error for TransferLeadershipErr {}
+impl Display for TransferLeadershipErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
 }
 
 /// An error returning the proposal that was failed due to that the current configuration is stopped.
@@ -442,6 +1018,8 @@ pub enum CompactionErr {
     NotAllDecided(u64),
     /// Trim was called at a follower node. Trim must be called by the leader, which is the returned NodeId.
     NotCurrentLeader(NodeId),
+    /// This server is halted due to a storage error.
+    Halted,
 }
 
 impl Error for CompactionErr {}
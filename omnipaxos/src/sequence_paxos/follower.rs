@@ -3,8 +3,9 @@ use super::super::ballot_leader_election::Ballot;
 use super::*;
 
 use crate::{
-    storage::{RollbackValue, Snapshot, SnapshotType, StorageResult},
-    util::MessageStatus,
+    errors::{StorageContext, StorageError},
+    storage::{RollbackValue, Snapshot, SnapshotType, StorageOp},
+    util::{MessageStatus, ReadIndex},
 };
 
 impl<T, B> SequencePaxos<T, B>
@@ -13,35 +14,54 @@ where
     B: Storage<T>,
 {
     /*** Follower ***/
-    pub(crate) fn handle_prepare(&mut self, prep: Prepare, from: NodeId) {
+    pub(crate) fn handle_prepare(
+        &mut self,
+        prep: Prepare,
+        from: NodeId,
+    ) -> Result<(), StorageError> {
         let old_promise = self.internal_storage.get_promise();
         if old_promise < prep.n || (old_promise == prep.n && self.state.1 == Phase::Recover) {
+            if old_promise < prep.n {
+                self.drop_unresolved_proposals();
+            }
+            // The batched entries and the promise are written together, the promise message is
+            // created from the state afterwards.
+            let mut ops = vec![];
+            let batched_entries = self.internal_storage.take_batched_entries();
+            if !batched_entries.is_empty() {
+                ops.push(StorageOp::AppendEntries(batched_entries));
+            }
+            ops.push(StorageOp::SetPromise(prep.n));
             self.internal_storage
-                .flush_batch()
-                .expect("storage error while trying to flush batch");
+                .write_atomically(ops)
+                .context("write promise")?;
             self.state = (Role::Follower, Phase::Prepare);
             self.current_seq_num = SequenceNumber::default();
+            self.pending_flush = None;
+            self.snapshot_transfer = None;
             let na = self.internal_storage.get_accepted_round();
             let accepted_idx = self.internal_storage.get_accepted_idx();
             let decided_idx = self.get_decided_idx();
             let stopsign = self.internal_storage.get_stopsign();
+            // The suffix is not limited by `max_sync_entries` or `max_sync_bytes`, since the leader needs the
+            // whole log of the most updated follower before it can synchronize the others.
             let (decided_snapshot, suffix) = if na > prep.n_accepted {
                 let ld = prep.decided_idx;
                 if ld < decided_idx && T::Snapshot::use_snapshots() {
                     let (delta_snapshot, _) = self
                         .internal_storage
                         .create_diff_snapshot(ld)
-                        .expect("storage error while trying to read diff snapshot");
+                        .context("read diff snapshot")?;
                     let suffix = self
                         .internal_storage
                         .get_suffix(decided_idx)
-                        .expect("storage error while trying to read log suffix");
+                        .context("read log suffix")?;
                     (delta_snapshot, suffix)
                 } else {
                     let suffix = self
                         .internal_storage
                         .get_suffix(ld)
-                        .expect("storage error while trying to read log suffix");
+                        .context("read log suffix")?;
                     (None, suffix)
                 }
             } else if na == prep.n_accepted && accepted_idx > prep.accepted_idx {
@@ -50,25 +70,22 @@ where
                     let (delta_snapshot, _) = self
                         .internal_storage
                         .create_diff_snapshot(prep.decided_idx)
-                        .expect("storage error while trying to read diff snapshot");
+                        .context("read diff snapshot")?;
                     let suffix = self
                         .internal_storage
                         .get_suffix(decided_idx)
-                        .expect("storage error while trying to read log suffix");
+                        .context("read log suffix")?;
                     (delta_snapshot, suffix)
                 } else {
                     let suffix = self
                         .internal_storage
                         .get_suffix(prep.accepted_idx)
-                        .expect("storage error while trying to read log suffix");
+                        .context("read log suffix")?;
                     (None, suffix)
                 }
             } else {
                 (None, vec![])
             };
-            self.internal_storage
-                .set_promise(prep.n)
-                .expect("storage error while trying to write promise");
             let promise = Promise {
                 n: prep.n,
                 n_accepted: na,
@@ -77,6 +94,7 @@ where
                 decided_idx,
                 accepted_idx,
                 stopsign,
+                membership: self.internal_storage.get_membership().map(Box::new),
             };
             self.cached_promise_message = Some(promise.clone());
             self.outgoing.push(PaxosMessage {
@@ -85,92 +103,38 @@ where
                 msg: PaxosMsg::Promise(promise),
             });
         }
+        Ok(())
     }
 
-    // Correctness: This function performs multiple storage operations that cannot be rolled
-    // back, so instead it relies on writing in a "safe" order for correctness.
-    pub(crate) fn handle_acceptsync(&mut self, accsync: AcceptSync<T>, from: NodeId) {
+    pub(crate) fn handle_acceptsync(
+        &mut self,
+        accsync: AcceptSync<T>,
+        from: NodeId,
+    ) -> Result<(), StorageError> {
         if self.check_valid_ballot(accsync.n) && self.state == (Role::Follower, Phase::Prepare) {
-            let old_decided_idx = self.internal_storage.get_decided_idx();
-            let old_accepted_round = self.internal_storage.get_accepted_round();
-            self.internal_storage
-                .set_accepted_round(accsync.n)
-                .expect("storage error while trying to write accepted round");
-            let result = self.internal_storage.set_decided_idx(accsync.decided_idx);
-            self.internal_storage.rollback_and_panic_if_err(
-                &result,
-                vec![RollbackValue::AcceptedRound(old_accepted_round)],
-                "storage error while trying to write decided index",
-            );
-            match accsync.decided_snapshot {
-                Some(s) => {
-                    let old_compacted_idx = self.internal_storage.get_compacted_idx();
-                    let old_log_res = self.internal_storage.get_suffix(old_compacted_idx);
-                    let old_snapshot_res = self.internal_storage.get_snapshot();
-                    if old_log_res.is_err() || old_snapshot_res.is_err() {
-                        self.internal_storage.rollback_and_panic(
-                            vec![
-                                RollbackValue::AcceptedRound(old_accepted_round),
-                                RollbackValue::DecidedIdx(old_decided_idx),
-                            ],
-                            "storage error while trying to read old log or snapshot",
-                        );
-                    }
-                    let snapshot_res = match s {
-                        SnapshotType::Complete(c) => {
-                            self.internal_storage.set_snapshot(accsync.sync_idx, c)
-                        }
-                        SnapshotType::Delta(d) => {
-                            self.internal_storage.merge_snapshot(accsync.sync_idx, d)
-                        }
-                    };
-                    self.internal_storage.rollback_and_panic_if_err(
-                        &snapshot_res,
-                        vec![
-                            RollbackValue::AcceptedRound(old_accepted_round),
-                            RollbackValue::DecidedIdx(old_decided_idx),
-                        ],
-                        "storage error while trying to write snapshot",
-                    );
-                    let accepted_res = self
-                        .internal_storage
-                        .append_on_prefix(accsync.sync_idx, accsync.suffix);
-                    self.internal_storage.rollback_and_panic_if_err(
-                        &accepted_res,
-                        vec![
-                            RollbackValue::AcceptedRound(old_accepted_round),
-                            RollbackValue::DecidedIdx(old_decided_idx),
-                            RollbackValue::Log(old_log_res.unwrap()),
-                            RollbackValue::Snapshot(old_compacted_idx, old_snapshot_res.unwrap()),
-                        ],
-                        "storage error while trying to write log entries",
-                    );
-                }
-                None => {
-                    // no snapshot, only suffix
-                    let accepted_idx = self
-                        .internal_storage
-                        .append_on_prefix(accsync.sync_idx, accsync.suffix);
-                    self.internal_storage.rollback_and_panic_if_err(
-                        &accepted_idx,
-                        vec![
-                            RollbackValue::AcceptedRound(old_accepted_round),
-                            RollbackValue::DecidedIdx(old_decided_idx),
-                        ],
-                        "storage error while trying to write log entries",
-                    );
-                }
-            };
+            self.write_sync(
+                accsync.n,
+                accsync.decided_idx,
+                accsync.decided_snapshot,
+                accsync.sync_idx,
+                accsync.suffix,
+            )?;
             match accsync.stopsign {
-                Some(ss) => self.accept_stopsign(ss),
+                Some(ss) => self.accept_stopsign(ss)?,
                 None => self.forward_pending_proposals(),
             }
+            if let Some(membership) = accsync.membership {
+                self.accept_membership_follower(accsync.n, *membership)?;
+            }
+            self.forward_pending_reads();
             let accepted = Accepted {
                 n: accsync.n,
                 accepted_idx: self.internal_storage.get_accepted_idx(),
             };
             self.state = (Role::Follower, Phase::Accept);
             self.current_seq_num = accsync.seq_num;
+            self.pending_flush = None;
+            self.snapshot_transfer = None;
             let cached_idx = self.outgoing.len();
             self.latest_accepted_meta = Some((accsync.n, cached_idx));
             self.outgoing.push(PaxosMessage {
@@ -181,6 +145,219 @@ where
             #[cfg(feature = "unicache")]
             self.internal_storage.set_unicache(accsync.unicache);
         }
+        Ok(())
+    }
+
+    // Correctness: This function performs multiple storage operations that cannot be rolled
+    // back, so instead it relies on writing in a "safe" order for correctness.
+    fn write_sync(
+        &mut self,
+        n: Ballot,
+        decided_idx: u64,
+        decided_snapshot: Option<SnapshotType<T>>,
+        sync_idx: u64,
+        suffix: Vec<T>,
+    ) -> Result<(), StorageError> {
+        let old_decided_idx = self.internal_storage.get_decided_idx();
+        let old_accepted_round = self.internal_storage.get_accepted_round();
+        let mut ops = vec![
+            StorageOp::SetAcceptedRound(n),
+            StorageOp::SetDecidedIdx(decided_idx),
+        ];
+        match decided_snapshot {
+            Some(s) => {
+                let result = self.internal_storage.write_atomically(ops);
+                self.internal_storage.rollback_if_err(
+                    result,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                    ],
+                    "write accepted round and decided index",
+                )?;
+                let old_compacted_idx = self.internal_storage.get_compacted_idx();
+                let old_log_res = self.internal_storage.get_suffix(old_compacted_idx);
+                let old_log = self.internal_storage.rollback_if_err(
+                    old_log_res,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                    ],
+                    "read old log",
+                )?;
+                let old_snapshot_res = self.internal_storage.get_snapshot();
+                let old_snapshot = self.internal_storage.rollback_if_err(
+                    old_snapshot_res,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                    ],
+                    "read old snapshot",
+                )?;
+                let snapshot_res = match s {
+                    SnapshotType::Complete(c) => self.internal_storage.set_snapshot(sync_idx, c),
+                    SnapshotType::Delta(d) => self.internal_storage.merge_snapshot(sync_idx, d),
+                };
+                self.internal_storage.rollback_if_err(
+                    snapshot_res,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                    ],
+                    "write snapshot",
+                )?;
+                let accepted_res = self.internal_storage.append_on_prefix(sync_idx, suffix);
+                self.internal_storage.rollback_if_err(
+                    accepted_res,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                        RollbackValue::Log(old_log),
+                        RollbackValue::Snapshot(old_compacted_idx, old_snapshot),
+                    ],
+                    "write log entries",
+                )?;
+            }
+            None => {
+                // no snapshot, only suffix
+                ops.push(StorageOp::AppendOnPrefix(sync_idx, suffix));
+                let result = self.internal_storage.write_atomically(ops);
+                self.internal_storage.rollback_if_err(
+                    result,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                    ],
+                    "write log entries",
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_accept_sync_chunk(
+        &mut self,
+        chunk: AcceptSyncChunk<T>,
+    ) -> Result<(), StorageError> {
+        if self.check_valid_ballot(chunk.n) && self.state == (Role::Follower, Phase::Accept) {
+            match self.handle_sequence_num(chunk.seq_num, chunk.n.pid) {
+                MessageStatus::Expected => {
+                    let decided_idx = chunk.decided_idx.max(self.get_decided_idx());
+                    self.write_sync(
+                        chunk.n,
+                        decided_idx,
+                        chunk.decided_snapshot,
+                        chunk.sync_idx,
+                        chunk.entries,
+                    )?;
+                    if let Some(ss) = chunk.stopsign {
+                        self.accept_stopsign(ss)?;
+                    }
+                }
+                // The leader resends a chunk if it did not get the `Accepted` for it
+                MessageStatus::Outdated => {}
+                MessageStatus::DroppedPreceding => return Ok(()),
+            }
+            let accepted_idx = self.internal_storage.get_accepted_idx();
+            self.handle_flushed_accepted(chunk.n, accepted_idx);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_snapshot_offer(
+        &mut self,
+        offer: SnapshotOffer,
+    ) -> Result<(), StorageError> {
+        // An offer is outdated if the entries of the snapshot have been decided already
+        if !self.check_valid_ballot(offer.n)
+            || !matches!(self.state, (Role::Follower, Phase::Prepare | Phase::Accept))
+            || offer.metadata.idx <= self.get_decided_idx()
+        {
+            return Ok(());
+        }
+        let offset = match self.snapshot_transfer {
+            // The leader offers the snapshot again if a chunk was lost, so the transfer is resumed
+            Some((n, metadata, offset)) if n == offer.n && metadata == offer.metadata => offset,
+            _ => {
+                self.snapshot_installer()
+                    .begin(&offer.metadata)
+                    .context("begin snapshot transfer")?;
+                0
+            }
+        };
+        // The leader synchronizes the log once the snapshot has been installed
+        self.state = (Role::Follower, Phase::Prepare);
+        self.pending_flush = None;
+        self.snapshot_transfer = Some((offer.n, offer.metadata, offset));
+        self.pull_snapshot()
+    }
+
+    pub(crate) fn handle_snapshot_chunk(
+        &mut self,
+        chunk: SnapshotChunk,
+    ) -> Result<(), StorageError> {
+        match self.snapshot_transfer {
+            Some((n, metadata, offset))
+                if n == chunk.n
+                    && metadata.id == chunk.id
+                    && offset == chunk.offset
+                    && !chunk.data.is_empty()
+                    && self.state == (Role::Follower, Phase::Prepare) =>
+            {
+                self.snapshot_installer()
+                    .write_chunk(&metadata, offset, &chunk.data)
+                    .context("write snapshot chunk")?;
+                self.snapshot_transfer = Some((n, metadata, offset + chunk.data.len() as u64));
+                self.pull_snapshot()
+            }
+            // Duplicated chunks and chunks of an earlier transfer are ignored
+            _ => Ok(()),
+        }
+    }
+
+    /// Requests the next chunk of the snapshot that is being transferred. Once all of its bytes have been received,
+    /// the snapshot is installed and a new `Promise` is sent to the leader, which then synchronizes the rest of the log.
+    fn pull_snapshot(&mut self) -> Result<(), StorageError> {
+        let (n, metadata, offset) = match self.snapshot_transfer {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+        if offset < metadata.size {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: n.pid,
+                msg: PaxosMsg::SnapshotChunkRequest(SnapshotChunkRequest {
+                    n,
+                    id: metadata.id,
+                    offset,
+                }),
+            });
+            return Ok(());
+        }
+        self.snapshot_installer()
+            .install(&metadata)
+            .context("install snapshot")?;
+        self.internal_storage
+            .set_installed_snapshot(metadata.idx)
+            .context("compact installed snapshot")?;
+        self.snapshot_transfer = None;
+        let promise = Promise {
+            n,
+            n_accepted: self.internal_storage.get_accepted_round(),
+            decided_snapshot: None,
+            suffix: vec![],
+            decided_idx: self.get_decided_idx(),
+            accepted_idx: self.internal_storage.get_accepted_idx(),
+            stopsign: self.internal_storage.get_stopsign(),
+            membership: self.internal_storage.get_membership().map(Box::new),
+        };
+        self.cached_promise_message = Some(promise.clone());
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: n.pid,
+            msg: PaxosMsg::Promise(promise),
+        });
+        Ok(())
     }
 
     fn forward_pending_proposals(&mut self) {
@@ -190,59 +367,81 @@ where
         }
     }
 
-    pub(crate) fn handle_acceptdecide(&mut self, acc: AcceptDecide<T>) {
+    fn forward_pending_reads(&mut self) {
+        // Only our own reads are forwarded, reads of other servers must be retried by them.
+        let pid = self.pid;
+        let pending_reads = std::mem::take(&mut self.pending_reads);
+        for (_, id) in pending_reads
+            .into_iter()
+            .filter(|(origin, _)| *origin == pid)
+        {
+            self.forward_read(id);
+        }
+    }
+
+    pub(crate) fn handle_read_index_check(&mut self, check: ReadIndexCheck) {
+        if self.check_valid_ballot(check.n) {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: check.n.pid,
+                msg: PaxosMsg::ReadIndexAck(ReadIndexAck {
+                    n: check.n,
+                    round: check.round,
+                }),
+            });
+        }
+    }
+
+    pub(crate) fn handle_transfer_leadership(&mut self, t: TransferLeadership) {
+        if t.n == self.get_promise()
+            && self.state == (Role::Follower, Phase::Accept)
+            && self.membership.config.nodes.contains(&self.pid)
+        {
+            self.takeover_request = Some(t.n);
+        }
+    }
+
+    pub(crate) fn handle_read_index_reply(&mut self, reply: ReadIndexReply) {
+        self.confirmed_reads.push(ReadIndex {
+            id: reply.id,
+            read_idx: reply.read_idx,
+        });
+    }
+
+    pub(crate) fn handle_acceptdecide(&mut self, acc: AcceptDecide<T>) -> Result<(), StorageError> {
         if self.check_valid_ballot(acc.n)
             && self.state == (Role::Follower, Phase::Accept)
             && self.handle_sequence_num(acc.seq_num, acc.n.pid) == MessageStatus::Expected
         {
-            // handle decide
-            let old_decided_idx = self.get_decided_idx();
-            if acc.decided_idx > old_decided_idx {
-                self.internal_storage
-                    .set_decided_idx(acc.decided_idx)
-                    .expect("storage error while trying to write decided index");
-            }
-            // handle accept
-            let entries = acc.entries;
-            let result = self.accept_entries_follower(acc.n, entries);
-            self.internal_storage.rollback_and_panic_if_err(
-                &result,
-                vec![RollbackValue::DecidedIdx(old_decided_idx)],
-                "storage error while trying to write log entries.",
-            );
+            self.accept_entries_follower(acc.n, acc.entries, acc.decided_idx)?;
         }
+        Ok(())
     }
 
     #[cfg(feature = "unicache")]
-    pub(crate) fn handle_encoded_acceptdecide(&mut self, e: EncodedAcceptDecide<T>) {
+    pub(crate) fn handle_encoded_acceptdecide(
+        &mut self,
+        e: EncodedAcceptDecide<T>,
+    ) -> Result<(), StorageError> {
         if self.check_valid_ballot(e.n)
             && self.state == (Role::Follower, Phase::Accept)
             && self.handle_sequence_num(e.seq_num, e.n.pid) == MessageStatus::Expected
         {
-            // handle decide
-            let old_decided_idx = self.get_decided_idx();
-            if e.decided_idx > old_decided_idx {
-                self.internal_storage
-                    .set_decided_idx(e.decided_idx)
-                    .expect("storage error while trying to write decided index");
-            }
-            // handle accept
-            let encoded_entries = e.entries;
-            let result = self.accept_encoded_entries_follower(e.n, encoded_entries);
-            self.internal_storage.rollback_and_panic_if_err(
-                &result,
-                vec![RollbackValue::DecidedIdx(old_decided_idx)],
-                "storage error while trying to write log entries.",
-            );
+            let entries = self.internal_storage.decode_entries(e.entries);
+            self.accept_entries_follower(e.n, entries, e.decided_idx)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_accept_stopsign(&mut self, acc_ss: AcceptStopSign) {
+    pub(crate) fn handle_accept_stopsign(
+        &mut self,
+        acc_ss: AcceptStopSign,
+    ) -> Result<(), StorageError> {
         if self.check_valid_ballot(acc_ss.n)
             && self.state == (Role::Follower, Phase::Accept)
             && self.handle_sequence_num(acc_ss.seq_num, acc_ss.n.pid) == MessageStatus::Expected
         {
-            self.accept_stopsign(acc_ss.ss);
+            self.accept_stopsign(acc_ss.ss)?;
             let a = Accepted {
                 n: acc_ss.n,
                 accepted_idx: self.internal_storage.get_accepted_idx(),
@@ -253,40 +452,121 @@ where
                 msg: PaxosMsg::Accepted(a),
             });
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_decide(&mut self, dec: Decide) {
+    pub(crate) fn handle_accept_membership(
+        &mut self,
+        acc: AcceptMembership,
+    ) -> Result<(), StorageError> {
+        if self.check_valid_ballot(acc.n) && self.state == (Role::Follower, Phase::Accept) {
+            // The leader resends the membership until it is accepted, so a resent one is
+            // answered again in case the earlier reply was lost.
+            match self.handle_sequence_num(acc.seq_num, acc.n.pid) {
+                MessageStatus::Expected | MessageStatus::Outdated => {
+                    self.accept_membership_follower(acc.n, acc.membership)?
+                }
+                MessageStatus::DroppedPreceding => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn accept_membership_follower(
+        &mut self,
+        n: Ballot,
+        membership: Membership,
+    ) -> Result<(), StorageError> {
+        let version = membership.version;
+        if membership.is_newer_than(&self.membership) {
+            #[cfg(feature = "logging")]
+            info!(
+                self.logger,
+                "Accepted membership change {:?} with {:?}", membership.config.nodes, n
+            );
+            self.set_membership(membership)?;
+        }
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: n.pid,
+            msg: PaxosMsg::AcceptedMembership(AcceptedMembership { n, version }),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn handle_decide(&mut self, dec: Decide) -> Result<(), StorageError> {
         if self.check_valid_ballot(dec.n)
             && self.state.1 == Phase::Accept
             && self.handle_sequence_num(dec.seq_num, dec.n.pid) == MessageStatus::Expected
         {
             self.internal_storage
                 .set_decided_idx(dec.decided_idx)
-                .expect("storage error while trying to write decided index");
+                .context("write decided index")?;
         }
+        Ok(())
     }
 
-    fn accept_entries_follower(&mut self, n: Ballot, entries: Vec<T>) -> StorageResult<()> {
-        let accepted_res = self
-            .internal_storage
-            .append_entries_and_get_accepted_idx(entries)?;
-        if let Some(accepted_idx) = accepted_res {
-            self.handle_flushed_accepted(n, accepted_idx);
+    // Writes the entries if the batch is full together with the decided index as one write set.
+    // With `async_flush`, the entries are only accepted once the storage has flushed them.
+    fn accept_entries_follower(
+        &mut self,
+        n: Ballot,
+        entries: Vec<T>,
+        decided_idx: u64,
+    ) -> Result<(), StorageError> {
+        let old_decided_idx = self.get_decided_idx();
+        let durable_idx = self.internal_storage.get_accepted_idx();
+        let mut ops = vec![];
+        if decided_idx > old_decided_idx {
+            ops.push(StorageOp::SetDecidedIdx(decided_idx));
+        }
+        let flushed_entries = self.internal_storage.batch_entries(entries);
+        let append = flushed_entries.is_some();
+        if let Some(entries) = flushed_entries {
+            ops.push(StorageOp::AppendEntries(entries));
+        }
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let result = match self.async_flush && append {
+            true => self.internal_storage.write_atomically_async(ops),
+            false => self.internal_storage.write_atomically(ops),
+        };
+        let accepted_idx = self.internal_storage.rollback_if_err(
+            result,
+            vec![RollbackValue::DecidedIdx(old_decided_idx)],
+            "write log entries",
+        )?;
+        if append {
+            if self.async_flush {
+                let reported_idx = match self.pending_flush {
+                    Some((round, idx)) if round == n => idx,
+                    _ => durable_idx,
+                };
+                self.pending_flush = Some((n, reported_idx));
+            } else {
+                self.handle_flushed_accepted(n, accepted_idx);
+            }
         }
         Ok(())
     }
 
-    #[cfg(feature = "unicache")]
-    fn accept_encoded_entries_follower(
-        &mut self,
-        n: Ballot,
-        encoded_entries: Vec<T::EncodeResult>,
-    ) -> StorageResult<()> {
-        let accepted_res = self
-            .internal_storage
-            .append_encoded_entries_and_get_accepted_idx(encoded_entries)?;
-        if let Some(accepted_idx) = accepted_res {
-            self.handle_flushed_accepted(n, accepted_idx);
+    /// Sends an `Accepted` for the entries that the storage has flushed since the last poll.
+    pub(crate) fn poll_flushed_accepted(&mut self) -> Result<(), StorageError> {
+        if let Some((n, reported_idx)) = self.pending_flush.take() {
+            if n != self.get_promise() || self.state != (Role::Follower, Phase::Accept) {
+                return Ok(());
+            }
+            let flushed_idx = self
+                .internal_storage
+                .poll_flushed()
+                .context("poll flushed entries")?;
+            if flushed_idx > reported_idx {
+                self.handle_flushed_accepted(n, flushed_idx);
+            }
+            if flushed_idx < self.internal_storage.get_accepted_idx() {
+                self.pending_flush = Some((n, flushed_idx.max(reported_idx)));
+            }
         }
         Ok(())
     }
@@ -1,11 +1,20 @@
 use super::super::{
     ballot_leader_election::Ballot,
-    util::{LeaderState, PromiseData, PromiseMetaData},
+    util::{
+        LeaderState, PendingRead, PromiseData, PromiseMetaData, ProposalOutcome, ProposalStatus,
+        ReadId, ReadIndex, SyncProgress,
+    },
+};
+use crate::{
+    errors::{StorageContext, StorageError},
+    storage::{RollbackValue, Snapshot, SnapshotMetadata, SnapshotType, StorageOp},
 };
-use crate::storage::{RollbackValue, Snapshot, SnapshotType};
 
 use super::*;
 
+/// The number of entries that are read from the storage at once when a chunk is only limited by its size.
+const SYNC_READ_BATCH_LEN: usize = 1024;
+
 impl<T, B> SequencePaxos<T, B>
 where
     T: Entry,
@@ -20,56 +29,88 @@ where
         #[cfg(feature = "logging")]
         debug!(self.logger, "Newly elected leader: {:?}", n);
         if self.pending_reconfiguration() {
-            self.pending_proposals.clear();
+            let proposals = std::mem::take(&mut self.pending_proposals);
+            self.drop_proposals(proposals);
         }
+        self.leadership_transfer = None;
         if self.pid == n.pid {
-            self.leader_state =
-                LeaderState::with(n, self.leader_state.max_pid, self.leader_state.quorum);
-            self.internal_storage
-                .flush_batch()
-                .expect("storage error while trying to flush batch");
-            /* insert my promise */
-            let na = self.internal_storage.get_accepted_round();
-            let decided_idx = self.get_decided_idx();
-            let accepted_idx = self.internal_storage.get_accepted_idx();
-            let my_promise = Promise {
-                n,
-                n_accepted: na,
-                decided_snapshot: None,
-                decided_idx,
-                accepted_idx,
-                suffix: vec![],
-                stopsign: self.internal_storage.get_stopsign(),
-            };
-            self.leader_state.set_promise(my_promise, self.pid, true);
-            /* initialise longest chosen sequence and update state */
-            self.state = (Role::Leader, Phase::Prepare);
-            let prep = Prepare {
-                n,
-                decided_idx,
-                n_accepted: na,
-                accepted_idx,
-            };
-            self.internal_storage
-                .set_promise(n)
-                .expect("storage error while trying to write promise");
-            /* send prepare */
-            for pid in &self.peers {
-                self.outgoing.push(PaxosMessage {
-                    from: self.pid,
-                    to: *pid,
-                    msg: PaxosMsg::Prepare(prep),
-                });
-            }
+            let result = self.become_leader(n);
+            self.handle_storage_error(result);
         } else {
             self.become_follower();
         }
     }
 
+    fn become_leader(&mut self, n: Ballot) -> Result<(), StorageError> {
+        self.drop_unresolved_proposals();
+        self.client_sessions = None;
+        self.leader_state = LeaderState::with(
+            n,
+            self.leader_state.max_pid,
+            self.leader_state.quorum,
+            std::mem::take(&mut self.leader_state.voters),
+            std::mem::take(&mut self.leader_state.learners),
+        );
+        let mut ops = vec![];
+        let batched_entries = self.internal_storage.take_batched_entries();
+        if !batched_entries.is_empty() {
+            ops.push(StorageOp::AppendEntries(batched_entries));
+        }
+        ops.push(StorageOp::SetPromise(n));
+        self.internal_storage
+            .write_atomically(ops)
+            .context("write promise")?;
+        /* insert my promise */
+        let na = self.internal_storage.get_accepted_round();
+        let decided_idx = self.get_decided_idx();
+        let accepted_idx = self.internal_storage.get_accepted_idx();
+        let my_promise = Promise {
+            n,
+            n_accepted: na,
+            decided_snapshot: None,
+            decided_idx,
+            accepted_idx,
+            suffix: vec![],
+            stopsign: self.internal_storage.get_stopsign(),
+            membership: self.internal_storage.get_membership().map(Box::new),
+        };
+        self.leader_state.set_promise(my_promise, self.pid, true);
+        /* initialise longest chosen sequence and update state */
+        self.state = (Role::Leader, Phase::Prepare);
+        let prep = Prepare {
+            n,
+            decided_idx,
+            n_accepted: na,
+            accepted_idx,
+        };
+        /* send prepare */
+        for pid in &self.peers {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: *pid,
+                msg: PaxosMsg::Prepare(prep),
+            });
+        }
+        Ok(())
+    }
+
     pub(crate) fn become_follower(&mut self) {
         self.state.0 = Role::Follower;
     }
 
+    /// Stops being the leader without a new leader being elected. Proposals are held back until a leader is elected.
+    pub(crate) fn step_down(&mut self) {
+        if self.state.0 == Role::Leader {
+            #[cfg(feature = "logging")]
+            info!(
+                self.logger,
+                "Stepping down as leader: {:?}", self.leader_state.n_leader
+            );
+            self.leadership_transfer = None;
+            self.state = (Role::Follower, Phase::Recover);
+        }
+    }
+
     pub(crate) fn handle_preparereq(&mut self, prepreq: PrepareReq, from: NodeId) {
         #[cfg(feature = "logging")]
         debug!(self.logger, "Incoming message PrepareReq from {}", from);
@@ -83,17 +124,26 @@ where
         }
     }
 
-    pub(crate) fn handle_forwarded_proposal(&mut self, mut entries: Vec<T>) {
-        if !self.pending_reconfiguration() {
+    pub(crate) fn handle_forwarded_proposal(
+        &mut self,
+        proposals: Vec<Proposal<T>>,
+    ) -> Result<(), StorageError> {
+        if self.pending_reconfiguration() {
+            self.drop_proposals(proposals);
+        } else {
             match self.state {
-                (Role::Leader, Phase::Prepare) => self.pending_proposals.append(&mut entries),
-                (Role::Leader, Phase::Accept) => self.accept_entries_leader(entries),
-                _ => self.forward_proposals(entries),
+                (Role::Leader, Phase::Prepare) => self.buffer_proposals(proposals),
+                (Role::Leader, Phase::Accept) if self.leadership_transfer.is_some() => {
+                    self.buffer_proposals(proposals)
+                }
+                (Role::Leader, Phase::Accept) => self.accept_entries_leader(proposals)?,
+                _ => self.forward_proposals(proposals),
             }
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_forwarded_stopsign(&mut self, ss: StopSign) {
+    pub(crate) fn handle_forwarded_stopsign(&mut self, ss: StopSign) -> Result<(), StorageError> {
         if !self.pending_reconfiguration() {
             match self.state {
                 (Role::Leader, Phase::Prepare) => {
@@ -103,8 +153,8 @@ where
                 }
                 (Role::Leader, Phase::Accept) => {
                     if self.pending_stopsign.is_none() {
-                        self.accept_stopsign(ss.clone());
-                        for pid in self.leader_state.get_promised_followers() {
+                        self.accept_stopsign(ss.clone())?;
+                        for pid in self.leader_state.get_synced_followers() {
                             self.send_accept_stopsign(pid, ss.clone(), false);
                         }
                     }
@@ -112,6 +162,191 @@ where
                 _ => self.forward_stopsign(ss),
             }
         }
+        Ok(())
+    }
+
+    pub(crate) fn handle_forwarded_membership(
+        &mut self,
+        config: ClusterConfig,
+    ) -> Result<(), StorageError> {
+        match self.state {
+            (Role::Leader, Phase::Accept) => {
+                // Forwarded changes have only been checked against the follower's configuration
+                let valid = config.nodes.contains(&self.pid)
+                    && self
+                        .membership
+                        .config
+                        .validate_membership_change(&config)
+                        .is_ok();
+                if valid && !self.leader_state.has_pending_membership() {
+                    self.propose_membership(config)?;
+                }
+            }
+            (Role::Leader, Phase::Prepare) => {}
+            _ => self.forward_membership(config),
+        }
+        Ok(())
+    }
+
+    /// Starts using `config` as the configuration and sends it to the followers.
+    pub(crate) fn propose_membership(&mut self, config: ClusterConfig) -> Result<(), StorageError> {
+        let membership = Membership {
+            config,
+            version: self.membership.version + 1,
+            n: self.leader_state.n_leader,
+        };
+        // Removed followers are also informed about the change, until they have accepted it
+        let followers = self.leader_state.get_promised_followers();
+        self.accept_membership_leader(membership.clone())?;
+        self.leader_state.add_removed_followers(followers.clone());
+        for pid in followers {
+            self.send_accept_membership(pid, membership.clone(), false);
+        }
+        Ok(())
+    }
+
+    fn accept_membership_leader(&mut self, membership: Membership) -> Result<(), StorageError> {
+        let old_peers = self.peers.clone();
+        let version = membership.version;
+        self.set_membership(membership)?;
+        self.leader_state.new_membership_round(version);
+        // Added servers have to be synchronized before they can accept the change
+        for pid in self.peers.clone() {
+            if !old_peers.contains(&pid) {
+                self.send_prepare(pid);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn send_accept_membership(
+        &mut self,
+        to: NodeId,
+        membership: Membership,
+        resend: bool,
+    ) {
+        let seq_num = match resend {
+            true => self.leader_state.get_seq_num(to),
+            false => self.leader_state.next_seq_num(to),
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to,
+            msg: PaxosMsg::AcceptMembership(AcceptMembership {
+                n: self.leader_state.n_leader,
+                seq_num,
+                membership,
+            }),
+        });
+    }
+
+    pub(crate) fn handle_accepted_membership(&mut self, acc: AcceptedMembership, from: NodeId) {
+        if acc.n == self.leader_state.n_leader && acc.version == self.membership.version {
+            self.leader_state.ack_removed_follower(from);
+        }
+        if acc.n == self.leader_state.n_leader
+            && self.state == (Role::Leader, Phase::Accept)
+            && self.leader_state.ack_membership(acc.version, from)
+        {
+            #[cfg(feature = "logging")]
+            info!(
+                self.logger,
+                "Membership change {:?} accepted by a quorum", self.membership.config.nodes
+            );
+        }
+    }
+
+    /// Tells the target of the leadership transfer to take over if it has accepted all entries.
+    pub(crate) fn try_transfer_leadership(&mut self) {
+        if let Some((to, _)) = self.leadership_transfer {
+            if self.leader_state.get_accepted_idx(to) >= self.internal_storage.get_accepted_idx() {
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to,
+                    msg: PaxosMsg::TransferLeadership(TransferLeadership {
+                        n: self.leader_state.n_leader,
+                    }),
+                });
+            }
+        }
+    }
+
+    /// Gives up the leadership transfer and proposes the entries that were held back.
+    pub(crate) fn cancel_leadership_transfer(&mut self) -> Result<(), StorageError> {
+        #[cfg(feature = "logging")]
+        warn!(self.logger, "Leadership transfer did not complete in time");
+        self.leadership_transfer = None;
+        let proposals = std::mem::take(&mut self.pending_proposals);
+        if !proposals.is_empty() {
+            self.accept_entries_leader(proposals)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_forwarded_read(&mut self, id: ReadId, from: NodeId) {
+        // Reads are not forwarded again as the reply must be sent to the origin. The origin has to
+        // retry the read if it is not confirmed.
+        match self.state {
+            (Role::Leader, Phase::Prepare) => self.pending_reads.push((from, id)),
+            (Role::Leader, Phase::Accept) => self.send_read_index_check(from, id),
+            _ => {}
+        }
+    }
+
+    /// Starts a read round for the read request `id` of `origin`. The read is confirmed once a
+    /// quorum has acknowledged that they are still promised to this leader.
+    pub(crate) fn send_read_index_check(&mut self, origin: NodeId, id: ReadId) {
+        // All entries that could have been decided by a previous leader are in my log
+        let read_idx = self.internal_storage.get_accepted_idx();
+        if self.leader_lease {
+            // No other leader can be elected while the lease is valid.
+            self.confirm_read(origin, id, read_idx);
+            return;
+        }
+        let round = self.leader_state.new_read_round(origin, id, read_idx);
+        let check = ReadIndexCheck {
+            n: self.leader_state.n_leader,
+            round,
+        };
+        for pid in self.leader_state.get_promised_followers() {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: pid,
+                msg: PaxosMsg::ReadIndexCheck(check),
+            });
+        }
+    }
+
+    fn send_pending_read_index_checks(&mut self) {
+        for (origin, id) in std::mem::take(&mut self.pending_reads) {
+            self.send_read_index_check(origin, id);
+        }
+    }
+
+    pub(crate) fn handle_read_index_ack(&mut self, ack: ReadIndexAck, from: NodeId) {
+        if ack.n == self.leader_state.n_leader && self.state == (Role::Leader, Phase::Accept) {
+            if let Some(PendingRead {
+                origin,
+                id,
+                read_idx,
+                ..
+            }) = self.leader_state.ack_read(ack.round, from)
+            {
+                self.confirm_read(origin, id, read_idx);
+            }
+        }
+    }
+
+    fn confirm_read(&mut self, origin: NodeId, id: ReadId, read_idx: u64) {
+        if origin == self.pid {
+            self.confirmed_reads.push(ReadIndex { id, read_idx });
+        } else {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: origin,
+                msg: PaxosMsg::ReadIndexReply(ReadIndexReply { id, read_idx }),
+            });
+        }
     }
 
     pub(crate) fn send_prepare(&mut self, to: NodeId) {
@@ -162,27 +397,98 @@ where
             .set_batch_accept_meta(to, Some(self.outgoing.len() - 1));
     }
 
-    pub(crate) fn accept_entry(&mut self, entry: T) {
-        let accepted_metadata = self
-            .internal_storage
-            .append_entry_with_batching(entry)
-            .expect("storage error while trying to write an entry");
-        if let Some(am) = accepted_metadata {
-            self.send_acceptdecide(am);
+    /// Removes the proposals that are duplicates of entries of the same client session in the log and reports them to the
+    /// servers that made them.
+    fn filter_duplicate_proposals(
+        &mut self,
+        proposals: Vec<Proposal<T>>,
+    ) -> Result<Vec<Proposal<T>>, StorageError> {
+        if self.client_sessions.is_none() {
+            if proposals.iter().all(|p| p.entry.client_session().is_none()) {
+                return Ok(proposals);
+            }
+            let sessions = self
+                .internal_storage
+                .get_client_sessions(self.internal_storage.get_accepted_idx())
+                .context("read client sessions")?;
+            self.client_sessions = Some(sessions);
+        }
+        let sessions = self.client_sessions.as_mut().unwrap();
+        let (proposals, duplicates): (Vec<_>, Vec<_>) = proposals
+            .into_iter()
+            .partition(|p| sessions.update(&p.entry));
+        let outcomes = duplicates
+            .into_iter()
+            .map(|p| {
+                let outcome = ProposalOutcome {
+                    id: p.id,
+                    status: ProposalStatus::Duplicate,
+                };
+                (p.origin, outcome)
+            })
+            .collect();
+        self.report_proposal_outcomes(outcomes);
+        Ok(proposals)
+    }
+
+    /// Tracks `proposals` as appended from the next log index and returns the entries that are not duplicates.
+    fn track_proposals(&mut self, proposals: Vec<Proposal<T>>) -> Result<Vec<T>, StorageError> {
+        let proposals = self.filter_duplicate_proposals(proposals)?;
+        let next_idx = self.internal_storage.get_next_idx();
+        Ok(proposals
+            .into_iter()
+            .zip(next_idx..)
+            .map(|(p, idx)| {
+                self.leader_state.add_accepted_proposal(idx, p.origin, p.id);
+                p.entry
+            })
+            .collect())
+    }
+
+    /// Reports the tracked proposals that are decided now to the servers that made them.
+    fn report_decided_proposals(&mut self) {
+        let decided_idx = self.internal_storage.get_decided_idx();
+        let outcomes = self
+            .leader_state
+            .take_decided_proposals(decided_idx)
+            .into_iter()
+            .map(|(idx, origin, id)| {
+                let outcome = ProposalOutcome {
+                    id,
+                    status: ProposalStatus::Decided(idx),
+                };
+                (origin, outcome)
+            })
+            .collect();
+        self.report_proposal_outcomes(outcomes);
+    }
+
+    pub(crate) fn accept_entry(&mut self, proposal: Proposal<T>) -> Result<(), StorageError> {
+        if let Some(entry) = self.track_proposals(vec![proposal])?.pop() {
+            let accepted_metadata = self
+                .internal_storage
+                .append_entry_with_batching(entry)
+                .context("write an entry")?;
+            if let Some(am) = accepted_metadata {
+                self.send_acceptdecide(am);
+            }
         }
+        Ok(())
     }
 
-    fn accept_entries_leader(&mut self, entries: Vec<T>) {
+    fn accept_entries_leader(&mut self, proposals: Vec<Proposal<T>>) -> Result<(), StorageError> {
+        let entries = self.track_proposals(proposals)?;
         let accepted_metadata = self
             .internal_storage
             .append_entries_with_batching(entries)
-            .expect("storage error while trying to write entries");
+            .context("write entries")?;
         if let Some(am) = accepted_metadata {
             self.send_acceptdecide(am);
         }
+        Ok(())
     }
 
-    fn send_accsync(&mut self, to: NodeId) {
+    fn send_accsync(&mut self, to: NodeId) -> Result<(), StorageError> {
         let my_decided_idx = self.get_decided_idx();
         let current_n = self.leader_state.n_leader;
         let PromiseMetaData {
@@ -208,37 +514,127 @@ where
         } else {
             followers_decided_idx
         };
-        let (delta_snapshot, suffix, sync_idx) =
-            if T::Snapshot::use_snapshots() && followers_valid_entries_idx < my_decided_idx {
-                // Synchronize by sending a snapshot from the follower's decided index up to
-                // leader's decided index and any suffix.
-                // Note: we snapshot from follower's decided and not follower's valid because
-                // snapshots currently can't handle merging onto accepted entries.
-                let (delta_snapshot, compacted_idx) = self
-                    .internal_storage
-                    .create_diff_snapshot(followers_decided_idx)
-                    .expect("storage error while trying to read diff snapshot");
-                let suffix = self
+        // The decided entries are valid in any case, e.g., after the follower installed a transferred snapshot
+        let followers_valid_entries_idx = followers_valid_entries_idx.max(followers_decided_idx);
+        if self.offer_snapshot(to, followers_valid_entries_idx)? {
+            return Ok(());
+        }
+        let chunked = self.max_sync_entries.is_some() || self.max_sync_bytes.is_some();
+        // A follower that is synchronized in chunks or with transferred snapshots is sent the
+        // entries instead of a snapshot as long as the leader has not compacted them.
+        let use_snapshot = T::Snapshot::use_snapshots()
+            && followers_valid_entries_idx < my_decided_idx
+            && !((chunked || self.has_snapshot_provider())
+                && followers_valid_entries_idx >= self.internal_storage.get_compacted_idx());
+        // The entries are sent instead of the snapshot if they are still in the log and smaller than the snapshot
+        let entries_in_log =
+            followers_valid_entries_idx >= self.internal_storage.get_compacted_idx();
+        // The snapshot covers the decided entries from the follower's decided index, or all of them if some are compacted
+        let snapshot_from_idx =
+            followers_decided_idx.max(self.internal_storage.get_compacted_idx());
+        let snapshot_to_idx = self.internal_storage.get_decided_idx_without_stopsign();
+        let snapshot_sync = if !use_snapshot {
+            None
+        } else if entries_in_log
+            && self.is_snapshot_larger_than_entries(
+                self.get_snapshot_size_estimate(snapshot_from_idx, snapshot_to_idx),
+                followers_valid_entries_idx,
+                snapshot_to_idx,
+            )?
+        {
+            // The same snapshot was already larger than the entries, so it is not created again
+            self.sync_metrics.larger_snapshots += 1;
+            None
+        } else {
+            // Note: we snapshot from follower's decided and not follower's valid because
+            // snapshots currently can't handle merging onto accepted entries.
+            let (delta_snapshot, compacted_idx) = self
+                .internal_storage
+                .create_diff_snapshot(followers_decided_idx)
+                .context("read diff snapshot")?;
+            let size_hint = match &delta_snapshot {
+                Some(SnapshotType::Complete(s)) | Some(SnapshotType::Delta(s)) => s.size_hint(),
+                None => None,
+            };
+            self.snapshot_size_estimate =
+                size_hint.map(|size| (snapshot_from_idx, snapshot_to_idx, size));
+            if entries_in_log
+                && self.is_snapshot_larger_than_entries(
+                    size_hint,
+                    followers_valid_entries_idx,
+                    compacted_idx,
+                )?
+            {
+                self.sync_metrics.larger_snapshots += 1;
+                None
+            } else {
+                Some((delta_snapshot, compacted_idx))
+            }
+        };
+        if snapshot_sync.is_none() && !entries_in_log {
+            self.refuse_sync(to);
+            return Ok(());
+        }
+        let (delta_snapshot, suffix, sync_idx) = if let Some((delta_snapshot, compacted_idx)) =
+            snapshot_sync
+        {
+            // Synchronize by sending a snapshot from the follower's decided index up to
+            // leader's decided index and any suffix.
+            self.sync_metrics.snapshot_syncs += 1;
+            let suffix = match chunked {
+                true => self.read_sync_chunk(compacted_idx, self.internal_storage.get_log_len())?,
+                false => self
                     .internal_storage
                     .get_suffix(my_decided_idx)
-                    .expect("storage error while trying to read log suffix");
-                (delta_snapshot, suffix, compacted_idx)
-            } else {
-                let sfx = self
+                    .context("read log suffix")?,
+            };
+            (delta_snapshot, suffix, compacted_idx)
+        } else {
+            self.sync_metrics.log_syncs += 1;
+            let sfx = match chunked {
+                true => self.read_sync_chunk(
+                    followers_valid_entries_idx,
+                    self.internal_storage.get_log_len(),
+                )?,
+                false => self
                     .internal_storage
                     .get_suffix(followers_valid_entries_idx)
-                    .expect("storage error while trying to read log suffix");
-                (None, sfx, followers_valid_entries_idx)
+                    .context("read log suffix")?,
             };
+            (None, sfx, followers_valid_entries_idx)
+        };
         self.leader_state.increment_seq_num_session(to);
+        #[cfg(feature = "batch_accept")]
+        {
+            self.leader_state.set_batch_accept_meta(to, None);
+        }
+        let seq_num = self.leader_state.next_seq_num(to);
+        let to_idx = sync_idx + suffix.len() as u64;
+        // The rest of the log is sent in chunks once the follower has accepted this one
+        let last_chunk = to_idx >= self.internal_storage.get_log_len();
+        let progress = SyncProgress {
+            from_idx: sync_idx,
+            to_idx,
+            seq_num,
+            stalled: false,
+        };
+        self.leader_state
+            .set_sync_progress(to, (!last_chunk).then_some(progress));
         let acc_sync = AcceptSync {
             n: current_n,
-            seq_num: self.leader_state.next_seq_num(to),
+            seq_num,
             decided_snapshot: delta_snapshot,
             suffix,
             sync_idx,
-            decided_idx: my_decided_idx,
-            stopsign: self.internal_storage.get_stopsign(),
+            decided_idx: match last_chunk {
+                true => my_decided_idx,
+                false => my_decided_idx.min(to_idx),
+            },
+            stopsign: match last_chunk {
+                true => self.internal_storage.get_stopsign(),
+                false => None,
+            },
+            membership: self.internal_storage.get_membership().map(Box::new),
             #[cfg(feature = "unicache")]
             unicache: self.internal_storage.get_unicache(),
         };
@@ -248,13 +644,234 @@ where
             msg: PaxosMsg::AcceptSync(acc_sync),
         };
         self.outgoing.push(msg);
+        Ok(())
+    }
+
+    /// Returns the size hint of the last snapshot created to synchronize a follower if it was created from the decided
+    /// entries from `from_idx` to `to_idx`, i.e., if the same snapshot would be created again.
+    fn get_snapshot_size_estimate(&self, from_idx: u64, to_idx: u64) -> Option<usize> {
+        match self.snapshot_size_estimate {
+            Some((from, to, size)) if from == from_idx && to == to_idx => Some(size),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the size hint of a snapshot is larger than the size hints of the entries from `from_idx` to
+    /// `to_idx`. Without a size hint, the snapshot is never larger.
+    fn is_snapshot_larger_than_entries(
+        &self,
+        snapshot_size: Option<usize>,
+        from_idx: u64,
+        to_idx: u64,
+    ) -> Result<bool, StorageError> {
+        let snapshot_size = match snapshot_size {
+            Some(size) => size,
+            None => return Ok(false),
+        };
+        let mut entries_size = 0usize;
+        let mut idx = from_idx;
+        // Stops reading as soon as the entries are at least as large as the snapshot
+        while idx < to_idx && entries_size < snapshot_size {
+            let batch_end = (idx + SYNC_READ_BATCH_LEN as u64).min(to_idx);
+            let batch = self
+                .internal_storage
+                .get_entries(idx, batch_end)
+                .context("read log entries")?;
+            entries_size = batch
+                .iter()
+                .fold(entries_size, |n, e| n.saturating_add(e.size_hint()));
+            idx = batch_end;
+        }
+        Ok(snapshot_size > entries_size)
+    }
+
+    /// Sends the next chunk of the log from `from_idx` to a follower that is being synchronized. A chunk that is resent
+    /// has the same sequence number and does not extend past the end of the original chunk.
+    pub(crate) fn send_sync_chunk(
+        &mut self,
+        to: NodeId,
+        from_idx: u64,
+        resend: Option<SyncProgress>,
+    ) -> Result<(), StorageError> {
+        if self.offer_snapshot(to, from_idx)? {
+            return Ok(());
+        }
+        let my_decided_idx = self.get_decided_idx();
+        let log_len = self.internal_storage.get_log_len();
+        let (decided_snapshot, sync_idx) = if from_idx >= self.internal_storage.get_compacted_idx()
+        {
+            (None, from_idx)
+        } else if T::Snapshot::use_snapshots() {
+            // The entries have been compacted since the synchronization started
+            self.internal_storage
+                .create_diff_snapshot(from_idx)
+                .context("read diff snapshot")?
+        } else {
+            self.refuse_sync(to);
+            return Ok(());
+        };
+        let (entries, seq_num) = match resend {
+            Some(progress) => (
+                self.read_sync_chunk(sync_idx, progress.to_idx)?,
+                progress.seq_num,
+            ),
+            None => (
+                self.read_sync_chunk(sync_idx, log_len)?,
+                self.leader_state.next_seq_num(to),
+            ),
+        };
+        let to_idx = sync_idx + entries.len() as u64;
+        let last_chunk = resend.is_none() && to_idx >= log_len;
+        let progress = SyncProgress {
+            from_idx,
+            to_idx,
+            seq_num,
+            stalled: false,
+        };
+        self.leader_state
+            .set_sync_progress(to, (!last_chunk).then_some(progress));
+        let chunk = AcceptSyncChunk {
+            n: self.leader_state.n_leader,
+            seq_num,
+            decided_snapshot,
+            entries,
+            sync_idx,
+            decided_idx: match last_chunk {
+                true => my_decided_idx,
+                false => my_decided_idx.min(to_idx),
+            },
+            stopsign: match last_chunk {
+                true => self.internal_storage.get_stopsign(),
+                false => None,
+            },
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to,
+            msg: PaxosMsg::AcceptSyncChunk(chunk),
+        });
+        Ok(())
+    }
+
+    /// Offers the latest snapshot of the snapshot provider to a follower that needs the entries from `from_idx` if they
+    /// are compacted. The follower is not synchronized until it has installed the snapshot and sent a new `Promise`.
+    /// Returns whether a snapshot was offered.
+    fn offer_snapshot(&mut self, to: NodeId, from_idx: u64) -> Result<bool, StorageError> {
+        if from_idx >= self.internal_storage.get_compacted_idx() {
+            return Ok(false);
+        }
+        match self.get_offered_snapshot()? {
+            Some(metadata) => {
+                self.leader_state.reset_promise(to);
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to,
+                    msg: PaxosMsg::SnapshotOffer(SnapshotOffer {
+                        n: self.leader_state.n_leader,
+                        metadata,
+                    }),
+                });
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Gives up synchronizing `to` because the entries it needs have been compacted and there is no snapshot to send
+    /// instead. The follower is prepared again later, e.g., once a snapshot is available.
+    fn refuse_sync(&mut self, to: NodeId) {
+        #[cfg(feature = "logging")]
+        warn!(
+            self.logger,
+            "Cannot synchronize {} as the log is compacted up to {} without a snapshot",
+            to,
+            self.internal_storage.get_compacted_idx()
+        );
+        self.leader_state.reset_promise(to);
+    }
+
+    /// Returns the latest snapshot of the snapshot provider if the log of this leader continues from its index.
+    fn get_offered_snapshot(&mut self) -> Result<Option<SnapshotMetadata>, StorageError> {
+        let compacted_idx = self.internal_storage.get_compacted_idx();
+        let decided_idx = self.internal_storage.get_decided_idx();
+        match self.snapshot_provider() {
+            Some(provider) => Ok(provider
+                .latest_snapshot()
+                .context("read latest snapshot")?
+                .filter(|m| m.idx >= compacted_idx && m.idx <= decided_idx)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn handle_snapshot_chunk_request(
+        &mut self,
+        req: SnapshotChunkRequest,
+        from: NodeId,
+    ) -> Result<(), StorageError> {
+        if req.n != self.leader_state.n_leader || self.state != (Role::Leader, Phase::Accept) {
+            return Ok(());
+        }
+        let msg = match self.get_offered_snapshot()? {
+            Some(metadata) if metadata.id == req.id => {
+                let data = self
+                    .snapshot_provider()
+                    .unwrap()
+                    .read_chunk(&metadata, req.offset)
+                    .context("read snapshot chunk")?;
+                PaxosMsg::SnapshotChunk(SnapshotChunk {
+                    n: req.n,
+                    id: req.id,
+                    offset: req.offset,
+                    data,
+                })
+            }
+            // The requested snapshot has been replaced by a newer one
+            Some(metadata) => PaxosMsg::SnapshotOffer(SnapshotOffer { n: req.n, metadata }),
+            // The follower is synchronized with the log once it sends its `Promise` again
+            None => return Ok(()),
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            msg,
+        });
+        Ok(())
+    }
+
+    /// Reads the entries from `from_idx` up to `to_idx` that fit into one chunk according to `max_sync_entries` and
+    /// `max_sync_bytes`. A chunk has at least one entry if there are any.
+    fn read_sync_chunk(&self, from_idx: u64, to_idx: u64) -> Result<Vec<T>, StorageError> {
+        let max_entries = self.max_sync_entries.unwrap_or(usize::MAX);
+        let max_bytes = self.max_sync_bytes.unwrap_or(usize::MAX);
+        let mut entries = vec![];
+        let mut num_bytes = 0usize;
+        let mut idx = from_idx;
+        while idx < to_idx && entries.len() < max_entries {
+            let batch_len = (max_entries - entries.len()).min(SYNC_READ_BATCH_LEN) as u64;
+            let batch_end = (idx + batch_len).min(to_idx);
+            let batch = self
+                .internal_storage
+                .get_entries(idx, batch_end)
+                .context("read log entries")?;
+            for entry in batch {
+                let size = entry.size_hint();
+                if !entries.is_empty() && num_bytes.saturating_add(size) > max_bytes {
+                    return Ok(entries);
+                }
+                num_bytes = num_bytes.saturating_add(size);
+                entries.push(entry);
+            }
+            idx = batch_end;
+        }
+        Ok(entries)
     }
 
     pub(crate) fn send_acceptdecide(&mut self, am: AcceptedMetaData<T>) {
         self.leader_state
             .set_accepted_idx(self.pid, am.accepted_idx);
         let decided_idx = self.internal_storage.get_decided_idx();
-        for pid in self.leader_state.get_promised_followers() {
+        // Followers that are being synchronized get the new entries with the last chunk
+        for pid in self.leader_state.get_synced_followers() {
             if cfg!(feature = "batch_accept") {
                 #[cfg(feature = "batch_accept")]
                 match self.leader_state.get_batch_accept_meta(pid) {
@@ -339,29 +956,32 @@ where
         });
     }
 
-    fn adopt_pending_stopsign(&mut self) {
+    fn adopt_pending_stopsign(&mut self) -> Result<(), StorageError> {
         if let Some(ss) = self.pending_stopsign.take() {
-            self.accept_stopsign(ss);
+            self.accept_stopsign(ss)?;
         }
+        Ok(())
     }
 
-    fn append_pending_proposals(&mut self) {
+    fn append_pending_proposals(&mut self) -> Result<(), StorageError> {
         if !self.pending_proposals.is_empty() {
-            let new_entries = std::mem::take(&mut self.pending_proposals);
+            let proposals = std::mem::take(&mut self.pending_proposals);
+            let new_entries = self.track_proposals(proposals)?;
             // append new proposals in my sequence
             let append_res = self
                 .internal_storage
                 .append_entries_and_get_accepted_idx(new_entries)
-                .expect("storage error while trying to write log entries");
+                .context("write log entries")?;
             if let Some(accepted_idx) = append_res {
                 self.leader_state.set_accepted_idx(self.pid, accepted_idx);
             }
         }
+        Ok(())
     }
 
     // Correctness: This function performs multiple operations that cannot be rolled
     // back, so instead it relies on writing in a "safe" order for correctness.
-    fn handle_majority_promises(&mut self) {
+    fn handle_majority_promises(&mut self) -> Result<(), StorageError> {
         self.state = (Role::Leader, Phase::Accept);
         let max_stopsign = self.leader_state.take_max_promise_stopsign();
         let max_promise = self.leader_state.take_max_promise();
@@ -369,15 +989,10 @@ where
         let decided_idx = self.leader_state.get_max_decided_idx().unwrap();
         let old_decided_idx = self.internal_storage.get_decided_idx();
         let old_accepted_round = self.internal_storage.get_accepted_round();
-        self.internal_storage
-            .set_accepted_round(self.leader_state.n_leader)
-            .expect("storage error while trying to write accepted round");
-        let result = self.internal_storage.set_decided_idx(decided_idx);
-        self.internal_storage.rollback_and_panic_if_err(
-            &result,
-            vec![RollbackValue::AcceptedRound(old_accepted_round)],
-            "storage error while trying to write decided index",
-        );
+        let mut ops = vec![
+            StorageOp::SetAcceptedRound(self.leader_state.n_leader),
+            StorageOp::SetDecidedIdx(decided_idx),
+        ];
         match max_promise {
             Some(PromiseData {
                 decided_snapshot,
@@ -385,18 +1000,34 @@ where
             }) => {
                 match decided_snapshot {
                     Some(s) => {
+                        let result = self.internal_storage.write_atomically(ops);
+                        self.internal_storage.rollback_if_err(
+                            result,
+                            vec![
+                                RollbackValue::AcceptedRound(old_accepted_round),
+                                RollbackValue::DecidedIdx(old_decided_idx),
+                            ],
+                            "write accepted round and decided index",
+                        )?;
                         let old_compacted_idx = self.internal_storage.get_compacted_idx();
                         let old_log_res = self.internal_storage.get_suffix(old_compacted_idx);
+                        let old_log = self.internal_storage.rollback_if_err(
+                            old_log_res,
+                            vec![
+                                RollbackValue::AcceptedRound(old_accepted_round),
+                                RollbackValue::DecidedIdx(old_decided_idx),
+                            ],
+                            "read old log",
+                        )?;
                         let old_snapshot_res = self.internal_storage.get_snapshot();
-                        if old_log_res.is_err() || old_snapshot_res.is_err() {
-                            self.internal_storage.rollback_and_panic(
-                                vec![
-                                    RollbackValue::AcceptedRound(old_accepted_round),
-                                    RollbackValue::DecidedIdx(old_decided_idx),
-                                ],
-                                "storage error while trying to read old log or snapshot",
-                            );
-                        }
+                        let old_snapshot = self.internal_storage.rollback_if_err(
+                            old_snapshot_res,
+                            vec![
+                                RollbackValue::AcceptedRound(old_accepted_round),
+                                RollbackValue::DecidedIdx(old_decided_idx),
+                            ],
+                            "read old snapshot",
+                        )?;
                         let decided_idx = self
                             .leader_state
                             .get_decided_idx(max_promise_meta.pid)
@@ -409,72 +1040,94 @@ where
                                 self.internal_storage.merge_snapshot(decided_idx, d)
                             }
                         };
-                        self.internal_storage.rollback_and_panic_if_err(
-                            &snapshot_result,
+                        self.internal_storage.rollback_if_err(
+                            snapshot_result,
                             vec![
                                 RollbackValue::AcceptedRound(old_accepted_round),
                                 RollbackValue::DecidedIdx(old_decided_idx),
                             ],
-                            "storage error while trying to write snapshot",
-                        );
+                            "write snapshot",
+                        )?;
                         let accepted_res = self
                             .internal_storage
                             .append_entries_without_batching(suffix);
                         // manually rollback snapshot and log if append suffix fails
-                        self.internal_storage.rollback_and_panic_if_err(
-                            &accepted_res,
+                        self.internal_storage.rollback_if_err(
+                            accepted_res,
                             vec![
                                 RollbackValue::AcceptedRound(old_accepted_round),
                                 RollbackValue::DecidedIdx(old_decided_idx),
-                                RollbackValue::Log(old_log_res.unwrap()),
-                                RollbackValue::Snapshot(
-                                    old_compacted_idx,
-                                    old_snapshot_res.unwrap(),
-                                ),
+                                RollbackValue::Log(old_log),
+                                RollbackValue::Snapshot(old_compacted_idx, old_snapshot),
                             ],
-                            "storage error while trying to write log entries",
-                        );
+                            "write log entries",
+                        )?;
                     }
                     None => {
                         // no snapshot, only suffix
-                        let result = if max_promise_meta.n_accepted == old_accepted_round {
-                            self.internal_storage
-                                .append_entries_without_batching(suffix)
+                        if max_promise_meta.n_accepted == old_accepted_round {
+                            ops.push(StorageOp::AppendEntries(suffix));
                         } else {
-                            self.internal_storage.append_on_decided_prefix(suffix)
-                        };
-                        self.internal_storage.rollback_and_panic_if_err(
-                            &result,
+                            ops.push(StorageOp::AppendOnPrefix(decided_idx, suffix));
+                        }
+                        let result = self.internal_storage.write_atomically(ops);
+                        self.internal_storage.rollback_if_err(
+                            result,
                             vec![
                                 RollbackValue::AcceptedRound(old_accepted_round),
                                 RollbackValue::DecidedIdx(old_decided_idx),
                             ],
-                            "storage error while trying to write log entries",
-                        );
+                            "write log entries",
+                        )?;
                     }
                 }
             }
             None => {
                 // I am the most updated
-                self.append_pending_proposals();
-                self.adopt_pending_stopsign();
+                let result = self.internal_storage.write_atomically(ops);
+                self.internal_storage.rollback_if_err(
+                    result,
+                    vec![
+                        RollbackValue::AcceptedRound(old_accepted_round),
+                        RollbackValue::DecidedIdx(old_decided_idx),
+                    ],
+                    "write accepted round and decided index",
+                )?;
+                self.append_pending_proposals()?;
+                self.adopt_pending_stopsign()?;
             }
         }
         match max_stopsign {
             Some(ss) => {
-                self.accept_stopsign(ss);
+                self.accept_stopsign(ss)?;
             }
             None => {
-                self.append_pending_proposals();
-                self.adopt_pending_stopsign();
+                self.append_pending_proposals()?;
+                self.adopt_pending_stopsign()?;
             }
         }
+        // The latest membership might not have been accepted by a quorum, so it is proposed again
+        // in this round, even if it is unchanged. No new membership change is allowed until a
+        // quorum has accepted it, as a change of an earlier round that only a minority accepted
+        // could otherwise be decided with a non-overlapping quorum. The AcceptSync messages include it.
+        let mut membership = match self.leader_state.take_max_promise_membership() {
+            Some(m) if m.is_newer_than(&self.membership) => m,
+            _ => self.membership.clone(),
+        };
+        membership.n = self.leader_state.n_leader;
+        self.accept_membership_leader(membership)?;
         for pid in self.leader_state.get_promised_followers() {
-            self.send_accsync(pid);
+            self.send_accsync(pid)?;
         }
+        self.send_pending_read_index_checks();
+        Ok(())
     }
 
-    pub(crate) fn handle_promise_prepare(&mut self, prom: Promise<T>, from: NodeId) {
+    pub(crate) fn handle_promise_prepare(
+        &mut self,
+        prom: Promise<T>,
+        from: NodeId,
+    ) -> Result<(), StorageError> {
         #[cfg(feature = "logging")]
         debug!(
             self.logger,
@@ -483,12 +1136,17 @@ where
         if prom.n == self.leader_state.n_leader {
             let received_majority = self.leader_state.set_promise(prom, from, true);
             if received_majority {
-                self.handle_majority_promises();
+                self.handle_majority_promises()?;
             }
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_promise_accept(&mut self, prom: Promise<T>, from: NodeId) {
+    pub(crate) fn handle_promise_accept(
+        &mut self,
+        prom: Promise<T>,
+        from: NodeId,
+    ) -> Result<(), StorageError> {
         #[cfg(feature = "logging")]
         {
             let (r, p) = &self.state;
@@ -499,11 +1157,16 @@ where
         }
         if prom.n == self.leader_state.n_leader {
             self.leader_state.set_promise(prom, from, false);
-            self.send_accsync(from);
+            self.send_accsync(from)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_accepted(&mut self, accepted: Accepted, from: NodeId) {
+    pub(crate) fn handle_accepted(
+        &mut self,
+        accepted: Accepted,
+        from: NodeId,
+    ) -> Result<(), StorageError> {
         #[cfg(feature = "logging")]
         trace!(
             self.logger,
@@ -517,15 +1180,18 @@ where
             let old_decided_idx = self.internal_storage.get_decided_idx();
             self.leader_state
                 .set_accepted_idx(from, accepted.accepted_idx);
+            if matches!(self.leadership_transfer, Some((to, _)) if to == from) {
+                self.try_transfer_leadership();
+            }
             if accepted.accepted_idx > old_decided_idx
                 && self.leader_state.is_chosen(accepted.accepted_idx)
             {
                 let decided_idx = accepted.accepted_idx;
                 self.internal_storage
                     .set_decided_idx(decided_idx)
-                    .expect("storage error while trying to write decided index");
+                    .context("write decided index")?;
                 // Send Decides to followers or batch with previous AcceptDecide
-                for pid in self.leader_state.get_promised_followers() {
+                for pid in self.leader_state.get_synced_followers() {
                     if cfg!(feature = "batch_accept") {
                         #[cfg(feature = "batch_accept")]
                         match self.leader_state.get_batch_accept_meta(pid) {
@@ -547,8 +1213,15 @@ where
                         self.send_decide(pid, decided_idx, false);
                     }
                 }
+                self.report_decided_proposals();
+            }
+            if let Some(progress) = self.leader_state.get_sync_progress(from) {
+                if accepted.accepted_idx >= progress.to_idx {
+                    self.send_sync_chunk(from, accepted.accepted_idx, None)?;
+                }
             }
         }
+        Ok(())
     }
 
     pub(crate) fn handle_notaccepted(&mut self, not_acc: NotAccepted, from: NodeId) {
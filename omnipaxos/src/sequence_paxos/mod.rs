@@ -1,19 +1,30 @@
 use super::{
     ballot_leader_election::Ballot,
     messages::sequence_paxos::*,
-    storage::{Entry, StopSign, Storage},
+    storage::{
+        Entry, Membership, Snapshot, SnapshotInstaller, SnapshotMetadata, SnapshotProvider,
+        StopSign, Storage,
+    },
     util::LeaderState,
 };
 #[cfg(feature = "logging")]
 use crate::utils::logger::create_logger;
 use crate::{
-    storage::{InternalStorage, InternalStorageConfig},
-    util::{AcceptedMetaData, FlexibleQuorum, NodeId, Quorum, SequenceNumber},
-    ClusterConfig, CompactionErr, OmniPaxosConfig, ProposeErr,
+    errors::{ConfigError, StorageContext, StorageError},
+    storage::{ApplicationSnapshots, InternalStorage, InternalStorageConfig, StorageResult},
+    util::{
+        AcceptedMetaData, AutoCompaction, ClientSessions, NodeId, ProposalId, ProposalOutcome,
+        ProposalStatus, Quorum, ReadId, ReadIndex, SequenceNumber, SyncMetrics,
+    },
+    ClusterConfig, CompactionErr, OmniPaxosConfig, ProposeErr, TransferLeadershipErr,
 };
 #[cfg(feature = "logging")]
 use slog::{debug, info, trace, warn, Logger};
-use std::{fmt::Debug, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    vec,
+};
 
 pub mod follower;
 pub mod leader;
@@ -30,7 +41,7 @@ where
     pid: NodeId,
     peers: Vec<NodeId>, // excluding self pid
     state: (Role, Phase),
-    pending_proposals: Vec<T>,
+    pending_proposals: Vec<Proposal<T>>,
     pending_stopsign: Option<StopSign>,
     outgoing: Vec<PaxosMessage<T>>,
     leader_state: LeaderState<T>,
@@ -39,6 +50,49 @@ where
     current_seq_num: SequenceNumber,
     cached_promise_message: Option<Promise<T>>,
     buffer_size: usize,
+    // Read requests (origin, id) that are waiting for a leader to be elected
+    pending_reads: Vec<(NodeId, ReadId)>,
+    confirmed_reads: Vec<ReadIndex>,
+    read_id: ReadId,
+    proposal_id: ProposalId,
+    // The proposals of this server whose outcome has not been reported yet
+    unresolved_proposals: HashSet<ProposalId>,
+    max_pending_proposals: Option<usize>,
+    proposal_outcomes: Vec<ProposalOutcome>,
+    // The client sessions of the log of this leader. Only created once an entry with a client session is proposed.
+    client_sessions: Option<ClientSessions>,
+    // Whether this server holds a leader lease, which allows reads to be confirmed locally
+    leader_lease: bool,
+    // The current cluster configuration, which can be changed in place
+    membership: Membership,
+    // Whether the membership has changed since the last call to `take_membership_change`
+    membership_changed: bool,
+    // The server that the leadership is transferred to and the number of resend timeouts since the transfer started
+    leadership_transfer: Option<(NodeId, u64)>,
+    // The round of the leader that asked this server to take over the leadership
+    takeover_request: Option<Ballot>,
+    halt_on_storage_error: bool,
+    async_flush: bool,
+    // The round and the accepted index of the last `Accepted` while entries are waiting to be flushed
+    pending_flush: Option<(Ballot, u64)>,
+    // The limits of the chunks that lagging followers are synchronized with
+    max_sync_entries: Option<usize>,
+    max_sync_bytes: Option<usize>,
+    // How the followers have been synchronized by this server as the leader
+    sync_metrics: SyncMetrics,
+    // The decided entries that the last snapshot created to synchronize a follower was created from, and its size hint
+    snapshot_size_estimate: Option<(u64, u64, usize)>,
+    // The policy for compacting the log in `tick()`, see `compaction_interval` in `ServerConfig`
+    auto_compaction: AutoCompaction,
+    // The out-of-band snapshot transfer, see `OmniPaxos::set_snapshot_transfer`
+    snapshot_provider: Option<Box<dyn SnapshotProvider>>,
+    snapshot_installer: Option<Box<dyn SnapshotInstaller>>,
+    // The snapshots of the application state, see `OmniPaxos::install_application_snapshot`
+    application_snapshots: ApplicationSnapshots,
+    // The round, the snapshot and the offset of the next chunk of the snapshot that this follower is pulling
+    snapshot_transfer: Option<(Ballot, SnapshotMetadata, u64)>,
+    // The storage error that halted this server
+    storage_error: Option<StorageError>,
     #[cfg(feature = "logging")]
     logger: Logger,
 }
@@ -52,9 +106,19 @@ where
     /// Creates a Sequence Paxos replica.
     pub(crate) fn with(config: SequencePaxosConfig, storage: B) -> Self {
         let pid = config.pid;
-        let peers = config.peers;
-        let num_nodes = &peers.len() + 1;
-        let quorum = Quorum::with(config.flexible_quorum, num_nodes);
+        let membership = storage
+            .get_membership()
+            .expect("storage error while trying to read membership")
+            .unwrap_or(Membership {
+                config: config.cluster_config,
+                version: 0,
+                n: Ballot::default(),
+            });
+        let peers = membership.config.peers_of(pid);
+        let voters = membership.config.nodes.clone();
+        let learners = membership.config.learners.clone();
+        let num_nodes = voters.len();
+        let quorum = Quorum::with(membership.config.flexible_quorum, num_nodes);
         let max_peer_pid = peers.iter().max().unwrap();
         let max_pid = *std::cmp::max(max_peer_pid, &pid) as usize;
         let mut outgoing = Vec::with_capacity(config.buffer_size);
@@ -93,11 +157,42 @@ where
             pending_proposals: vec![],
             pending_stopsign: None,
             outgoing,
-            leader_state: LeaderState::<T>::with(leader, max_pid, quorum),
+            leader_state: LeaderState::<T>::with(leader, max_pid, quorum, voters, learners),
             latest_accepted_meta: None,
             current_seq_num: SequenceNumber::default(),
             cached_promise_message: None,
             buffer_size: config.buffer_size,
+            pending_reads: vec![],
+            confirmed_reads: vec![],
+            read_id: 0,
+            proposal_id: 0,
+            unresolved_proposals: HashSet::new(),
+            max_pending_proposals: config.max_pending_proposals,
+            proposal_outcomes: vec![],
+            client_sessions: None,
+            leader_lease: false,
+            membership,
+            membership_changed: false,
+            leadership_transfer: None,
+            takeover_request: None,
+            halt_on_storage_error: config.halt_on_storage_error,
+            async_flush: config.async_flush,
+            pending_flush: None,
+            max_sync_entries: config.max_sync_entries,
+            max_sync_bytes: config.max_sync_bytes,
+            sync_metrics: SyncMetrics::default(),
+            snapshot_size_estimate: None,
+            auto_compaction: AutoCompaction {
+                interval: config.compaction_interval,
+                log_bytes: config.compaction_log_bytes,
+                safety_margin: config.compaction_safety_margin,
+                ..Default::default()
+            },
+            snapshot_provider: None,
+            snapshot_installer: None,
+            application_snapshots: ApplicationSnapshots::default(),
+            snapshot_transfer: None,
+            storage_error: None,
             #[cfg(feature = "logging")]
             logger: {
                 if let Some(logger) = config.custom_logger {
@@ -136,6 +231,105 @@ where
         self.internal_storage.get_promise()
     }
 
+    /// Returns the storage error that halted this server, if any.
+    pub(crate) fn get_storage_error(&self) -> Option<&StorageError> {
+        self.storage_error.as_ref()
+    }
+
+    pub(crate) fn is_halted(&self) -> bool {
+        self.storage_error.is_some()
+    }
+
+    /// Returns the value of `result` if the storage operation succeeded. Otherwise this server halts if
+    /// `halt_on_storage_error` is set and panics if not.
+    fn handle_storage_error<R>(&mut self, result: Result<R, StorageError>) -> Option<R> {
+        match result {
+            Ok(r) => Some(r),
+            Err(e) if self.halt_on_storage_error => {
+                #[cfg(feature = "logging")]
+                warn!(self.logger, "Halting due to {}", e);
+                // A halted server does not send the messages that are still buffered
+                self.get_outgoing_msgs();
+                self.storage_error = Some(e);
+                None
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Maps the result of a trim or snapshot. Errors other than [`CompactionErr`] are storage errors.
+    fn handle_compaction_result(
+        &mut self,
+        result: StorageResult<()>,
+        operation: &'static str,
+    ) -> Result<(), CompactionErr> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => match e.downcast::<CompactionErr>() {
+                Ok(e) => Err(*e),
+                Err(error) => {
+                    self.handle_storage_error::<()>(Err(StorageError {
+                        operation,
+                        error: error.to_string(),
+                    }));
+                    Err(CompactionErr::Halted)
+                }
+            },
+        }
+    }
+
+    /// Resumes a server that halted due to a storage error. The state is loaded from the storage again and the
+    /// server recovers like after a crash, i.e., it drops its unresolved proposals and waits for a leader to
+    /// synchronize it. If the storage still fails, the server stays halted.
+    pub(crate) fn recover(&mut self) {
+        if self.storage_error.is_none() {
+            return;
+        }
+        #[cfg(feature = "logging")]
+        info!(self.logger, "Recovering from {:?}", self.storage_error);
+        self.storage_error = None;
+        let result = self.internal_storage.reload_cache().context("load state");
+        if self.handle_storage_error(result).is_none() {
+            return;
+        }
+        let promise = self.internal_storage.get_promise();
+        if let Some(membership) = self.internal_storage.get_membership() {
+            self.apply_membership(membership);
+        }
+        self.leader_state = LeaderState::with(
+            promise,
+            self.leader_state.max_pid,
+            self.leader_state.quorum,
+            std::mem::take(&mut self.leader_state.voters),
+            std::mem::take(&mut self.leader_state.learners),
+        );
+        self.latest_accepted_meta = None;
+        self.pending_flush = None;
+        self.snapshot_transfer = None;
+        self.auto_compaction
+            .reset_measurement(self.get_compacted_idx());
+        self.current_seq_num = SequenceNumber::default();
+        self.cached_promise_message = None;
+        self.pending_stopsign = None;
+        // Only our own reads are kept, they are forwarded to the leader once this server is synchronized
+        let pid = self.pid;
+        self.pending_reads.retain(|(origin, _)| *origin == pid);
+        self.client_sessions = None;
+        self.leader_lease = false;
+        self.leadership_transfer = None;
+        self.takeover_request = None;
+        self.get_outgoing_msgs();
+        let proposals = std::mem::take(&mut self.pending_proposals);
+        self.drop_proposals(proposals);
+        self.drop_unresolved_proposals();
+        if promise == Ballot::default() {
+            self.state = (Role::Follower, Phase::None);
+        } else {
+            self.state = (Role::Follower, Phase::Recover);
+            self.send_preparereq_to_all_peers();
+        }
+    }
+
     /// Initiates the trim process.
     /// # Arguments
     /// * `trim_idx` - Deletes all entries up to [`trim_idx`], if the [`trim_idx`] is `None` then the minimum index accepted by **ALL** servers will be used as the [`trim_idx`].
@@ -169,10 +363,7 @@ where
                         });
                     }
                 }
-                result.map_err(|e| {
-                    *e.downcast()
-                        .expect("storage error while trying to trim log")
-                })
+                self.handle_compaction_result(result, "trim log")
             }
             _ => Err(CompactionErr::NotCurrentLeader(self.get_current_leader())),
         }
@@ -199,10 +390,135 @@ where
                 });
             }
         }
-        result.map_err(|e| {
-            *e.downcast()
-                .expect("storage error while trying to snapshot log")
-        })
+        self.handle_compaction_result(result, "snapshot log")
+    }
+
+    /// Compacts the log if the decided entries of the leader that are not compacted exceed `compaction_interval` or
+    /// `compaction_log_bytes`. The leader compacts the entries that all servers have accepted, except for the latest
+    /// `compaction_safety_margin` decided entries, and tells the followers to compact them too. If snapshots are used,
+    /// the entries are snapshotted and otherwise they are trimmed. Followers only compact when the leader tells them to.
+    pub(crate) fn auto_compact(&mut self) {
+        let policy = self.auto_compaction;
+        if !policy.is_enabled() || self.state != (Role::Leader, Phase::Accept) {
+            return;
+        }
+        let compacted_idx = self.get_compacted_idx();
+        let decided_idx = self.internal_storage.get_decided_idx_without_stopsign();
+        let exceeds_interval = policy
+            .interval
+            .is_some_and(|n| decided_idx.saturating_sub(compacted_idx) >= n);
+        let exceeds_log_bytes = match policy.log_bytes {
+            Some(max_bytes) if !exceeds_interval => {
+                match self.get_decided_log_bytes(compacted_idx, decided_idx) {
+                    Some(num_bytes) => num_bytes >= max_bytes,
+                    None => return,
+                }
+            }
+            _ => false,
+        };
+        if !exceeds_interval && !exceeds_log_bytes {
+            return;
+        }
+        let compact_idx = decided_idx
+            .min(*self.leader_state.get_min_all_accepted_idx())
+            .saturating_sub(policy.safety_margin);
+        if compact_idx <= compacted_idx {
+            return;
+        }
+        #[cfg(feature = "logging")]
+        debug!(self.logger, "Compacting the log up to {}", compact_idx);
+        // The index is decided and accepted by all servers, so only storage errors can occur, which are handled already
+        let _ = if T::Snapshot::use_snapshots() {
+            self.snapshot(Some(compact_idx), false)
+        } else {
+            self.trim(Some(compact_idx))
+        };
+    }
+
+    /// Returns the size in bytes of the decided entries that are not compacted. Only the sizes of the entries that
+    /// were decided since the last call are added, unless the log has been compacted in the meantime.
+    fn get_decided_log_bytes(&mut self, compacted_idx: u64, decided_idx: u64) -> Option<usize> {
+        let measurement = &mut self.auto_compaction;
+        if measurement.measured_from != compacted_idx || measurement.measured_to > decided_idx {
+            measurement.reset_measurement(compacted_idx);
+        }
+        if measurement.measured_to < decided_idx {
+            let result = self
+                .internal_storage
+                .get_entries(measurement.measured_to, decided_idx)
+                .context("read log entries");
+            let entries = self.handle_storage_error(result)?;
+            let measurement = &mut self.auto_compaction;
+            measurement.measured_bytes = entries.iter().fold(measurement.measured_bytes, |n, e| {
+                n.saturating_add(e.size_hint())
+            });
+            measurement.measured_to = decided_idx;
+        }
+        Some(self.auto_compaction.measured_bytes)
+    }
+
+    /// Enables the out-of-band transfer of the snapshots of `provider` to followers whose missing entries are compacted.
+    pub(crate) fn set_snapshot_transfer(
+        &mut self,
+        provider: Box<dyn SnapshotProvider>,
+        installer: Box<dyn SnapshotInstaller>,
+    ) {
+        self.snapshot_provider = Some(provider);
+        self.snapshot_installer = Some(installer);
+    }
+
+    /// Compacts the log with a snapshot of the application state at `idx`, which is transferred to followers whose
+    /// missing entries are compacted unless a snapshot provider has been set.
+    pub(crate) fn install_application_snapshot(
+        &mut self,
+        idx: u64,
+        snapshot: Vec<u8>,
+    ) -> Result<(), CompactionErr> {
+        let decided_idx = self.get_decided_idx();
+        let compacted_idx = self.get_compacted_idx();
+        if idx > decided_idx {
+            return Err(CompactionErr::UndecidedIndex(decided_idx));
+        }
+        if idx < compacted_idx {
+            return Err(CompactionErr::TrimmedIndex(compacted_idx));
+        }
+        // A decided stopsign is not part of the log, and applying it does not change the application state
+        let idx = idx.min(self.internal_storage.get_decided_idx_without_stopsign());
+        let result = self.internal_storage.set_installed_snapshot(idx);
+        if result.is_ok() {
+            self.application_snapshots.set(idx, snapshot);
+        }
+        self.handle_compaction_result(result, "trim log")
+    }
+
+    /// Returns the snapshot of the application state that has been transferred from the leader since the last call.
+    pub(crate) fn take_installed_application_snapshot(&mut self) -> Option<(u64, Vec<u8>)> {
+        self.application_snapshots.take_installed()
+    }
+
+    /// Returns the provider of the snapshots that are transferred to followers, i.e., the one set with
+    /// `set_snapshot_transfer()` or else the snapshots of the application state if there are any.
+    fn snapshot_provider(&mut self) -> Option<&mut dyn SnapshotProvider> {
+        match &mut self.snapshot_provider {
+            Some(provider) => Some(provider.as_mut()),
+            None if self.application_snapshots.has_snapshot() => {
+                Some(&mut self.application_snapshots)
+            }
+            None => None,
+        }
+    }
+
+    fn has_snapshot_provider(&self) -> bool {
+        self.snapshot_provider.is_some() || self.application_snapshots.has_snapshot()
+    }
+
+    /// Returns the installer of transferred snapshots. Without one set with `set_snapshot_transfer()`, the
+    /// snapshots are kept until the application polls them.
+    fn snapshot_installer(&mut self) -> &mut dyn SnapshotInstaller {
+        match &mut self.snapshot_installer {
+            Some(installer) => installer.as_mut(),
+            None => &mut self.application_snapshots,
+        }
     }
 
     /// Return the decided index.
@@ -215,6 +531,10 @@ where
         self.internal_storage.get_compacted_idx()
     }
 
+    pub(crate) fn get_sync_metrics(&self) -> SyncMetrics {
+        self.sync_metrics
+    }
+
     fn handle_compaction(&mut self, c: Compaction) {
         // try trimming and snapshotting forwarded compaction. Errors are ignored as that the data will still be kept.
         match c {
@@ -227,7 +547,7 @@ where
         }
     }
 
-    /// Detects if a Prepare, Promise, AcceptStopSign, Decide of a Stopsign, or PrepareReq message
+    /// Detects if a Prepare, Promise, AcceptStopSign, AcceptMembership, AcceptSyncChunk, Decide of a Stopsign, or PrepareReq message
     /// has been sent but not been received. If so resends them. Note: We can't detect if a
     /// StopSign's Decide message has been received so we always resend to be safe.
     pub(crate) fn resend_message_timeout(&mut self) {
@@ -240,10 +560,20 @@ where
                 }
             }
             (Role::Leader, Phase::Accept) => {
+                // Give up a leadership transfer that did not complete in time
+                if let Some((_, timeouts)) = &mut self.leadership_transfer {
+                    *timeouts += 1;
+                    if *timeouts > 1 {
+                        let result = self.cancel_leadership_transfer();
+                        if self.handle_storage_error(result).is_none() {
+                            return;
+                        }
+                    }
+                }
                 // Resend AcceptStopSign or StopSign's decide
                 if let Some(ss) = self.internal_storage.get_stopsign() {
                     let decided_idx = self.internal_storage.get_decided_idx();
-                    for follower in self.leader_state.get_promised_followers() {
+                    for follower in self.leader_state.get_synced_followers() {
                         if self.internal_storage.stopsign_is_decided() {
                             self.send_decide(follower, decided_idx, true);
                         } else if self.leader_state.get_accepted_idx(follower)
@@ -253,6 +583,31 @@ where
                         }
                     }
                 }
+                // Resend the chunks that followers have not accepted since the last timeout
+                for follower in self.leader_state.get_promised_followers() {
+                    if let Some(mut progress) = self.leader_state.get_sync_progress(follower) {
+                        if progress.stalled {
+                            let result =
+                                self.send_sync_chunk(follower, progress.from_idx, Some(progress));
+                            if self.handle_storage_error(result).is_none() {
+                                return;
+                            }
+                        } else {
+                            progress.stalled = true;
+                            self.leader_state
+                                .set_sync_progress(follower, Some(progress));
+                        }
+                    }
+                }
+                // Resend AcceptMembership
+                if self.leader_state.has_pending_membership() {
+                    for follower in self.leader_state.get_membership_unacked_followers() {
+                        self.send_accept_membership(follower, self.membership.clone(), true);
+                    }
+                }
+                for follower in self.leader_state.get_removed_followers() {
+                    self.send_accept_membership(follower, self.membership.clone(), true);
+                }
                 // Resend Prepare
                 let preparable_peers = self.leader_state.get_preparable_peers();
                 for peer in preparable_peers {
@@ -260,7 +615,7 @@ where
                 }
             }
             (Role::Follower, Phase::Prepare) => {
-                // Resend Promise
+                // Resend Promise, which also makes the leader offer a snapshot that is being transferred again
                 match &self.cached_promise_message {
                     Some(promise) => {
                         self.outgoing.push(PaxosMessage {
@@ -302,6 +657,8 @@ where
 
     /// Returns the outgoing messages from this replica. The messages should then be sent via the network implementation.
     pub(crate) fn get_outgoing_msgs(&mut self) -> Vec<PaxosMessage<T>> {
+        let result = self.poll_flushed_accepted();
+        self.handle_storage_error(result);
         let mut outgoing = Vec::with_capacity(self.buffer_size);
         std::mem::swap(&mut self.outgoing, &mut outgoing);
         #[cfg(feature = "batch_accept")]
@@ -314,28 +671,47 @@ where
 
     /// Handle an incoming message.
     pub(crate) fn handle(&mut self, m: PaxosMessage<T>) {
+        let result = self.handle_message(m);
+        self.handle_storage_error(result);
+    }
+
+    fn handle_message(&mut self, m: PaxosMessage<T>) -> Result<(), StorageError> {
         match m.msg {
             PaxosMsg::PrepareReq(prepreq) => self.handle_preparereq(prepreq, m.from),
-            PaxosMsg::Prepare(prep) => self.handle_prepare(prep, m.from),
+            PaxosMsg::Prepare(prep) => self.handle_prepare(prep, m.from)?,
             PaxosMsg::Promise(prom) => match &self.state {
-                (Role::Leader, Phase::Prepare) => self.handle_promise_prepare(prom, m.from),
-                (Role::Leader, Phase::Accept) => self.handle_promise_accept(prom, m.from),
+                (Role::Leader, Phase::Prepare) => self.handle_promise_prepare(prom, m.from)?,
+                (Role::Leader, Phase::Accept) => self.handle_promise_accept(prom, m.from)?,
                 _ => {}
             },
-            PaxosMsg::AcceptSync(acc_sync) => self.handle_acceptsync(acc_sync, m.from),
-            PaxosMsg::AcceptDecide(acc) => self.handle_acceptdecide(acc),
+            PaxosMsg::AcceptSync(acc_sync) => self.handle_acceptsync(acc_sync, m.from)?,
+            PaxosMsg::AcceptSyncChunk(chunk) => self.handle_accept_sync_chunk(chunk)?,
+            PaxosMsg::SnapshotOffer(offer) => self.handle_snapshot_offer(offer)?,
+            PaxosMsg::SnapshotChunkRequest(req) => {
+                self.handle_snapshot_chunk_request(req, m.from)?
+            }
+            PaxosMsg::SnapshotChunk(chunk) => self.handle_snapshot_chunk(chunk)?,
+            PaxosMsg::AcceptDecide(acc) => self.handle_acceptdecide(acc)?,
             PaxosMsg::NotAccepted(not_acc) => self.handle_notaccepted(not_acc, m.from),
-            PaxosMsg::Accepted(accepted) => self.handle_accepted(accepted, m.from),
-            PaxosMsg::Decide(d) => self.handle_decide(d),
-            PaxosMsg::ProposalForward(proposals) => self.handle_forwarded_proposal(proposals),
+            PaxosMsg::Accepted(accepted) => self.handle_accepted(accepted, m.from)?,
+            PaxosMsg::Decide(d) => self.handle_decide(d)?,
+            PaxosMsg::ProposalForward(proposals) => self.handle_forwarded_proposal(proposals)?,
+            PaxosMsg::ProposalOutcomes(outcomes) => self.handle_proposal_outcomes(outcomes),
             PaxosMsg::Compaction(c) => self.handle_compaction(c),
-            PaxosMsg::AcceptStopSign(acc_ss) => self.handle_accept_stopsign(acc_ss),
-            PaxosMsg::ForwardStopSign(f_ss) => self.handle_forwarded_stopsign(f_ss),
+            PaxosMsg::AcceptStopSign(acc_ss) => self.handle_accept_stopsign(acc_ss)?,
+            PaxosMsg::ForwardStopSign(f_ss) => self.handle_forwarded_stopsign(f_ss)?,
+            PaxosMsg::ReadIndexForward(id) => self.handle_forwarded_read(id, m.from),
+            PaxosMsg::ReadIndexCheck(check) => self.handle_read_index_check(check),
+            PaxosMsg::ReadIndexAck(ack) => self.handle_read_index_ack(ack, m.from),
+            PaxosMsg::ReadIndexReply(reply) => self.handle_read_index_reply(reply),
+            PaxosMsg::TransferLeadership(t) => self.handle_transfer_leadership(t),
+            PaxosMsg::ForwardMembership(config) => self.handle_forwarded_membership(config)?,
+            PaxosMsg::AcceptMembership(acc) => self.handle_accept_membership(acc)?,
+            PaxosMsg::AcceptedMembership(acc) => self.handle_accepted_membership(acc, m.from),
             #[cfg(feature = "unicache")]
-            PaxosMsg::EncodedAcceptDecide(e) => {
-                self.handle_encoded_acceptdecide(e);
-            }
+            PaxosMsg::EncodedAcceptDecide(e) => self.handle_encoded_acceptdecide(e)?,
         }
+        Ok(())
     }
 
     /// Returns whether this Sequence Paxos has been reconfigured
@@ -351,13 +727,28 @@ where
         self.internal_storage.get_stopsign().is_some()
     }
 
-    /// Append an entry to the replicated log.
-    pub(crate) fn append(&mut self, entry: T) -> Result<(), ProposeErr<T>> {
+    /// Append an entry to the replicated log. Returns the id of the proposal.
+    pub(crate) fn append(&mut self, entry: T) -> Result<ProposalId, ProposeErr<T>> {
         if self.pending_reconfiguration() {
             Err(ProposeErr::PendingReconfigEntry(entry))
+        } else if matches!(self.max_pending_proposals, Some(max) if self.unresolved_proposals.len() >= max)
+        {
+            match self.get_current_leader() {
+                0 => Err(ProposeErr::NoLeader(entry)),
+                _ => Err(ProposeErr::Overloaded(entry)),
+            }
         } else {
-            self.propose_entry(entry);
-            Ok(())
+            self.proposal_id += 1;
+            let id = self.proposal_id;
+            self.unresolved_proposals.insert(id);
+            // If the entry cannot be written, it is reported as dropped once the server is resumed
+            let result = self.propose_entry(Proposal {
+                origin: self.pid,
+                id,
+                entry,
+            });
+            self.handle_storage_error(result);
+            Ok(id)
         }
     }
 
@@ -391,8 +782,11 @@ where
                             self.leader_state.n_leader
                         );
                         let ss = StopSign::with(new_config, metadata);
-                        self.accept_stopsign(ss.clone());
-                        for pid in self.leader_state.get_promised_followers() {
+                        let result = self.accept_stopsign(ss.clone());
+                        if self.handle_storage_error(result).is_none() {
+                            return Err(ProposeErr::HaltedConfig(ss.next_config, ss.metadata));
+                        }
+                        for pid in self.leader_state.get_synced_followers() {
                             self.send_accept_stopsign(pid, ss.clone(), false);
                         }
                     } else {
@@ -408,6 +802,215 @@ where
         }
     }
 
+    /// Propose an in-place membership change to `new_config`. The caller must have checked that
+    /// `new_config` is a valid change of the current configuration.
+    pub(crate) fn change_membership(
+        &mut self,
+        new_config: ClusterConfig,
+    ) -> Result<(), ProposeErr<T>> {
+        match self.state {
+            (Role::Leader, Phase::Accept) if !self.leader_state.has_pending_membership() => {
+                if !new_config.nodes.contains(&self.pid) {
+                    return Err(ProposeErr::ConfigError(
+                        ConfigError::InvalidConfig(
+                            "The leader cannot remove itself from the configuration".to_string(),
+                        ),
+                        new_config,
+                        None,
+                    ));
+                }
+                #[cfg(feature = "logging")]
+                info!(
+                    self.logger,
+                    "Propose membership change {:?} with {:?}",
+                    new_config.nodes,
+                    self.leader_state.n_leader
+                );
+                let result = self.propose_membership(new_config.clone());
+                if self.handle_storage_error(result).is_none() {
+                    return Err(ProposeErr::HaltedConfig(new_config, None));
+                }
+            }
+            (Role::Leader, _) => return Err(ProposeErr::PendingMembershipChange(new_config)),
+            _ => self.forward_membership(new_config),
+        }
+        Ok(())
+    }
+
+    /// Returns the current cluster configuration.
+    pub(crate) fn get_cluster_config(&self) -> &ClusterConfig {
+        &self.membership.config
+    }
+
+    /// Returns the new cluster configuration if the membership has changed since the last call.
+    pub(crate) fn take_membership_change(&mut self) -> Option<ClusterConfig> {
+        if self.membership_changed {
+            self.membership_changed = false;
+            Some(self.membership.config.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Persists `membership` and starts using it as the current configuration.
+    fn set_membership(&mut self, membership: Membership) -> Result<(), StorageError> {
+        self.internal_storage
+            .set_membership(membership.clone())
+            .context("write membership")?;
+        self.apply_membership(membership);
+        Ok(())
+    }
+
+    /// Starts using `membership` as the current configuration.
+    fn apply_membership(&mut self, membership: Membership) {
+        let config = &membership.config;
+        self.peers = config.peers_of(self.pid);
+        let max_pid = self
+            .peers
+            .iter()
+            .chain(std::iter::once(&self.pid))
+            .max()
+            .copied()
+            .unwrap() as usize;
+        let quorum = Quorum::with(config.flexible_quorum, config.nodes.len());
+        self.leader_state.set_members(
+            max_pid,
+            quorum,
+            config.nodes.clone(),
+            config.learners.clone(),
+        );
+        self.membership_changed |= self.membership.config != membership.config;
+        self.membership = membership;
+    }
+
+    /// Request a linearizable read. Returns the id of the read request.
+    pub(crate) fn read_index(&mut self) -> ReadId {
+        self.read_id += 1;
+        let id = self.read_id;
+        match self.state {
+            (Role::Leader, Phase::Prepare) => self.pending_reads.push((self.pid, id)),
+            (Role::Leader, Phase::Accept) => self.send_read_index_check(self.pid, id),
+            _ => self.forward_read(id),
+        }
+        id
+    }
+
+    /// Transfers the leadership to `to` once it has accepted all entries of this leader. New proposals are
+    /// held back until the transfer completes or is given up.
+    pub(crate) fn transfer_leadership(&mut self, to: NodeId) -> Result<(), TransferLeadershipErr> {
+        match self.state {
+            (Role::Leader, Phase::Accept) => {
+                if to == self.pid {
+                    return Ok(());
+                }
+                if !self.membership.config.nodes.contains(&to) {
+                    return Err(TransferLeadershipErr::InvalidTarget(to));
+                }
+                #[cfg(feature = "logging")]
+                info!(self.logger, "Transferring leadership to {}", to);
+                self.leadership_transfer = Some((to, 0));
+                self.try_transfer_leadership();
+                Ok(())
+            }
+            _ => Err(TransferLeadershipErr::NotCurrentLeader(
+                self.get_current_leader(),
+            )),
+        }
+    }
+
+    /// Returns the round of the leader that asked this server to take over the leadership, if any.
+    pub(crate) fn take_takeover_request(&mut self) -> Option<Ballot> {
+        self.takeover_request.take()
+    }
+
+    /// Sets whether this server currently holds a valid leader lease.
+    pub(crate) fn set_leader_lease(&mut self, valid: bool) {
+        self.leader_lease = valid;
+    }
+
+    /// Returns the read requests that have been confirmed since the last call.
+    pub(crate) fn get_confirmed_reads(&mut self) -> Vec<ReadIndex> {
+        std::mem::take(&mut self.confirmed_reads)
+    }
+
+    /// Returns the outcomes of the proposals of this server that have been reported since the last call.
+    pub(crate) fn get_proposal_outcomes(&mut self) -> Vec<ProposalOutcome> {
+        std::mem::take(&mut self.proposal_outcomes)
+    }
+
+    /// Reports the outcomes of proposals to the servers that made them, given as (origin, outcome).
+    pub(crate) fn report_proposal_outcomes(&mut self, outcomes: Vec<(NodeId, ProposalOutcome)>) {
+        let mut remote_outcomes: Vec<(NodeId, Vec<ProposalOutcome>)> = vec![];
+        for (origin, outcome) in outcomes {
+            if origin == self.pid {
+                self.handle_proposal_outcomes(vec![outcome]);
+            } else {
+                match remote_outcomes.iter_mut().find(|(pid, _)| *pid == origin) {
+                    Some((_, o)) => o.push(outcome),
+                    None => remote_outcomes.push((origin, vec![outcome])),
+                }
+            }
+        }
+        for (origin, o) in remote_outcomes {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: origin,
+                msg: PaxosMsg::ProposalOutcomes(o),
+            });
+        }
+    }
+
+    /// Reports `proposals` as dropped to the servers that made them.
+    pub(crate) fn drop_proposals(&mut self, proposals: Vec<Proposal<T>>) {
+        let outcomes = proposals
+            .into_iter()
+            .map(|p| {
+                let outcome = ProposalOutcome {
+                    id: p.id,
+                    status: ProposalStatus::Dropped,
+                };
+                (p.origin, outcome)
+            })
+            .collect();
+        self.report_proposal_outcomes(outcomes);
+    }
+
+    fn handle_proposal_outcomes(&mut self, outcomes: Vec<ProposalOutcome>) {
+        for outcome in outcomes {
+            // A proposal that was already reported as dropped gets no further outcome
+            if self.unresolved_proposals.remove(&outcome.id) {
+                self.proposal_outcomes.push(outcome);
+            }
+        }
+    }
+
+    /// Reports the proposals of this server that were sent to the leader of a previous round, or to the current leader
+    /// before reconnecting to it, as dropped. The proposals that have not been sent to a leader yet are kept, since they
+    /// are forwarded to the new leader.
+    pub(crate) fn drop_unresolved_proposals(&mut self) {
+        let pid = self.pid;
+        let pending: HashSet<ProposalId> = self
+            .pending_proposals
+            .iter()
+            .filter(|p| p.origin == pid)
+            .map(|p| p.id)
+            .collect();
+        let mut dropped: Vec<ProposalId> = self
+            .unresolved_proposals
+            .iter()
+            .filter(|id| !pending.contains(id))
+            .copied()
+            .collect();
+        dropped.sort_unstable();
+        for id in dropped {
+            self.unresolved_proposals.remove(&id);
+            self.proposal_outcomes.push(ProposalOutcome {
+                id,
+                status: ProposalStatus::Dropped,
+            });
+        }
+    }
+
     fn send_accept_stopsign(&mut self, to: NodeId, ss: StopSign, resend: bool) {
         let seq_num = match resend {
             true => self.leader_state.get_seq_num(to),
@@ -425,14 +1028,15 @@ where
         });
     }
 
-    fn accept_stopsign(&mut self, ss: StopSign) {
+    fn accept_stopsign(&mut self, ss: StopSign) -> Result<(), StorageError> {
         self.internal_storage
             .set_stopsign(Some(ss))
-            .expect("storage error while trying to write stopsign");
+            .context("write stopsign")?;
         if self.state.0 == Role::Leader {
             let accepted_idx = self.internal_storage.get_accepted_idx();
             self.leader_state.set_accepted_idx(self.pid, accepted_idx);
         }
+        Ok(())
     }
 
     fn get_current_leader(&self) -> NodeId {
@@ -446,6 +1050,8 @@ where
             return;
         } else if pid == self.get_current_leader() {
             self.state = (Role::Follower, Phase::Recover);
+            // The proposals sent to the leader might have been lost with the connection
+            self.drop_unresolved_proposals();
         }
         let prepreq = PrepareReq {
             n: self.get_promise(),
@@ -457,22 +1063,26 @@ where
         });
     }
 
-    fn propose_entry(&mut self, entry: T) {
+    fn propose_entry(&mut self, proposal: Proposal<T>) -> Result<(), StorageError> {
         match self.state {
-            (Role::Leader, Phase::Prepare) => self.pending_proposals.push(entry),
-            (Role::Leader, Phase::Accept) => self.accept_entry(entry),
-            _ => self.forward_proposals(vec![entry]),
+            (Role::Leader, Phase::Prepare) => self.pending_proposals.push(proposal),
+            (Role::Leader, Phase::Accept) if self.leadership_transfer.is_some() => {
+                self.pending_proposals.push(proposal)
+            }
+            (Role::Leader, Phase::Accept) => self.accept_entry(proposal)?,
+            _ => self.forward_proposals(vec![proposal]),
         }
+        Ok(())
     }
 
     pub(crate) fn get_leader_state(&self) -> &LeaderState<T> {
         &self.leader_state
     }
 
-    pub(crate) fn forward_proposals(&mut self, mut entries: Vec<T>) {
+    pub(crate) fn forward_proposals(&mut self, proposals: Vec<Proposal<T>>) {
         let leader = self.get_current_leader();
         if leader > 0 && self.pid != leader {
-            let pf = PaxosMsg::ProposalForward(entries);
+            let pf = PaxosMsg::ProposalForward(proposals);
             let msg = PaxosMessage {
                 from: self.pid,
                 to: leader,
@@ -480,7 +1090,64 @@ where
             };
             self.outgoing.push(msg);
         } else {
-            self.pending_proposals.append(&mut entries);
+            self.buffer_proposals(proposals);
+        }
+    }
+
+    /// Buffers `proposals` until they can be proposed or forwarded to a leader. If `max_pending_proposals` is set, at most
+    /// that many proposals of each server are buffered and the rest are reported as dropped to the servers that made them.
+    pub(crate) fn buffer_proposals(&mut self, proposals: Vec<Proposal<T>>) {
+        match self.max_pending_proposals {
+            Some(max) => {
+                let mut buffered: HashMap<NodeId, usize> = HashMap::new();
+                for p in &self.pending_proposals {
+                    *buffered.entry(p.origin).or_default() += 1;
+                }
+                let mut dropped = vec![];
+                for proposal in proposals {
+                    let count = buffered.entry(proposal.origin).or_default();
+                    if *count < max {
+                        *count += 1;
+                        self.pending_proposals.push(proposal);
+                    } else {
+                        dropped.push(proposal);
+                    }
+                }
+                if !dropped.is_empty() {
+                    self.drop_proposals(dropped);
+                }
+            }
+            None => self.pending_proposals.extend(proposals),
+        }
+    }
+
+    pub(crate) fn forward_read(&mut self, id: ReadId) {
+        let leader = self.get_current_leader();
+        if leader > 0 && self.pid != leader {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: leader,
+                msg: PaxosMsg::ReadIndexForward(id),
+            });
+        } else {
+            self.pending_reads.push((self.pid, id));
+        }
+    }
+
+    pub(crate) fn forward_membership(&mut self, config: ClusterConfig) {
+        let leader = self.get_current_leader();
+        if leader > 0 && self.pid != leader {
+            #[cfg(feature = "logging")]
+            trace!(
+                self.logger,
+                "Forwarding membership change to Leader {:?}",
+                leader
+            );
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: leader,
+                msg: PaxosMsg::ForwardMembership(config),
+            });
         }
     }
 
@@ -519,18 +1186,32 @@ pub(crate) enum Role {
 /// Configuration for `SequencePaxos`.
 /// # Fields
 /// * `pid`: The unique identifier of this node. Must not be 0.
-/// * `peers`: The peers of this node i.e. the `pid`s of the other servers in the configuration.
-/// * `flexible_quorum` : Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
+/// * `cluster_config`: The initial configuration of the cluster, used if no membership change has been stored.
 /// * `buffer_size`: The buffer size for outgoing messages.
 /// * `batch_size`: The size of the buffer for log batching. The default is 1, which means no batching.
+/// * `max_pending_proposals`: The maximum number of proposals of this server whose outcome is not known yet.
+/// * `halt_on_storage_error`: Whether this server halts instead of panicking when the storage fails.
+/// * `async_flush`: Whether followers write accepted entries asynchronously and only reply once they are flushed.
+/// * `max_sync_entries`: The maximum number of entries in a message that synchronizes a follower.
+/// * `max_sync_bytes`: The maximum size of the entries in a message that synchronizes a follower.
+/// * `compaction_interval`: The number of decided entries after which the log is compacted automatically.
+/// * `compaction_log_bytes`: The size of the decided entries after which the log is compacted automatically.
+/// * `compaction_safety_margin`: The number of the latest decided entries that are kept when compacting automatically.
 /// * `logger_file_path`: The path where the default logger logs events.
 #[derive(Clone, Debug)]
 pub(crate) struct SequencePaxosConfig {
     pid: NodeId,
-    peers: Vec<NodeId>,
+    cluster_config: ClusterConfig,
     buffer_size: usize,
     pub(crate) batch_size: usize,
-    flexible_quorum: Option<FlexibleQuorum>,
+    max_pending_proposals: Option<usize>,
+    halt_on_storage_error: bool,
+    async_flush: bool,
+    max_sync_entries: Option<usize>,
+    max_sync_bytes: Option<usize>,
+    compaction_interval: Option<u64>,
+    compaction_log_bytes: Option<usize>,
+    compaction_safety_margin: u64,
     #[cfg(feature = "logging")]
     logger_file_path: Option<String>,
     #[cfg(feature = "logging")]
@@ -539,19 +1220,19 @@ pub(crate) struct SequencePaxosConfig {
 
 impl From<OmniPaxosConfig> for SequencePaxosConfig {
     fn from(config: OmniPaxosConfig) -> Self {
-        let pid = config.server_config.pid;
-        let peers = config
-            .cluster_config
-            .nodes
-            .into_iter()
-            .filter(|x| *x != pid)
-            .collect();
         SequencePaxosConfig {
-            pid,
-            peers,
-            flexible_quorum: config.cluster_config.flexible_quorum,
+            pid: config.server_config.pid,
+            cluster_config: config.cluster_config,
             buffer_size: config.server_config.buffer_size,
             batch_size: config.server_config.batch_size,
+            max_pending_proposals: config.server_config.max_pending_proposals,
+            halt_on_storage_error: config.server_config.halt_on_storage_error,
+            async_flush: config.server_config.async_flush,
+            max_sync_entries: config.server_config.max_sync_entries,
+            max_sync_bytes: config.server_config.max_sync_bytes,
+            compaction_interval: config.server_config.compaction_interval,
+            compaction_log_bytes: config.server_config.compaction_log_bytes,
+            compaction_safety_margin: config.server_config.compaction_safety_margin,
             #[cfg(feature = "logging")]
             logger_file_path: config.server_config.logger_file_path,
             #[cfg(feature = "logging")]
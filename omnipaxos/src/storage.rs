@@ -1,10 +1,14 @@
 use super::ballot_leader_election::Ballot;
-#[cfg(feature = "unicache")]
-use crate::{unicache::*, util::NodeId};
 use crate::{
-    util::{AcceptedMetaData, IndexEntry, LogEntry, SnapshottedEntry},
+    errors::{StorageContext, StorageError},
+    util::{
+        defaults::SNAPSHOT_CHUNK_SIZE, AcceptedMetaData, ClientSession, ClientSessions, IndexEntry,
+        LogEntry, SnapshottedEntry,
+    },
     ClusterConfig, CompactionErr,
 };
+#[cfg(feature = "unicache")]
+use crate::{unicache::*, util::NodeId};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::{
@@ -49,6 +53,20 @@ pub trait Entry: Clone + Debug {
     #[cfg(all(feature = "unicache", feature = "serde"))]
     /// The unicache type for caching popular/re-occurring fields of an entry.
     type UniCache: UniCache<T = Self> + Serialize + for<'a> Deserialize<'a>;
+
+    /// Returns the client session of the entry if it was proposed in one. The leader does not append duplicates of the
+    /// entries of a client session, and `OmniPaxos::poll_decided()` skips any duplicates that were decided nonetheless,
+    /// e.g., when a client proposed the entry again after a leader change. See [`ClientSession`].
+    fn client_session(&self) -> Option<ClientSession> {
+        None
+    }
+
+    /// Returns an estimate of the size of the entry in bytes. The leader uses it to limit the size of the messages that
+    /// synchronize a follower, see `max_sync_bytes` in [`ServerConfig`](crate::ServerConfig). The default is the size of
+    /// the entry itself, which does not include any heap allocations, e.g., of a `String` field.
+    fn size_hint(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
 }
 
 /// A StopSign entry that marks the end of a configuration. Used for reconfiguration.
@@ -71,6 +89,27 @@ impl StopSign {
     }
 }
 
+/// A cluster configuration that was changed in place with [`OmniPaxos::change_membership`](crate::OmniPaxos::change_membership),
+/// i.e., without stopping the current configuration.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Membership {
+    /// The current `OmniPaxos` cluster configuration
+    pub config: ClusterConfig,
+    /// The number of membership changes that led to this configuration.
+    pub version: u64,
+    /// The ballot of the leader that proposed this configuration.
+    pub n: Ballot,
+}
+
+impl Membership {
+    /// Returns whether this membership should be adopted over `other`, i.e., if it was proposed in a
+    /// higher round or is a later change of the same round.
+    pub(crate) fn is_newer_than(&self, other: &Membership) -> bool {
+        (self.n, self.version) > (other.n, other.version)
+    }
+}
+
 /// Snapshot type. A `Complete` snapshot contains all snapshotted data while `Delta` has snapshotted changes since an earlier snapshot.
 #[allow(missing_docs)]
 #[derive(Clone, Debug)]
@@ -97,12 +136,170 @@ where
     /// Whether `T` is snapshottable. If not, simply return `false` and leave the other functions `unimplemented!()`.
     fn use_snapshots() -> bool;
 
-    //fn size_hint() -> u64;  // TODO: To let the system know trade-off of using entries vs snapshot?
+    /// Returns the client sessions of the snapshotted entries. Should be implemented if the entries use client sessions, so
+    /// that duplicates of compacted entries are filtered out. The sessions can be created with
+    /// [`ClientSessions::from_entries`] in `create()` and merged with [`ClientSessions::merge`] in `merge()`.
+    fn client_sessions(&self) -> Option<&ClientSessions> {
+        None
+    }
+
+    /// Returns an estimate of the size of the snapshot in bytes, comparable to [`Entry::size_hint()`]. If the entries
+    /// that a follower misses are not compacted yet, the leader synchronizes the follower with a snapshot only if it is
+    /// not larger than these entries, see [`SyncMetrics`](crate::util::SyncMetrics). If the leader already created a snapshot
+    /// of the same entries for another follower, its size hint is compared with the entries without creating it again. The default is `None`, which
+    /// means that a follower that has not decided all entries is always synchronized with a snapshot.
+    fn size_hint(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// The metadata of a snapshot of the application state that is transferred with a [`SnapshotProvider`] and a
+/// [`SnapshotInstaller`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SnapshotMetadata {
+    /// The log index of the snapshot, i.e., the snapshot contains the state after applying the entries before `idx`.
+    pub idx: u64,
+    /// The size of the snapshot in bytes.
+    pub size: u64,
+    /// The identifier of the snapshot. Different snapshots must have different ids.
+    pub id: u64,
+}
+
+/// Provides the snapshots of the application state that the leader transfers to followers whose missing entries have
+/// been compacted. Unlike a [`Snapshot`], the snapshot is not sent in a message of OmniPaxos. Only its metadata is
+/// sent and the follower pulls its bytes in chunks, so the snapshot never has to be held in memory as a whole. See
+/// [`OmniPaxos::set_snapshot_transfer()`](crate::OmniPaxos::set_snapshot_transfer).
+pub trait SnapshotProvider: Send {
+    /// Returns the metadata of the latest snapshot, or `None` if there is none. A snapshot is only offered to a follower
+    /// if its index is at least the compacted index and at most the decided index of the leader.
+    fn latest_snapshot(&mut self) -> StorageResult<Option<SnapshotMetadata>>;
+
+    /// Returns the bytes of the snapshot `metadata` from `offset`. The provider chooses the length of the chunk, which
+    /// must not be empty if `offset` is smaller than the size of the snapshot.
+    fn read_chunk(&mut self, metadata: &SnapshotMetadata, offset: u64) -> StorageResult<Vec<u8>>;
+}
+
+/// Installs the snapshots of the application state that a follower pulls from the leader, see [`SnapshotProvider`].
+pub trait SnapshotInstaller: Send {
+    /// Starts receiving the snapshot `metadata`. Any snapshot that has been partially received before is discarded.
+    fn begin(&mut self, metadata: &SnapshotMetadata) -> StorageResult<()>;
+
+    /// Writes the bytes `data` of the snapshot `metadata` at `offset`. The chunks are written in order.
+    fn write_chunk(
+        &mut self,
+        metadata: &SnapshotMetadata,
+        offset: u64,
+        data: &[u8],
+    ) -> StorageResult<()>;
+
+    /// Installs the snapshot `metadata` once all of its bytes have been written. Afterwards, the entries before
+    /// `metadata.idx` are compacted and the follower is synchronized with the entries of the leader from there.
+    fn install(&mut self, metadata: &SnapshotMetadata) -> StorageResult<()>;
+}
+
+/// The snapshots of the application state that the application hands to OmniPaxos with
+/// `OmniPaxos::install_application_snapshot()`. They are kept in memory and used as the [`SnapshotProvider`] and the
+/// [`SnapshotInstaller`] unless they are set with `OmniPaxos::set_snapshot_transfer()`.
+#[derive(Default)]
+pub(crate) struct ApplicationSnapshots {
+    latest: Option<(SnapshotMetadata, Vec<u8>)>,
+    // The bytes of the snapshot that is being received from the leader
+    received: Vec<u8>,
+    // Whether the latest snapshot was received from the leader and has not been polled by the application yet
+    installed: bool,
+}
+
+impl ApplicationSnapshots {
+    /// Sets the latest snapshot, which the application took at `idx`.
+    pub(crate) fn set(&mut self, idx: u64, snapshot: Vec<u8>) {
+        let metadata = SnapshotMetadata {
+            idx,
+            size: snapshot.len() as u64,
+            // Snapshots of the application state at the same index are the same
+            id: idx,
+        };
+        self.latest = Some((metadata, snapshot));
+        self.installed = false;
+    }
+
+    pub(crate) fn has_snapshot(&self) -> bool {
+        self.latest.is_some()
+    }
+
+    /// Returns the index and the bytes of the latest snapshot if it was received from the leader since the last call.
+    pub(crate) fn take_installed(&mut self) -> Option<(u64, Vec<u8>)> {
+        match std::mem::take(&mut self.installed) {
+            true => self
+                .latest
+                .as_ref()
+                .map(|(metadata, snapshot)| (metadata.idx, snapshot.clone())),
+            false => None,
+        }
+    }
+}
+
+impl SnapshotProvider for ApplicationSnapshots {
+    fn latest_snapshot(&mut self) -> StorageResult<Option<SnapshotMetadata>> {
+        Ok(self.latest.as_ref().map(|(metadata, _)| *metadata))
+    }
+
+    fn read_chunk(&mut self, metadata: &SnapshotMetadata, offset: u64) -> StorageResult<Vec<u8>> {
+        match &self.latest {
+            Some((latest, snapshot)) if latest == metadata => {
+                let from = (offset as usize).min(snapshot.len());
+                let to = (from + SNAPSHOT_CHUNK_SIZE).min(snapshot.len());
+                Ok(snapshot[from..to].to_vec())
+            }
+            _ => Err("The application snapshot has been replaced".into()),
+        }
+    }
+}
+
+impl SnapshotInstaller for ApplicationSnapshots {
+    fn begin(&mut self, _metadata: &SnapshotMetadata) -> StorageResult<()> {
+        self.received.clear();
+        Ok(())
+    }
+
+    fn write_chunk(
+        &mut self,
+        _metadata: &SnapshotMetadata,
+        _offset: u64,
+        data: &[u8],
+    ) -> StorageResult<()> {
+        self.received.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn install(&mut self, metadata: &SnapshotMetadata) -> StorageResult<()> {
+        self.latest = Some((*metadata, std::mem::take(&mut self.received)));
+        self.installed = true;
+        Ok(())
+    }
 }
 
 /// The Result type returned by the storage API.
 pub type StorageResult<T> = Result<T, Box<dyn Error>>;
 
+/// A write that is part of a write set, see [`Storage::write_atomically()`].
+#[derive(Clone, Debug)]
+pub enum StorageOp<T>
+where
+    T: Entry,
+{
+    /// Appends the entries to the end of the log.
+    AppendEntries(Vec<T>),
+    /// Appends the entries to the prefix from the given index in the log.
+    AppendOnPrefix(u64, Vec<T>),
+    /// Sets the round that has been promised.
+    SetPromise(Ballot),
+    /// Sets the latest accepted round.
+    SetAcceptedRound(Ballot),
+    /// Sets the decided index in the log.
+    SetDecidedIdx(u64),
+}
+
 /// Trait for implementing the storage backend of Sequence Paxos.
 pub trait Storage<T>
 where
@@ -152,6 +349,13 @@ where
     /// Returns the stored StopSign, returns `None` if no StopSign has been stored.
     fn get_stopsign(&self) -> StorageResult<Option<StopSign>>;
 
+    /// Sets the cluster configuration resulting from in-place membership changes.
+    fn set_membership(&mut self, m: Option<Membership>) -> StorageResult<()>;
+
+    /// Returns the stored cluster configuration resulting from in-place membership changes, returns `None` if
+    /// the membership has never been changed.
+    fn get_membership(&self) -> StorageResult<Option<Membership>>;
+
     /// Removes elements up to the given [`idx`] from storage.
     fn trim(&mut self, idx: u64) -> StorageResult<()>;
 
@@ -166,6 +370,43 @@ where
 
     /// Returns the stored snapshot.
     fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>>;
+
+    /// Performs the writes of `ops` in order as a single atomic write and returns the log length. A backend can
+    /// override this to make the whole write set durable at once, e.g., with a single fsync. The default
+    /// implementation performs the writes one by one.
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        let mut log_len = None;
+        for op in ops {
+            match op {
+                StorageOp::AppendEntries(entries) => log_len = Some(self.append_entries(entries)?),
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    log_len = Some(self.append_on_prefix(from_idx, entries)?)
+                }
+                StorageOp::SetPromise(n_prom) => self.set_promise(n_prom)?,
+                StorageOp::SetAcceptedRound(na) => self.set_accepted_round(na)?,
+                StorageOp::SetDecidedIdx(ld) => self.set_decided_idx(ld)?,
+            }
+        }
+        match log_len {
+            Some(len) => Ok(len),
+            None => self.get_log_len(),
+        }
+    }
+
+    /// Like [`write_atomically()`](Storage::write_atomically), but the write set does not have to be durable
+    /// when this returns. Only used if `async_flush` is enabled in the `ServerConfig`. The backend must make
+    /// the write sets durable in order, and any other write must make the earlier write sets durable before it
+    /// returns. The default implementation is synchronous.
+    fn write_atomically_async(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        self.write_atomically(ops)
+    }
+
+    /// Returns the length of the log that is durable, i.e., which entries written with
+    /// [`write_atomically_async()`](Storage::write_atomically_async) have been flushed. The backend can
+    /// also use this to flush the pending write sets. The default implementation returns the log length.
+    fn poll_flushed(&mut self) -> StorageResult<u64> {
+        self.get_log_len()
+    }
 }
 
 /// A place holder type for when not using snapshots. You should not use this type, it is only internally when deriving the Entry implementation.
@@ -221,6 +462,8 @@ where
     real_log_len: u64,
     /// Stopsign entry.
     stopsign: Option<StopSign>,
+    /// Cluster configuration of in-place membership changes.
+    membership: Option<Membership>,
     #[cfg(feature = "unicache")]
     /// Batch of entries that are processed (i.e., maybe encoded). Only used by the leader.
     batched_processed_by_leader: Vec<T::EncodeResult>,
@@ -244,6 +487,7 @@ where
             compacted_idx: 0,
             real_log_len: 0,
             stopsign: None,
+            membership: None,
             #[cfg(feature = "unicache")]
             batched_processed_by_leader: Vec::with_capacity(config.batch_size),
             #[cfg(feature = "unicache")]
@@ -351,10 +595,25 @@ where
             ),
             _t: Default::default(),
         };
-        internal_store.load_cache();
+        internal_store
+            .load_cache()
+            .expect("failed to load cache from storage");
         internal_store
     }
 
+    /// Discards the cached state, including the batched entries, and loads it from the storage again.
+    pub(crate) fn reload_cache(&mut self) -> StorageResult<()> {
+        let config = InternalStorageConfig {
+            batch_size: self.state_cache.batch_size,
+        };
+        self.state_cache = StateCache::new(
+            config,
+            #[cfg(feature = "unicache")]
+            self.state_cache.pid,
+        );
+        self.load_cache()
+    }
+
     /// Writes the value.
     pub(crate) fn single_rollback(&mut self, value: RollbackValue<T>) {
         match value {
@@ -380,28 +639,19 @@ where
         }
     }
 
-    pub(crate) fn rollback_and_panic(&mut self, values: Vec<RollbackValue<T>>, msg: &str) {
-        for value in values {
-            self.single_rollback(value);
-        }
-        panic!("{}", msg);
-    }
-
     /// This function is useful to handle `StorageResult::Error`.
-    /// If `result` is an error, this function tries to write the `values` and then panics with `msg`.
-    /// Otherwise it returns.
-    pub(crate) fn rollback_and_panic_if_err<R>(
+    /// If `result` is an error, this function tries to write the `values` and then returns the error of `operation`.
+    /// Otherwise it returns the result. Panics if the rollback fails, as the storage would be left inconsistent.
+    pub(crate) fn rollback_if_err<R>(
         &mut self,
-        result: &StorageResult<R>,
+        result: StorageResult<R>,
         values: Vec<RollbackValue<T>>,
-        msg: &str,
-    ) where
-        R: Debug,
-    {
+        operation: &'static str,
+    ) -> Result<R, StorageError> {
         if result.is_err() {
             self.rollback(values);
-            panic!("{}: {}", msg, result.as_ref().unwrap_err());
         }
+        result.context(operation)
     }
 
     /// Rollback the log in the storage using given log entries.
@@ -587,24 +837,19 @@ where
         })
     }
 
-    fn load_cache(&mut self) {
+    fn load_cache(&mut self) -> StorageResult<()> {
         // try to load from storage
-        if let Some(promise) = self
-            .storage
-            .get_promise()
-            .expect("failed to load cache from storage")
-        {
+        if let Some(promise) = self.storage.get_promise()? {
             self.state_cache.promise = promise;
-            self.state_cache.decided_idx = self.storage.get_decided_idx().unwrap();
-            self.state_cache.accepted_round = self
-                .storage
-                .get_accepted_round()
-                .unwrap()
-                .unwrap_or_default();
-            self.state_cache.compacted_idx = self.storage.get_compacted_idx().unwrap();
-            self.state_cache.real_log_len = self.storage.get_log_len().unwrap();
-            self.state_cache.stopsign = self.storage.get_stopsign().unwrap();
+            self.state_cache.decided_idx = self.storage.get_decided_idx()?;
+            self.state_cache.accepted_round =
+                self.storage.get_accepted_round()?.unwrap_or_default();
+            self.state_cache.compacted_idx = self.storage.get_compacted_idx()?;
+            self.state_cache.real_log_len = self.storage.get_log_len()?;
+            self.state_cache.stopsign = self.storage.get_stopsign()?;
+            self.state_cache.membership = self.storage.get_membership()?;
         }
+        Ok(())
     }
 
     /*** Writing ***/
@@ -672,25 +917,14 @@ where
     }
 
     #[cfg(feature = "unicache")]
-    pub(crate) fn append_encoded_entries_and_get_accepted_idx(
+    pub(crate) fn decode_entries(
         &mut self,
         encoded_entries: Vec<<T as Entry>::EncodeResult>,
-    ) -> StorageResult<Option<u64>> {
-        let entries = encoded_entries
+    ) -> Vec<T> {
+        encoded_entries
             .into_iter()
             .map(|x| self.state_cache.unicache.decode(x))
-            .collect();
-        self.append_entries_and_get_accepted_idx(entries)
-    }
-
-    pub(crate) fn flush_batch(&mut self) -> StorageResult<u64> {
-        #[cfg(feature = "unicache")]
-        {
-            // clear the processed batch
-            self.state_cache.batched_processed_by_leader.clear();
-        }
-        let flushed_entries = self.state_cache.take_batched_entries();
-        self.append_entries_without_batching(flushed_entries)
+            .collect()
     }
 
     // Append entries without batching, return the accepted index
@@ -702,15 +936,6 @@ where
         Ok(self.get_accepted_idx())
     }
 
-    pub(crate) fn append_on_decided_prefix(&mut self, entries: Vec<T>) -> StorageResult<u64> {
-        let decided_idx = self.get_decided_idx();
-        let compacted_idx = self.get_compacted_idx();
-        self.state_cache.real_log_len = self
-            .storage
-            .append_on_prefix(decided_idx - compacted_idx, entries)?;
-        Ok(self.get_accepted_idx())
-    }
-
     pub(crate) fn append_on_prefix(
         &mut self,
         from_idx: u64,
@@ -723,6 +948,78 @@ where
         Ok(self.get_accepted_idx())
     }
 
+    /// Performs `ops` as one atomic write and returns the accepted index. The indexes of
+    /// `StorageOp::AppendOnPrefix` include the compacted entries.
+    pub(crate) fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        self.write_set(ops, false)
+    }
+
+    /// Like `write_atomically()`, but the entries might not be durable yet, see `poll_flushed()`.
+    pub(crate) fn write_atomically_async(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        self.write_set(ops, true)
+    }
+
+    fn write_set(&mut self, ops: Vec<StorageOp<T>>, async_flush: bool) -> StorageResult<u64> {
+        let compacted_idx = self.get_compacted_idx();
+        let ops: Vec<StorageOp<T>> = ops
+            .into_iter()
+            .map(|op| match op {
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    StorageOp::AppendOnPrefix(from_idx - compacted_idx, entries)
+                }
+                op => op,
+            })
+            .collect();
+        let cached_ops: Vec<StorageOp<T>> = ops
+            .iter()
+            .filter(|op| {
+                !matches!(
+                    op,
+                    StorageOp::AppendEntries(_) | StorageOp::AppendOnPrefix(..)
+                )
+            })
+            .cloned()
+            .collect();
+        self.state_cache.real_log_len = match async_flush {
+            true => self.storage.write_atomically_async(ops)?,
+            false => self.storage.write_atomically(ops)?,
+        };
+        for op in cached_ops {
+            match op {
+                StorageOp::SetPromise(n_prom) => self.state_cache.promise = n_prom,
+                StorageOp::SetAcceptedRound(na) => self.state_cache.accepted_round = na,
+                StorageOp::SetDecidedIdx(ld) => self.state_cache.decided_idx = ld,
+                _ => {}
+            }
+        }
+        Ok(self.get_accepted_idx())
+    }
+
+    /// Returns the accepted index up to which the entries are durable.
+    pub(crate) fn poll_flushed(&mut self) -> StorageResult<u64> {
+        let flushed_log_len = self.storage.poll_flushed()?;
+        if flushed_log_len >= self.state_cache.real_log_len {
+            Ok(self.get_accepted_idx())
+        } else {
+            Ok(self.get_compacted_idx() + flushed_log_len)
+        }
+    }
+
+    /// Takes the batched entries if the batch is full after appending `entries`.
+    pub(crate) fn batch_entries(&mut self, entries: Vec<T>) -> Option<Vec<T>> {
+        self.state_cache.append_entries(entries)
+    }
+
+    /// Takes all batched entries.
+    pub(crate) fn take_batched_entries(&mut self) -> Vec<T> {
+        #[cfg(feature = "unicache")]
+        {
+            // clear the processed batch
+            self.state_cache.batched_processed_by_leader.clear();
+        }
+        self.state_cache.take_batched_entries()
+    }
+
     pub(crate) fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
         self.state_cache.promise = n_prom;
         self.storage.set_promise(n_prom)
@@ -737,7 +1034,7 @@ where
         self.state_cache.decided_idx
     }
 
-    fn get_decided_idx_without_stopsign(&self) -> u64 {
+    pub(crate) fn get_decided_idx_without_stopsign(&self) -> u64 {
         match self.stopsign_is_decided() {
             true => self.get_decided_idx() - 1,
             false => self.get_decided_idx(),
@@ -772,6 +1069,16 @@ where
         self.state_cache.get_accepted_idx()
     }
 
+    /// The length of the log without the stopsign, as if the log was never compacted.
+    pub(crate) fn get_log_len(&self) -> u64 {
+        self.state_cache.compacted_idx + self.state_cache.real_log_len
+    }
+
+    /// The log index of the next appended entry, taking the batched entries into account.
+    pub(crate) fn get_next_idx(&self) -> u64 {
+        self.state_cache.get_accepted_idx() + self.state_cache.batched_entries.len() as u64
+    }
+
     pub(crate) fn get_suffix(&self, from: u64) -> StorageResult<Vec<T>> {
         let compacted_idx = self.get_compacted_idx();
         self.storage.get_suffix(from - compacted_idx.min(from))
@@ -791,6 +1098,15 @@ where
         self.state_cache.stopsign.clone()
     }
 
+    pub(crate) fn set_membership(&mut self, m: Membership) -> StorageResult<()> {
+        self.state_cache.membership = Some(m.clone());
+        self.storage.set_membership(Some(m))
+    }
+
+    pub(crate) fn get_membership(&self) -> Option<Membership> {
+        self.state_cache.membership.clone()
+    }
+
     // Returns whether a stopsign is decided
     pub(crate) fn stopsign_is_decided(&self) -> bool {
         self.state_cache.stopsign_is_decided()
@@ -821,6 +1137,22 @@ where
         self.storage.get_snapshot()
     }
 
+    /// Returns the client sessions of the entries before `to_idx`, including the compacted entries.
+    pub(crate) fn get_client_sessions(&self, to_idx: u64) -> StorageResult<ClientSessions> {
+        let mut sessions = match self.get_snapshot()? {
+            Some(s) => s.client_sessions().cloned().unwrap_or_default(),
+            None => ClientSessions::default(),
+        };
+        let compacted_idx = self.get_compacted_idx();
+        if to_idx > compacted_idx {
+            let num_entries = (to_idx - compacted_idx) as usize;
+            for entry in self.get_suffix(compacted_idx)?.iter().take(num_entries) {
+                sessions.update(entry);
+            }
+        }
+        Ok(sessions)
+    }
+
     // Creates a Delta snapshot of entries from `from_idx` to the end of the decided log and also
     // returns the compacted idx of the created snapshot. If the range of entries contains entries
     // which have already been compacted a valid delta cannot be created, so creates a Complete
@@ -907,6 +1239,28 @@ where
         }
     }
 
+    /// Compacts the entries before `idx` after a snapshot of the application state has been installed, either by the
+    /// application itself or with a [`SnapshotInstaller`]. The entries are decided and the stored snapshot is reset, as
+    /// it does not contain them.
+    pub(crate) fn set_installed_snapshot(&mut self, idx: u64) -> StorageResult<()> {
+        let compacted_idx = self.get_compacted_idx();
+        if idx <= compacted_idx {
+            return Ok(());
+        }
+        let decided_idx = self.get_decided_idx();
+        if idx > decided_idx {
+            self.set_decided_idx(idx)?;
+        }
+        self.set_compacted_idx(idx)?;
+        if let Err(e) = self.storage.trim(idx - compacted_idx) {
+            self.set_compacted_idx(compacted_idx)?;
+            self.set_decided_idx(decided_idx)?;
+            return Err(e);
+        }
+        self.state_cache.real_log_len = self.storage.get_log_len()?;
+        self.reset_snapshot()
+    }
+
     pub(crate) fn set_compacted_idx(&mut self, idx: u64) -> StorageResult<()> {
         self.state_cache.compacted_idx = idx;
         self.storage.set_compacted_idx(idx)
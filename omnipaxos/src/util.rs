@@ -1,11 +1,16 @@
 use super::{
     ballot_leader_election::Ballot,
     messages::sequence_paxos::Promise,
-    storage::{Entry, SnapshotType, StopSign},
+    storage::{Entry, Membership, SnapshotType, StopSign},
 };
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering, fmt::Debug, marker::PhantomData};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    marker::PhantomData,
+};
 
 #[derive(Debug, Clone)]
 pub(crate) struct AcceptedMetaData<T: Entry> {
@@ -59,6 +64,49 @@ pub(crate) struct PromiseData<T: Entry> {
     pub suffix: Vec<T>,
 }
 
+#[derive(Debug, Clone, Copy)]
+/// The chunk of the log that is sent to a follower that is being synchronized.
+pub(crate) struct SyncProgress {
+    /// The log index where the chunk starts.
+    pub from_idx: u64,
+    /// The log index where the chunk ends.
+    pub to_idx: u64,
+    /// The sequence number of the message with the chunk.
+    pub seq_num: SequenceNumber,
+    /// Whether a resend timeout passed since the chunk was sent.
+    pub stalled: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The policy for compacting the log automatically and the size of the decided entries that are not compacted yet.
+pub(crate) struct AutoCompaction {
+    /// The number of decided entries that are not compacted after which the log is compacted.
+    pub interval: Option<u64>,
+    /// The size in bytes of the decided entries that are not compacted after which the log is compacted.
+    pub log_bytes: Option<usize>,
+    /// The number of the latest decided entries that are not compacted.
+    pub safety_margin: u64,
+    /// The log index from which the sizes of the decided entries have been summed up.
+    pub measured_from: u64,
+    /// The log index up to which the sizes of the decided entries have been summed up.
+    pub measured_to: u64,
+    /// The size in bytes of the decided entries from `measured_from` to `measured_to`.
+    pub measured_bytes: usize,
+}
+
+impl AutoCompaction {
+    pub fn is_enabled(&self) -> bool {
+        self.interval.is_some() || self.log_bytes.is_some()
+    }
+
+    /// Restarts summing up the sizes of the decided entries from `idx`.
+    pub fn reset_measurement(&mut self, idx: u64) {
+        self.measured_from = idx;
+        self.measured_to = idx;
+        self.measured_bytes = 0;
+    }
+}
+
 #[derive(Debug, Clone)]
 /// The promise state of a node.
 enum PromiseState {
@@ -80,6 +128,8 @@ where
     // the sequence number of accepts for each follower where AcceptSync has sequence number = 1
     follower_seq_nums: Vec<SequenceNumber>,
     pub accepted_indexes: Vec<u64>,
+    // The chunk that each follower that is still being synchronized has not accepted yet
+    sync_progress: Vec<Option<SyncProgress>>,
     max_promise_meta: PromiseMetaData,
     max_promise: Option<PromiseData<T>>,
     #[cfg(feature = "batch_accept")]
@@ -88,24 +138,54 @@ where
     // The number of promises needed in the prepare phase to become synced and
     // the number of accepteds needed in the accept phase to decide an entry.
     pub quorum: Quorum,
+    // The voting servers, including the leader
+    pub voters: Vec<NodeId>,
+    // The non-voting servers, which are not counted in any quorum
+    pub learners: Vec<NodeId>,
+    // The most recent membership among the promises
+    max_promise_membership: Option<Membership>,
+    // The version of the membership change of this round and the voters that accepted it
+    pending_membership: Option<(u64, Vec<NodeId>)>,
+    // The removed servers that have not accepted the membership change that removed them
+    removed_followers: Vec<NodeId>,
+    // Read requests waiting for a quorum to confirm the leadership of this round
+    pending_reads: Vec<PendingRead>,
+    read_round: u64,
+    // The proposals appended in this round that are not decided yet, as (log index, origin, id)
+    accepted_proposals: VecDeque<(u64, NodeId, ProposalId)>,
 }
 
 impl<T> LeaderState<T>
 where
     T: Entry,
 {
-    pub fn with(n_leader: Ballot, max_pid: usize, quorum: Quorum) -> Self {
+    pub fn with(
+        n_leader: Ballot,
+        max_pid: usize,
+        quorum: Quorum,
+        voters: Vec<NodeId>,
+        learners: Vec<NodeId>,
+    ) -> Self {
         Self {
             n_leader,
             promises_meta: vec![PromiseState::NotPromised; max_pid],
             follower_seq_nums: vec![SequenceNumber::default(); max_pid],
             accepted_indexes: vec![0; max_pid],
+            sync_progress: vec![None; max_pid],
             max_promise_meta: PromiseMetaData::default(),
             max_promise: None,
             #[cfg(feature = "batch_accept")]
             batch_accept_meta: vec![None; max_pid],
             max_pid,
             quorum,
+            voters,
+            learners,
+            max_promise_membership: None,
+            pending_membership: None,
+            removed_followers: vec![],
+            pending_reads: vec![],
+            read_round: 0,
+            accepted_proposals: VecDeque::new(),
         }
     }
 
@@ -113,6 +193,38 @@ where
         (pid - 1) as usize
     }
 
+    fn is_voter_idx(&self, idx: usize) -> bool {
+        self.voters.contains(&((idx + 1) as NodeId))
+    }
+
+    fn is_member_idx(&self, idx: usize) -> bool {
+        self.is_voter_idx(idx) || self.learners.contains(&((idx + 1) as NodeId))
+    }
+
+    /// Changes the servers of the configuration after a membership change.
+    pub fn set_members(
+        &mut self,
+        max_pid: usize,
+        quorum: Quorum,
+        voters: Vec<NodeId>,
+        learners: Vec<NodeId>,
+    ) {
+        if max_pid > self.max_pid {
+            self.promises_meta
+                .resize(max_pid, PromiseState::NotPromised);
+            self.follower_seq_nums
+                .resize(max_pid, SequenceNumber::default());
+            self.accepted_indexes.resize(max_pid, 0);
+            self.sync_progress.resize(max_pid, None);
+            #[cfg(feature = "batch_accept")]
+            self.batch_accept_meta.resize(max_pid, None);
+            self.max_pid = max_pid;
+        }
+        self.quorum = quorum;
+        self.voters = voters;
+        self.learners = learners;
+    }
+
     // Resets `pid`'s accept sequence to indicate they are in the next session of accepts
     pub fn increment_seq_num_session(&mut self, pid: NodeId) {
         let idx = Self::pid_to_idx(pid);
@@ -138,7 +250,14 @@ where
             pid: from,
             stopsign: prom.stopsign,
         };
-        if check_max_prom && promise_meta > self.max_promise_meta {
+        if let Some(m) = prom.membership {
+            match &self.max_promise_membership {
+                Some(max) if !m.is_newer_than(max) => {}
+                _ => self.max_promise_membership = Some(*m),
+            }
+        }
+        let is_voter = self.voters.contains(&from);
+        if check_max_prom && is_voter && promise_meta > self.max_promise_meta {
             self.max_promise_meta = promise_meta.clone();
             self.max_promise = Some(PromiseData {
                 decided_snapshot: prom.decided_snapshot,
@@ -149,18 +268,21 @@ where
         let num_promised = self
             .promises_meta
             .iter()
-            .filter(|p| matches!(p, PromiseState::Promised(_)))
+            .enumerate()
+            .filter(|(idx, p)| matches!(p, PromiseState::Promised(_)) && self.is_voter_idx(*idx))
             .count();
         self.quorum.is_prepare_quorum(num_promised)
     }
 
     pub fn reset_promise(&mut self, pid: NodeId) {
         self.promises_meta[Self::pid_to_idx(pid)] = PromiseState::NotPromised;
+        self.sync_progress[Self::pid_to_idx(pid)] = None;
     }
 
     /// Node `pid` seen with ballot greater than my ballot
     pub fn lost_promise(&mut self, pid: NodeId) {
         self.promises_meta[Self::pid_to_idx(pid)] = PromiseState::PromisedHigher;
+        self.sync_progress[Self::pid_to_idx(pid)] = None;
     }
 
     pub fn take_max_promise(&mut self) -> Option<PromiseData<T>> {
@@ -191,6 +313,9 @@ where
     pub fn get_min_all_accepted_idx(&self) -> &u64 {
         self.accepted_indexes
             .iter()
+            .enumerate()
+            .filter(|(idx, _)| self.is_member_idx(*idx))
+            .map(|(_, accepted_idx)| accepted_idx)
             .min()
             .expect("Should be all initialised to 0!")
     }
@@ -205,7 +330,9 @@ where
             .iter()
             .enumerate()
             .filter_map(|(idx, x)| match x {
-                PromiseState::Promised(_) if idx != Self::pid_to_idx(self.n_leader.pid) => {
+                PromiseState::Promised(_)
+                    if idx != Self::pid_to_idx(self.n_leader.pid) && self.is_member_idx(idx) =>
+                {
                     Some((idx + 1) as NodeId)
                 }
                 _ => None,
@@ -213,13 +340,29 @@ where
             .collect()
     }
 
+    /// Returns the promised followers that are not being synchronized, i.e., that are sent new entries.
+    pub fn get_synced_followers(&self) -> Vec<NodeId> {
+        self.get_promised_followers()
+            .into_iter()
+            .filter(|pid| self.sync_progress[Self::pid_to_idx(*pid)].is_none())
+            .collect()
+    }
+
+    pub fn get_sync_progress(&self, pid: NodeId) -> Option<SyncProgress> {
+        self.sync_progress[Self::pid_to_idx(pid)]
+    }
+
+    pub fn set_sync_progress(&mut self, pid: NodeId, progress: Option<SyncProgress>) {
+        self.sync_progress[Self::pid_to_idx(pid)] = progress;
+    }
+
     /// The pids of peers which have not promised a higher ballot than mine.
     pub fn get_preparable_peers(&self) -> Vec<NodeId> {
         self.promises_meta
             .iter()
             .enumerate()
             .filter_map(|(idx, x)| match x {
-                PromiseState::NotPromised => Some((idx + 1) as NodeId),
+                PromiseState::NotPromised if self.is_member_idx(idx) => Some((idx + 1) as NodeId),
                 _ => None,
             })
             .collect()
@@ -259,7 +402,8 @@ where
         let num_accepted = self
             .accepted_indexes
             .iter()
-            .filter(|la| **la >= idx)
+            .enumerate()
+            .filter(|(i, la)| **la >= idx && self.is_voter_idx(*i))
             .count();
         self.quorum.is_accept_quorum(num_accepted)
     }
@@ -267,6 +411,236 @@ where
     pub fn take_max_promise_stopsign(&mut self) -> Option<StopSign> {
         self.max_promise_meta.stopsign.take()
     }
+
+    pub fn take_max_promise_membership(&mut self) -> Option<Membership> {
+        self.max_promise_membership.take()
+    }
+
+    /// Starts collecting the acceptances of the voters for the membership `version`.
+    pub fn new_membership_round(&mut self, version: u64) {
+        self.pending_membership = Some((version, vec![self.n_leader.pid]));
+    }
+
+    pub fn has_pending_membership(&self) -> bool {
+        self.pending_membership.is_some()
+    }
+
+    /// Returns the members that have not accepted the pending membership change.
+    pub fn get_membership_unacked_followers(&self) -> Vec<NodeId> {
+        match &self.pending_membership {
+            Some((_, acks)) => self
+                .get_promised_followers()
+                .into_iter()
+                .filter(|pid| !acks.contains(pid))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Remembers the `followers` that are not members anymore, so that the membership change is resent to them until
+    /// they have accepted it.
+    pub fn add_removed_followers(&mut self, followers: Vec<NodeId>) {
+        let mut removed_followers = std::mem::take(&mut self.removed_followers);
+        removed_followers.extend(followers);
+        removed_followers.sort_unstable();
+        removed_followers.dedup();
+        removed_followers.retain(|pid| !self.is_member_idx(Self::pid_to_idx(*pid)));
+        self.removed_followers = removed_followers;
+    }
+
+    pub fn get_removed_followers(&self) -> Vec<NodeId> {
+        self.removed_followers.clone()
+    }
+
+    /// Registers that the removed follower `pid` accepted the membership change that removed it.
+    pub fn ack_removed_follower(&mut self, pid: NodeId) {
+        self.removed_followers.retain(|p| *p != pid);
+    }
+
+    /// Registers that `pid` accepted the membership `version`. Returns `true` if the membership
+    /// change is now accepted by a quorum of the new configuration.
+    pub fn ack_membership(&mut self, version: u64, pid: NodeId) -> bool {
+        match &mut self.pending_membership {
+            Some((v, acks)) if *v == version => {
+                if !acks.contains(&pid) && self.voters.contains(&pid) {
+                    acks.push(pid);
+                }
+                let num_acks = acks.iter().filter(|p| self.voters.contains(p)).count();
+                if self.quorum.is_accept_quorum(num_acks) {
+                    self.pending_membership = None;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Starts a new read round for the read request `id` of server `origin`. Returns the round.
+    pub fn new_read_round(&mut self, origin: NodeId, id: ReadId, read_idx: u64) -> u64 {
+        self.read_round += 1;
+        self.pending_reads.push(PendingRead {
+            round: self.read_round,
+            origin,
+            id,
+            read_idx,
+            acks: vec![self.n_leader.pid],
+        });
+        self.read_round
+    }
+
+    /// Tracks the proposal `id` of server `origin` that was appended at log index `idx` in this round.
+    pub fn add_accepted_proposal(&mut self, idx: u64, origin: NodeId, id: ProposalId) {
+        self.accepted_proposals.push_back((idx, origin, id));
+    }
+
+    /// Returns the tracked proposals that are decided with the decided index `decided_idx`.
+    pub fn take_decided_proposals(&mut self, decided_idx: u64) -> Vec<(u64, NodeId, ProposalId)> {
+        let num_decided = self
+            .accepted_proposals
+            .iter()
+            .take_while(|(idx, _, _)| *idx < decided_idx)
+            .count();
+        self.accepted_proposals.drain(..num_decided).collect()
+    }
+
+    /// Registers that `pid` acknowledged read round `round`. Returns the read if it is now
+    /// confirmed by a quorum.
+    pub fn ack_read(&mut self, round: u64, pid: NodeId) -> Option<PendingRead> {
+        let pos = self.pending_reads.iter().position(|r| r.round == round)?;
+        let read = &mut self.pending_reads[pos];
+        if !read.acks.contains(&pid) && self.voters.contains(&pid) {
+            read.acks.push(pid);
+        }
+        if self.quorum.is_accept_quorum(read.acks.len()) {
+            Some(self.pending_reads.remove(pos))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A read request waiting for a quorum to confirm the leadership of the leader.
+pub(crate) struct PendingRead {
+    pub round: u64,
+    pub origin: NodeId,
+    pub id: ReadId,
+    pub read_idx: u64,
+    acks: Vec<NodeId>,
+}
+
+/// A read request that has been confirmed by the leader. The read is linearizable if it is served
+/// once the decided index of the server that requested it has reached `read_idx`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReadIndex {
+    /// The id of the read request.
+    pub id: ReadId,
+    /// The index in the log that must be decided before the read can be served.
+    pub read_idx: u64,
+}
+
+/// Counters of how the leader has synchronized followers, e.g., to tune [`Snapshot::size_hint()`](crate::storage::Snapshot::size_hint).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SyncMetrics {
+    /// The number of times a follower was synchronized with a snapshot and the entries after it.
+    pub snapshot_syncs: u64,
+    /// The number of times a follower was synchronized with only log entries.
+    pub log_syncs: u64,
+    /// The number of times a follower was synchronized with log entries because the snapshot would have been larger
+    /// according to the size hints. Included in `log_syncs`.
+    pub larger_snapshots: u64,
+}
+
+/// The outcome of a proposal made with `OmniPaxos::append()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProposalOutcome {
+    /// The id of the proposal.
+    pub id: ProposalId,
+    /// Whether the proposed entry was decided.
+    pub status: ProposalStatus,
+}
+
+/// The status of a proposal that is no longer in progress.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProposalStatus {
+    /// The entry was decided at the given log index.
+    Decided(u64),
+    /// The outcome of the entry is unknown, e.g., because the leader that it was sent to was replaced or the log was
+    /// reconfigured. The entry may still be decided, since it might have been accepted by a majority, but no further
+    /// outcome is reported for it. A dropped entry that is retried should therefore be idempotent, e.g., with a
+    /// [`ClientSession`].
+    Dropped,
+    /// The entry was not appended since the log already contains an entry of the same client session with an equal or
+    /// higher sequence number. See [`ClientSession`].
+    Duplicate,
+}
+
+/// The client session of an entry, which is used to filter out duplicates of entries that are proposed again by a client,
+/// e.g., after the leader crashed before the client learned the outcome of its proposal. A client should number its entries
+/// with increasing sequence numbers and not propose an entry before the outcome of its previous entry is known, since any
+/// entry with a sequence number that is not higher than that of an earlier entry of the same client is a duplicate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClientSession {
+    /// The id of the client that proposed the entry.
+    pub client_id: ClientId,
+    /// The sequence number of the entry among the entries of the client.
+    pub seq_num: u64,
+}
+
+/// The highest sequence number of each client in a sequence of entries, used to filter out duplicate entries. To filter out
+/// duplicates of entries that have been compacted, the snapshots must include the client sessions of their entries, see
+/// [`Snapshot::client_sessions`](crate::storage::Snapshot::client_sessions).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClientSessions {
+    seq_nums: HashMap<ClientId, u64>,
+}
+
+impl ClientSessions {
+    /// Creates the client sessions of `entries`.
+    pub fn from_entries<T: Entry>(entries: &[T]) -> Self {
+        let mut sessions = Self::default();
+        for entry in entries {
+            sessions.update(entry);
+        }
+        sessions
+    }
+
+    /// Returns whether `entry` is a duplicate of an entry in these sessions.
+    pub fn is_duplicate<T: Entry>(&self, entry: &T) -> bool {
+        match entry.client_session() {
+            Some(s) => {
+                matches!(self.seq_nums.get(&s.client_id), Some(seq_num) if s.seq_num <= *seq_num)
+            }
+            None => false,
+        }
+    }
+
+    /// Adds `entry` to the sessions. Returns `false` if `entry` is a duplicate.
+    pub fn update<T: Entry>(&mut self, entry: &T) -> bool {
+        if self.is_duplicate(entry) {
+            return false;
+        }
+        if let Some(s) = entry.client_session() {
+            self.seq_nums.insert(s.client_id, s.seq_num);
+        }
+        true
+    }
+
+    /// Merges `other` into these sessions, e.g., when merging a delta snapshot.
+    pub fn merge(&mut self, other: &ClientSessions) {
+        for (client_id, seq_num) in &other.seq_nums {
+            let s = self.seq_nums.entry(*client_id).or_default();
+            *s = (*s).max(*seq_num);
+        }
+    }
 }
 
 /// The entry read in the log.
@@ -350,6 +724,7 @@ pub(crate) mod defaults {
     pub(crate) const BLE_BUFFER_SIZE: usize = 100;
     pub(crate) const ELECTION_TIMEOUT: u64 = 10;
     pub(crate) const RESEND_MESSAGE_TIMEOUT: u64 = 100;
+    pub(crate) const SNAPSHOT_CHUNK_SIZE: usize = 1024 * 1024;
 }
 
 #[allow(missing_docs)]
@@ -357,6 +732,13 @@ pub type TrimmedIndex = u64;
 
 /// ID for an OmniPaxos node
 pub type NodeId = u64;
+/// ID for a read request
+pub type ReadId = u64;
+/// ID for a proposal made on a server. The ids are not persisted, i.e., they start again from 0 after the server is
+/// restarted.
+pub type ProposalId = u64;
+/// ID for a client that proposes entries in a [`ClientSession`]
+pub type ClientId = u64;
 /// ID for an OmniPaxos configuration (i.e., the set of servers in an OmniPaxos cluster)
 pub type ConfigurationId = u32;
 
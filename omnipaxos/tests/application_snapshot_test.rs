@@ -0,0 +1,149 @@
+//! Tests for compacting the log with snapshots of the application state with `OmniPaxos::install_application_snapshot`.
+//! The servers are driven directly so that the messages to the follower can be inspected.
+pub mod utils;
+
+#[cfg(not(feature = "unicache"))]
+use omnipaxos::macros::Entry;
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    util::{LogEntry, NodeId},
+    CompactionErr, ServerConfig,
+};
+use utils::{
+    direct::{create_server, create_servers, deliver, follower_of, wait_for_leader, Servers},
+    Value,
+};
+
+const NUM_PROPOSALS: u64 = 100;
+// Larger than the chunks that the snapshot is transferred in
+const SNAPSHOT_SIZE: usize = 3 << 20;
+
+/// An entry that can not be snapshotted.
+#[cfg(not(feature = "unicache"))]
+#[derive(Entry, Clone, Debug)]
+struct PlainValue;
+
+/// Starts the servers and decides `NUM_PROPOSALS` entries. Returns the servers, the leader, and a follower.
+fn decide_entries() -> (Servers<Value>, NodeId, NodeId) {
+    let mut servers = create_servers(ServerConfig::default());
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    for i in 0..NUM_PROPOSALS {
+        servers
+            .get_mut(&leader)
+            .unwrap()
+            .append(Value::with_id(i))
+            .expect("Failed to append");
+    }
+    deliver(&mut servers, |_| false);
+    assert_eq!(servers[&leader].get_decided_idx(), NUM_PROPOSALS);
+    (servers, leader, follower)
+}
+
+/// Verifies that a follower whose missing entries have been compacted by the leader receives the snapshot of the
+/// application state and the rest of the log.
+#[test]
+fn application_snapshot_transfer_test() {
+    let (mut servers, leader, follower) = decide_entries();
+    let snapshot: Vec<u8> = (0..SNAPSHOT_SIZE).map(|i| i as u8).collect();
+    servers
+        .get_mut(&leader)
+        .unwrap()
+        .install_application_snapshot(NUM_PROPOSALS, snapshot.clone())
+        .expect("Failed to install snapshot");
+    assert_eq!(servers[&leader].get_compacted_idx(), NUM_PROPOSALS);
+    for i in NUM_PROPOSALS..NUM_PROPOSALS + 10 {
+        servers
+            .get_mut(&leader)
+            .unwrap()
+            .append(Value::with_id(i))
+            .expect("Failed to append");
+    }
+    deliver(&mut servers, |_| false);
+
+    // The follower loses its storage and asks the leader to be synchronized
+    servers.insert(follower, create_server(follower, ServerConfig::default()));
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    let msgs = deliver(&mut servers, |_| false);
+    let num_chunks = msgs
+        .iter()
+        .filter(|msg| matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::SnapshotChunk(_))))
+        .count();
+    assert!(
+        num_chunks > 1,
+        "The snapshot was sent in {} chunks",
+        num_chunks
+    );
+
+    let server = servers.get_mut(&follower).unwrap();
+    assert_eq!(server.get_compacted_idx(), NUM_PROPOSALS);
+    assert_eq!(server.get_decided_idx(), NUM_PROPOSALS + 10);
+    assert_eq!(
+        server.poll_application_snapshot(),
+        Some((NUM_PROPOSALS, snapshot))
+    );
+    assert_eq!(server.poll_application_snapshot(), None);
+    let decided = server.poll_decided();
+    assert!(matches!(decided.first(), Some(LogEntry::Trimmed(idx)) if *idx == NUM_PROPOSALS));
+    assert_eq!(decided.len(), 11);
+}
+
+/// Verifies that only the decided and not yet compacted entries can be compacted with a snapshot of the application
+/// state, and that only the local log is compacted.
+#[test]
+fn install_application_snapshot_test() {
+    let (mut servers, leader, follower) = decide_entries();
+    let server = servers.get_mut(&follower).unwrap();
+    assert!(matches!(
+        server.install_application_snapshot(NUM_PROPOSALS + 1, vec![1]),
+        Err(CompactionErr::UndecidedIndex(NUM_PROPOSALS))
+    ));
+    server
+        .install_application_snapshot(NUM_PROPOSALS / 2, vec![1])
+        .expect("Failed to install snapshot");
+    assert_eq!(server.get_compacted_idx(), NUM_PROPOSALS / 2);
+    assert!(matches!(
+        server.install_application_snapshot(NUM_PROPOSALS / 4, vec![2]),
+        Err(CompactionErr::TrimmedIndex(idx)) if idx == NUM_PROPOSALS / 2
+    ));
+    // Snapshots installed by the application itself are not returned by `poll_application_snapshot`
+    assert_eq!(server.poll_application_snapshot(), None);
+    deliver(&mut servers, |_| false);
+    assert_eq!(servers[&leader].get_compacted_idx(), 0);
+}
+
+/// Verifies that a leader that has compacted its log without a snapshot does not synchronize a follower that needs the
+/// compacted entries, instead of sending it the log from the compacted index.
+#[cfg(not(feature = "unicache"))]
+#[test]
+fn compacted_without_snapshot_test() {
+    let mut servers: Servers<PlainValue> = create_servers(ServerConfig::default());
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    for _ in 0..NUM_PROPOSALS {
+        servers
+            .get_mut(&leader)
+            .unwrap()
+            .append(PlainValue)
+            .expect("Failed to append");
+    }
+    deliver(&mut servers, |_| false);
+    servers
+        .get_mut(&leader)
+        .unwrap()
+        .trim(Some(NUM_PROPOSALS))
+        .expect("Failed to trim");
+    deliver(&mut servers, |_| false);
+
+    // The follower loses its storage and is prepared again, but never synchronized
+    servers.insert(follower, create_server(follower, ServerConfig::default()));
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    for _ in 0..50 {
+        servers.values_mut().for_each(|s| s.tick());
+        let msgs = deliver(&mut servers, |_| false);
+        assert!(!msgs.iter().any(|msg| msg.get_receiver() == follower
+            && matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::AcceptSync(_)))));
+    }
+    assert_eq!(servers[&follower].get_decided_idx(), 0);
+    assert_eq!(servers[&leader].get_decided_idx(), NUM_PROPOSALS);
+}
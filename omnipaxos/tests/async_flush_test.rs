@@ -0,0 +1,280 @@
+//! Tests for followers that write the accepted entries asynchronously with `async_flush`. The servers are driven
+//! directly so that the flushes of the followers can be held back.
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Membership, StopSign, Storage, StorageOp, StorageResult},
+    util::NodeId,
+    OmniPaxosConfig, ServerConfig,
+};
+use omnipaxos_storage::{
+    memory_storage::MemoryStorage,
+    persistent_storage::{PersistentStorage, PersistentStorageConfig},
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use utils::{
+    create_temp_dir,
+    direct::{self, tick, wait_for_leader, Servers, NUM_NODES},
+    Value, ValueSnapshot,
+};
+
+const NUM_PROPOSALS: u64 = 5;
+
+/// A `MemoryStorage` whose asynchronous writes are only reported as flushed while `hold_flush` is not set.
+struct DelayedFlushStorage {
+    storage: MemoryStorage<Value>,
+    hold_flush: Arc<AtomicBool>,
+    flushed_len: u64,
+}
+
+impl DelayedFlushStorage {
+    fn with(hold_flush: Arc<AtomicBool>) -> Self {
+        Self {
+            storage: MemoryStorage::default(),
+            hold_flush,
+            flushed_len: 0,
+        }
+    }
+
+    // Any synchronous write makes the earlier asynchronous writes durable
+    fn flush(&mut self) -> StorageResult<()> {
+        self.flushed_len = self.storage.get_log_len()?;
+        Ok(())
+    }
+}
+
+impl Storage<Value> for DelayedFlushStorage {
+    fn append_entry(&mut self, entry: Value) -> StorageResult<u64> {
+        self.flush()?;
+        self.storage.append_entry(entry)
+    }
+
+    fn append_entries(&mut self, entries: Vec<Value>) -> StorageResult<u64> {
+        self.flush()?;
+        self.storage.append_entries(entries)
+    }
+
+    fn append_on_prefix(&mut self, from_idx: u64, entries: Vec<Value>) -> StorageResult<u64> {
+        self.flush()?;
+        self.storage.append_on_prefix(from_idx, entries)
+    }
+
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_promise(n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: u64) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_decided_idx(ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<u64> {
+        self.storage.get_decided_idx()
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_accepted_round(na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_accepted_round()
+    }
+
+    fn get_entries(&self, from: u64, to: u64) -> StorageResult<Vec<Value>> {
+        self.storage.get_entries(from, to)
+    }
+
+    fn get_log_len(&self) -> StorageResult<u64> {
+        self.storage.get_log_len()
+    }
+
+    fn get_suffix(&self, from: u64) -> StorageResult<Vec<Value>> {
+        self.storage.get_suffix(from)
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_promise()
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_stopsign(s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        self.storage.get_stopsign()
+    }
+
+    fn set_membership(&mut self, m: Option<Membership>) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_membership(m)
+    }
+
+    fn get_membership(&self) -> StorageResult<Option<Membership>> {
+        self.storage.get_membership()
+    }
+
+    fn trim(&mut self, idx: u64) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.trim(idx)
+    }
+
+    fn set_compacted_idx(&mut self, idx: u64) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_compacted_idx(idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<u64> {
+        self.storage.get_compacted_idx()
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<ValueSnapshot>) -> StorageResult<()> {
+        self.flush()?;
+        self.storage.set_snapshot(snapshot)
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<ValueSnapshot>> {
+        self.storage.get_snapshot()
+    }
+
+    fn write_atomically(&mut self, ops: Vec<StorageOp<Value>>) -> StorageResult<u64> {
+        let log_len = self.storage.write_atomically(ops)?;
+        self.flushed_len = log_len;
+        Ok(log_len)
+    }
+
+    fn write_atomically_async(&mut self, ops: Vec<StorageOp<Value>>) -> StorageResult<u64> {
+        self.storage.write_atomically(ops)
+    }
+
+    fn poll_flushed(&mut self) -> StorageResult<u64> {
+        if !self.hold_flush.load(Ordering::SeqCst) {
+            self.flush()?;
+        }
+        Ok(self.flushed_len)
+    }
+}
+
+fn server_config(pid: NodeId) -> OmniPaxosConfig {
+    direct::server_config(
+        pid,
+        ServerConfig {
+            async_flush: true,
+            ..Default::default()
+        },
+    )
+}
+
+/// Verifies that followers only accept entries once their storage reports them as flushed, so that the leader
+/// cannot decide entries that are not durable on a quorum.
+#[test]
+fn accepted_after_flush_test() {
+    let hold_flush: HashMap<NodeId, Arc<AtomicBool>> = (1..=NUM_NODES)
+        .map(|pid| (pid, Arc::new(AtomicBool::new(false))))
+        .collect();
+    let mut servers: Servers<Value, DelayedFlushStorage> = (1..=NUM_NODES)
+        .map(|pid| {
+            let storage = DelayedFlushStorage::with(hold_flush[&pid].clone());
+            (pid, server_config(pid).build(storage).unwrap())
+        })
+        .collect();
+    let leader = wait_for_leader(&mut servers);
+    let decided_idx = servers[&leader].get_decided_idx();
+
+    hold_flush
+        .iter()
+        .filter(|(pid, _)| **pid != leader)
+        .for_each(|(_, hold)| hold.store(true, Ordering::SeqCst));
+    let server = servers.get_mut(&leader).unwrap();
+    for i in 0..NUM_PROPOSALS {
+        server.append(Value::with_id(i)).expect("Failed to append");
+    }
+    for _ in 0..5 {
+        tick(&mut servers);
+    }
+    for (pid, server) in &servers {
+        assert_eq!(
+            server.get_decided_idx(),
+            decided_idx,
+            "Server {} decided entries that were not flushed by a quorum",
+            pid
+        );
+    }
+
+    hold_flush
+        .values()
+        .for_each(|hold| hold.store(false, Ordering::SeqCst));
+    for _ in 0..5 {
+        tick(&mut servers);
+    }
+    for (pid, server) in &servers {
+        assert_eq!(
+            server.get_decided_idx(),
+            decided_idx + NUM_PROPOSALS,
+            "Server {} did not decide the flushed entries",
+            pid
+        );
+    }
+}
+
+/// Verifies that entries are decided with `async_flush` on `PersistentStorage` and that the decided entries are
+/// durable when the storage is opened again.
+#[test]
+fn persistent_async_flush_test() {
+    let temp_dir = create_temp_dir();
+    let storage_config = |pid: NodeId| {
+        let path = format!("{temp_dir}/server{pid}");
+        let log_opts = commitlog::LogOptions::new(format!("{path}/commitlog/"));
+        PersistentStorageConfig::with(path, log_opts, sled::Config::new())
+    };
+    let mut servers: Servers<Value, PersistentStorage<Value>> = (1..=NUM_NODES)
+        .map(|pid| {
+            let storage = PersistentStorage::open(storage_config(pid));
+            (pid, server_config(pid).build(storage).unwrap())
+        })
+        .collect();
+    let leader = wait_for_leader(&mut servers);
+    let decided_idx = servers[&leader].get_decided_idx();
+    let server = servers.get_mut(&leader).unwrap();
+    for i in 0..NUM_PROPOSALS {
+        server.append(Value::with_id(i)).expect("Failed to append");
+    }
+    for _ in 0..5 {
+        tick(&mut servers);
+    }
+    for (pid, server) in &servers {
+        assert_eq!(
+            server.get_decided_idx(),
+            decided_idx + NUM_PROPOSALS,
+            "Server {} did not decide the entries",
+            pid
+        );
+    }
+
+    drop(servers);
+    for pid in 1..=NUM_NODES {
+        let storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(pid));
+        assert_eq!(
+            storage.get_log_len().unwrap(),
+            decided_idx + NUM_PROPOSALS,
+            "Server {} lost entries",
+            pid
+        );
+        let entries = storage
+            .get_entries(decided_idx, decided_idx + NUM_PROPOSALS)
+            .unwrap();
+        assert_eq!(
+            entries,
+            (0..NUM_PROPOSALS).map(Value::with_id).collect::<Vec<_>>()
+        );
+    }
+}
@@ -130,6 +130,7 @@ fn _setup_leader() -> (
             n_accepted: n_old,
             decided_snapshot: None,
             stopsign: None,
+            membership: None,
         }),
     });
     op.handle_incoming(setup_msg);
@@ -186,6 +187,7 @@ fn setup_follower() -> (
             sync_idx: 0,
             decided_idx: 0,
             stopsign: None,
+            membership: None,
             #[cfg(feature = "unicache")]
             unicache: <Value as Entry>::UniCache::new(),
         }),
@@ -241,6 +243,7 @@ fn atomic_storage_acceptsync_test() {
                 sync_idx: 0,
                 decided_idx: 1,
                 stopsign: None,
+                membership: None,
                 #[cfg(feature = "unicache")]
                 unicache: <Value as Entry>::UniCache::new(),
             }),
@@ -555,6 +558,7 @@ fn atomic_storage_majority_promises_test() {
                     Value::with_id(2),
                 ]))),
                 stopsign: None,
+                membership: None,
             }),
         });
         let _res = catch_unwind(AssertUnwindSafe(|| op.handle_incoming(msg.clone())));
@@ -0,0 +1,178 @@
+//! Tests for compacting the log automatically in `tick()` with `compaction_interval`, `compaction_log_bytes` and
+//! `compaction_safety_margin`. The servers are driven directly so that the messages of a follower can be dropped.
+#![cfg(not(feature = "unicache"))]
+pub mod utils;
+
+use omnipaxos::{
+    messages::Message,
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+use utils::{
+    direct::{
+        create_servers, deliver, follower_of, tick, wait_for_leader, Servers, MAX_TICKS, NUM_NODES,
+    },
+    Value,
+};
+
+/// An entry without snapshots, so that the log can only be trimmed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Command(u64);
+
+impl Entry for Command {
+    type Snapshot = NoSnapshot;
+}
+
+fn propose<T: Entry>(servers: &mut Servers<T>, leader: NodeId, entries: impl Iterator<Item = T>) {
+    let server = servers.get_mut(&leader).unwrap();
+    for entry in entries {
+        server.append(entry).expect("Failed to append");
+    }
+    deliver(servers, |_| false);
+}
+
+fn compacted_indexes<T: Entry>(servers: &Servers<T>) -> Vec<u64> {
+    (1..=NUM_NODES)
+        .map(|pid| servers[&pid].get_compacted_idx())
+        .collect()
+}
+
+/// Verifies that the leader snapshots its log once `compaction_interval` entries are decided and that the followers
+/// snapshot their logs too.
+#[test]
+fn snapshot_interval_test() {
+    let mut servers = create_servers(ServerConfig {
+        compaction_interval: Some(10),
+        ..Default::default()
+    });
+    let leader = wait_for_leader(&mut servers);
+    propose(&mut servers, leader, (0..9).map(Value::with_id));
+    tick(&mut servers);
+    assert_eq!(compacted_indexes(&servers), vec![0; NUM_NODES as usize]);
+
+    propose(&mut servers, leader, (9..12).map(Value::with_id));
+    tick(&mut servers);
+    assert_eq!(compacted_indexes(&servers), vec![12; NUM_NODES as usize]);
+    for pid in 1..=NUM_NODES {
+        let entries = servers[&pid].read_decided_suffix(0).unwrap();
+        assert!(matches!(entries.as_slice(), [LogEntry::Snapshotted(s)] if s.trimmed_idx == 12));
+    }
+}
+
+/// Verifies that the log is snapshotted once the decided entries exceed `compaction_log_bytes`, keeping the latest
+/// `compaction_safety_margin` entries.
+#[test]
+fn snapshot_log_bytes_test() {
+    let entry_size = Value::with_id(0).size_hint();
+    let mut servers = create_servers(ServerConfig {
+        compaction_log_bytes: Some(10 * entry_size),
+        compaction_safety_margin: 3,
+        ..Default::default()
+    });
+    let leader = wait_for_leader(&mut servers);
+    for i in 0..9 {
+        propose(&mut servers, leader, std::iter::once(Value::with_id(i)));
+        tick(&mut servers);
+    }
+    assert_eq!(compacted_indexes(&servers), vec![0; NUM_NODES as usize]);
+
+    propose(&mut servers, leader, std::iter::once(Value::with_id(9)));
+    tick(&mut servers);
+    assert_eq!(compacted_indexes(&servers), vec![7; NUM_NODES as usize]);
+    // Only 3 decided entries are left in the log
+    propose(&mut servers, leader, (10..16).map(Value::with_id));
+    tick(&mut servers);
+    assert_eq!(compacted_indexes(&servers), vec![7; NUM_NODES as usize]);
+}
+
+/// Verifies that without snapshots, the leader only trims the entries that all servers have accepted.
+#[test]
+fn trim_accepted_entries_test() {
+    let mut servers = create_servers(ServerConfig {
+        compaction_interval: Some(10),
+        compaction_safety_margin: 5,
+        ..Default::default()
+    });
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    let is_follower =
+        |msg: &Message<Command>| msg.get_receiver() == follower || msg.get_sender() == follower;
+    let server = servers.get_mut(&leader).unwrap();
+    for i in 0..20 {
+        server.append(Command(i)).expect("Failed to append");
+    }
+    deliver(&mut servers, is_follower);
+    servers.values_mut().for_each(|s| s.tick());
+    deliver(&mut servers, is_follower);
+    assert_eq!(servers[&leader].get_decided_idx(), 20);
+    assert_eq!(compacted_indexes(&servers), vec![0; NUM_NODES as usize]);
+
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    servers.get_mut(&leader).unwrap().reconnected(follower);
+    for _ in 0..MAX_TICKS {
+        tick(&mut servers);
+        if compacted_indexes(&servers) == vec![15; NUM_NODES as usize] {
+            return;
+        }
+    }
+    panic!(
+        "The log was not trimmed on all servers: {:?}",
+        compacted_indexes(&servers)
+    );
+}
+
+/// Verifies that the leader only snapshots the entries that all servers have accepted, so that a follower that is
+/// behind can be synchronized with the entries.
+#[test]
+fn snapshot_accepted_entries_test() {
+    let mut servers = create_servers(ServerConfig {
+        compaction_interval: Some(10),
+        ..Default::default()
+    });
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    let is_follower =
+        |msg: &Message<Value>| msg.get_receiver() == follower || msg.get_sender() == follower;
+    let server = servers.get_mut(&leader).unwrap();
+    for i in 0..20 {
+        server.append(Value::with_id(i)).expect("Failed to append");
+    }
+    deliver(&mut servers, is_follower);
+    servers.values_mut().for_each(|s| s.tick());
+    deliver(&mut servers, is_follower);
+    assert_eq!(servers[&leader].get_decided_idx(), 20);
+    assert_eq!(compacted_indexes(&servers), vec![0; NUM_NODES as usize]);
+
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    servers.get_mut(&leader).unwrap().reconnected(follower);
+    for _ in 0..MAX_TICKS {
+        tick(&mut servers);
+        if compacted_indexes(&servers) == vec![20; NUM_NODES as usize] {
+            return;
+        }
+    }
+    panic!(
+        "The log was not snapshotted on all servers: {:?}",
+        compacted_indexes(&servers)
+    );
+}
+
+#[test]
+fn validate_compaction_config_test() {
+    for server_config in [
+        ServerConfig {
+            pid: 1,
+            compaction_interval: Some(0),
+            ..Default::default()
+        },
+        ServerConfig {
+            pid: 1,
+            compaction_log_bytes: Some(0),
+            ..Default::default()
+        },
+    ] {
+        assert!(server_config.validate().is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! Tests for limiting the number of pending proposals of a server. The servers are driven directly so that the
+//! messages to the leader can be held back.
+pub mod utils;
+
+use omnipaxos::{
+    messages::{
+        sequence_paxos::{PaxosMessage, PaxosMsg, Proposal},
+        Message,
+    },
+    util::{ProposalOutcome, ProposalStatus},
+    ProposeErr, ServerConfig,
+};
+use utils::{
+    direct::{self, follower_of, tick, wait_for_leader, Servers},
+    Value,
+};
+
+const MAX_PENDING_PROPOSALS: usize = 2;
+
+fn create_servers() -> Servers<Value> {
+    direct::create_servers(ServerConfig {
+        max_pending_proposals: Some(MAX_PENDING_PROPOSALS),
+        ..Default::default()
+    })
+}
+
+/// Verifies that proposals are rejected with `NoLeader` once `max_pending_proposals` proposals are buffered
+/// while there is no leader, and that the buffered proposals are decided once a leader is elected.
+#[test]
+fn no_leader_backpressure_test() {
+    let mut servers = create_servers();
+    let server = servers.get_mut(&1).unwrap();
+    for i in 0..MAX_PENDING_PROPOSALS as u64 {
+        server
+            .append(Value::with_id(i))
+            .expect("Failed to append before reaching the limit");
+    }
+    match server.append(Value::with_id(MAX_PENDING_PROPOSALS as u64)) {
+        Err(ProposeErr::NoLeader(v)) => assert_eq!(v, Value::with_id(MAX_PENDING_PROPOSALS as u64)),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+
+    wait_for_leader(&mut servers);
+    tick(&mut servers);
+    let server = servers.get_mut(&1).unwrap();
+    let outcomes = server.proposal_outcomes();
+    assert_eq!(outcomes.len(), MAX_PENDING_PROPOSALS);
+    assert!(outcomes
+        .iter()
+        .all(|o| matches!(o.status, ProposalStatus::Decided(_))));
+    server
+        .append(Value::with_id(MAX_PENDING_PROPOSALS as u64))
+        .expect("Failed to append after the pending proposals were decided");
+}
+
+/// Verifies that a follower rejects proposals with `Overloaded` once the leader has not decided
+/// `max_pending_proposals` of its proposals, and accepts proposals again once they are decided.
+#[test]
+fn overloaded_backpressure_test() {
+    let mut servers = create_servers();
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+
+    // The forwarded proposals are held back in the outgoing messages of the follower until the next tick
+    let server = servers.get_mut(&follower).unwrap();
+    for i in 0..MAX_PENDING_PROPOSALS as u64 {
+        server
+            .append(Value::with_id(i))
+            .expect("Failed to append before reaching the limit");
+    }
+    match server.append(Value::with_id(MAX_PENDING_PROPOSALS as u64)) {
+        Err(ProposeErr::Overloaded(v)) => {
+            assert_eq!(v, Value::with_id(MAX_PENDING_PROPOSALS as u64))
+        }
+        res => panic!("Unexpected result: {:?}", res),
+    }
+
+    tick(&mut servers);
+    let server = servers.get_mut(&follower).unwrap();
+    assert_eq!(server.proposal_outcomes().len(), MAX_PENDING_PROPOSALS);
+    server
+        .append(Value::with_id(MAX_PENDING_PROPOSALS as u64))
+        .expect("Failed to append after the pending proposals were decided");
+}
+
+/// Verifies that a server without a leader buffers at most `max_pending_proposals` proposals forwarded by another
+/// server and reports the rest to it as dropped.
+#[test]
+fn forwarded_backpressure_test() {
+    let mut servers = create_servers();
+    let server = servers.get_mut(&1).unwrap();
+    let proposals = (1..=MAX_PENDING_PROPOSALS as u64 + 1)
+        .map(|id| Proposal {
+            origin: 2,
+            id,
+            entry: Value::with_id(id),
+        })
+        .collect();
+    server.handle_incoming(Message::SequencePaxos(PaxosMessage {
+        from: 2,
+        to: 1,
+        msg: PaxosMsg::ProposalForward(proposals),
+    }));
+    let dropped: Vec<_> = server
+        .outgoing_messages()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            Message::SequencePaxos(PaxosMessage {
+                to: 2,
+                msg: PaxosMsg::ProposalOutcomes(outcomes),
+                ..
+            }) => Some(outcomes),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    assert_eq!(
+        dropped,
+        vec![ProposalOutcome {
+            id: MAX_PENDING_PROPOSALS as u64 + 1,
+            status: ProposalStatus::Dropped,
+        }]
+    );
+}
+
+/// Verifies that the proposals sent to the leader are reported as dropped when a follower reconnects to it, and that
+/// they are not reported again if they are decided nonetheless.
+#[test]
+fn reconnect_drops_unresolved_test() {
+    let mut servers = create_servers();
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    let server = servers.get_mut(&follower).unwrap();
+    let id = server.append(Value::with_id(1)).expect("Failed to append");
+    server.reconnected(leader);
+    assert_eq!(
+        server.proposal_outcomes(),
+        vec![ProposalOutcome {
+            id,
+            status: ProposalStatus::Dropped,
+        }]
+    );
+
+    tick(&mut servers);
+    assert_eq!(servers[&leader].get_decided_idx(), 1);
+    assert!(servers
+        .get_mut(&follower)
+        .unwrap()
+        .proposal_outcomes()
+        .is_empty());
+}
@@ -0,0 +1,234 @@
+//! Tests for synchronizing a follower that is far behind in chunks with `max_sync_entries` and `max_sync_bytes`. The
+//! servers are driven directly so that the messages to the follower can be inspected and dropped.
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::Entry,
+    util::{LogEntry, NodeId},
+    ServerConfig,
+};
+use utils::{
+    direct::{self, deliver_round, follower_of, tick, wait_for_leader, MAX_TICKS},
+    Value,
+};
+
+const NUM_PROPOSALS: u64 = 100;
+const MAX_SYNC_ENTRIES: usize = 10;
+
+type Servers = direct::Servers<Value>;
+
+fn create_servers(max_sync_entries: Option<usize>, max_sync_bytes: Option<usize>) -> Servers {
+    direct::create_servers(ServerConfig {
+        max_sync_entries,
+        max_sync_bytes,
+        ..Default::default()
+    })
+}
+
+/// Delivers messages until there are none left and returns the number of entries of each message that synchronized
+/// `follower`.
+fn deliver(servers: &mut Servers, follower: NodeId) -> Vec<usize> {
+    direct::deliver(servers, |_| false)
+        .iter()
+        .filter_map(|msg| sync_msg_len(msg, follower))
+        .collect()
+}
+
+/// Returns the number of entries in `msg` if it is a message that synchronizes `follower`.
+fn sync_msg_len(msg: &Message<Value>, follower: NodeId) -> Option<usize> {
+    match msg {
+        Message::SequencePaxos(m) if m.to == follower => match &m.msg {
+            PaxosMsg::AcceptSync(a) => Some(a.suffix.len()),
+            PaxosMsg::AcceptSyncChunk(c) => Some(c.entries.len()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Decides `NUM_PROPOSALS` entries while the messages to and from the follower are dropped, and returns the leader
+/// and the follower.
+fn decide_without_follower(servers: &mut Servers) -> (NodeId, NodeId) {
+    let leader = wait_for_leader(servers);
+    let follower = follower_of(leader);
+    let server = servers.get_mut(&leader).unwrap();
+    for i in 0..NUM_PROPOSALS {
+        server.append(Value::with_id(i)).expect("Failed to append");
+    }
+    let is_follower =
+        |msg: &Message<Value>| msg.get_receiver() == follower || msg.get_sender() == follower;
+    while !deliver_round(servers, &mut |msg| is_follower(msg)).is_empty() {}
+    assert!(servers[&leader].get_decided_idx() >= NUM_PROPOSALS);
+    assert!(servers[&follower].get_decided_idx() < NUM_PROPOSALS);
+    (leader, follower)
+}
+
+/// Simulates that the follower reconnects to the leader, which makes the leader synchronize the follower.
+fn reconnect(servers: &mut Servers, leader: NodeId, follower: NodeId) {
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    servers.get_mut(&leader).unwrap().reconnected(follower);
+}
+
+fn decided_entries(servers: &Servers, pid: NodeId) -> Vec<Value> {
+    servers[&pid]
+        .read_decided_suffix(servers[&pid].get_compacted_idx())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entry| match entry {
+            LogEntry::Decided(value) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+fn assert_synchronized(servers: &Servers, leader: NodeId, follower: NodeId) {
+    assert_eq!(
+        servers[&follower].get_decided_idx(),
+        servers[&leader].get_decided_idx(),
+        "The follower did not decide the entries of the leader"
+    );
+    assert_eq!(
+        decided_entries(servers, follower),
+        decided_entries(servers, leader)
+    );
+}
+
+/// Verifies that a follower that is far behind is synchronized with chunks of at most `max_sync_entries` entries.
+#[test]
+fn sync_in_chunks_test() {
+    let mut servers = create_servers(Some(MAX_SYNC_ENTRIES), None);
+    let (leader, follower) = decide_without_follower(&mut servers);
+    reconnect(&mut servers, leader, follower);
+    let sync_msgs = deliver(&mut servers, follower);
+    assert_synchronized(&servers, leader, follower);
+    assert!(
+        sync_msgs.iter().all(|len| *len <= MAX_SYNC_ENTRIES),
+        "A chunk is larger than the limit: {:?}",
+        sync_msgs
+    );
+    assert!(sync_msgs.len() as u64 >= NUM_PROPOSALS / MAX_SYNC_ENTRIES as u64);
+}
+
+/// Verifies that a follower that is far behind is synchronized with chunks of at most `max_sync_bytes` bytes.
+#[test]
+fn sync_in_chunks_by_size_test() {
+    let entry_size = Value::with_id(0).size_hint();
+    let mut servers = create_servers(None, Some(MAX_SYNC_ENTRIES * entry_size));
+    let (leader, follower) = decide_without_follower(&mut servers);
+    reconnect(&mut servers, leader, follower);
+    let sync_msgs = deliver(&mut servers, follower);
+    assert_synchronized(&servers, leader, follower);
+    assert!(
+        sync_msgs.iter().all(|len| *len <= MAX_SYNC_ENTRIES),
+        "A chunk is larger than the limit: {:?}",
+        sync_msgs
+    );
+    assert!(sync_msgs.len() as u64 >= NUM_PROPOSALS / MAX_SYNC_ENTRIES as u64);
+}
+
+/// Verifies that a follower is synchronized completely if new entries are proposed while it is being synchronized.
+#[test]
+fn sync_with_concurrent_proposals_test() {
+    let mut servers = create_servers(Some(MAX_SYNC_ENTRIES), None);
+    let (leader, follower) = decide_without_follower(&mut servers);
+    reconnect(&mut servers, leader, follower);
+    for i in 0..NUM_PROPOSALS {
+        servers
+            .get_mut(&leader)
+            .unwrap()
+            .append(Value::with_id(NUM_PROPOSALS + i))
+            .expect("Failed to append");
+        deliver_round(&mut servers, &mut |_| false);
+    }
+    deliver(&mut servers, follower);
+    assert_eq!(servers[&leader].get_decided_idx(), 2 * NUM_PROPOSALS);
+    assert_synchronized(&servers, leader, follower);
+}
+
+/// Verifies that the synchronization continues from the entries that the follower has accepted after it reconnects.
+#[test]
+fn resume_sync_after_reconnect_test() {
+    let mut servers = create_servers(Some(MAX_SYNC_ENTRIES), None);
+    let (leader, follower) = decide_without_follower(&mut servers);
+    reconnect(&mut servers, leader, follower);
+    for _ in 0..5 {
+        deliver_round(&mut servers, &mut |_| false);
+    }
+    // The connection is lost while a chunk is sent
+    deliver_round(&mut servers, &mut |msg| {
+        sync_msg_len(msg, follower).is_some()
+    });
+    let accepted_idx = servers[&follower]
+        .read_entries(0..)
+        .map(|entries| entries.len() as u64)
+        .unwrap_or_default();
+    assert!(accepted_idx > 0 && accepted_idx < NUM_PROPOSALS);
+
+    reconnect(&mut servers, leader, follower);
+    let mut resumed_sync_idx = None;
+    loop {
+        let msgs = deliver_round(&mut servers, &mut |_| false);
+        if msgs.is_empty() {
+            break;
+        }
+        for msg in msgs {
+            if let Message::SequencePaxos(m) = msg {
+                if let PaxosMsg::AcceptSync(a) = m.msg {
+                    resumed_sync_idx = Some(a.sync_idx);
+                }
+            }
+        }
+    }
+    assert_eq!(resumed_sync_idx, Some(accepted_idx));
+    assert_synchronized(&servers, leader, follower);
+}
+
+/// Verifies that the leader resends a chunk that the follower did not accept.
+#[test]
+fn resend_sync_chunk_test() {
+    let mut servers = create_servers(Some(MAX_SYNC_ENTRIES), None);
+    let (leader, follower) = decide_without_follower(&mut servers);
+    reconnect(&mut servers, leader, follower);
+    let mut dropped = false;
+    while !deliver_round(&mut servers, &mut |msg| {
+        let drop = !dropped
+            && matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::AcceptSyncChunk(_)));
+        dropped |= drop;
+        drop
+    })
+    .is_empty()
+    {}
+    assert!(dropped);
+    assert!(servers[&follower].get_decided_idx() < NUM_PROPOSALS);
+
+    for _ in 0..MAX_TICKS {
+        tick(&mut servers);
+        if servers[&follower].get_decided_idx() == servers[&leader].get_decided_idx() {
+            break;
+        }
+    }
+    assert_synchronized(&servers, leader, follower);
+}
+
+/// Verifies that a follower is sent a snapshot if the leader compacts the entries that are not sent yet.
+#[test]
+fn snapshot_during_sync_test() {
+    let mut servers = create_servers(Some(MAX_SYNC_ENTRIES), None);
+    let (leader, follower) = decide_without_follower(&mut servers);
+    reconnect(&mut servers, leader, follower);
+    for _ in 0..5 {
+        deliver_round(&mut servers, &mut |_| false);
+    }
+    servers
+        .get_mut(&leader)
+        .unwrap()
+        .snapshot(None, true)
+        .expect("Failed to snapshot");
+    deliver(&mut servers, follower);
+    assert_eq!(
+        servers[&follower].get_compacted_idx(),
+        servers[&leader].get_compacted_idx()
+    );
+    assert_synchronized(&servers, leader, follower);
+}
@@ -0,0 +1,189 @@
+//! Tests for filtering duplicate entries of client sessions. The servers are driven directly by passing
+//! their messages to each other, since the entries need a custom `Entry` implementation.
+#![cfg(not(feature = "unicache"))]
+
+pub mod utils;
+
+use omnipaxos::{
+    storage::{Entry, Snapshot},
+    util::{ClientSession, ClientSessions, LogEntry, NodeId, ProposalOutcome, ProposalStatus},
+    OmniPaxos, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serde::{Deserialize, Serialize};
+use utils::direct::{self, create_servers, follower_of, Servers, NUM_NODES};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Command {
+    client_id: u64,
+    seq_num: u64,
+    value: u64,
+}
+
+impl Entry for Command {
+    type Snapshot = CommandSnapshot;
+
+    fn client_session(&self) -> Option<ClientSession> {
+        Some(ClientSession {
+            client_id: self.client_id,
+            seq_num: self.seq_num,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CommandSnapshot {
+    values: Vec<u64>,
+    sessions: ClientSessions,
+}
+
+impl Snapshot<Command> for CommandSnapshot {
+    fn create(entries: &[Command]) -> Self {
+        Self {
+            values: entries.iter().map(|c| c.value).collect(),
+            sessions: ClientSessions::from_entries(entries),
+        }
+    }
+
+    fn merge(&mut self, delta: Self) {
+        self.values.extend(delta.values);
+        self.sessions.merge(&delta.sessions);
+    }
+
+    fn use_snapshots() -> bool {
+        true
+    }
+
+    fn client_sessions(&self) -> Option<&ClientSessions> {
+        Some(&self.sessions)
+    }
+}
+
+type Server = OmniPaxos<Command, MemoryStorage<Command>>;
+
+struct Cluster {
+    servers: Servers<Command>,
+}
+
+impl Cluster {
+    fn new() -> Self {
+        Self {
+            servers: create_servers(ServerConfig::default()),
+        }
+    }
+
+    fn server(&mut self, pid: NodeId) -> &mut Server {
+        self.servers.get_mut(&pid).unwrap()
+    }
+
+    /// Ticks all servers `ticks` times and delivers their messages after each tick.
+    fn tick(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            direct::tick(&mut self.servers);
+        }
+    }
+
+    fn wait_for_leader(&mut self) -> NodeId {
+        direct::wait_for_leader(&mut self.servers)
+    }
+
+    fn decided_values(&mut self, pid: NodeId) -> Vec<u64> {
+        self.server(pid)
+            .poll_decided()
+            .into_iter()
+            .flat_map(|e| match e {
+                LogEntry::Decided(c) => vec![c.value],
+                LogEntry::Snapshotted(s) => s.snapshot.values,
+                e => panic!("Unexpected entry: {:?}", e),
+            })
+            .collect()
+    }
+}
+
+fn command(client_id: u64, seq_num: u64, value: u64) -> Command {
+    Command {
+        client_id,
+        seq_num,
+        value,
+    }
+}
+
+/// Verifies that a retried entry of a client session is reported as a duplicate and not decided
+/// again, whether it is proposed on the leader or forwarded by a follower.
+#[test]
+fn duplicate_proposal_test() {
+    let mut cluster = Cluster::new();
+    let leader = cluster.wait_for_leader();
+    let follower = follower_of(leader);
+
+    cluster.server(leader).append(command(1, 1, 10)).unwrap();
+    cluster.server(follower).append(command(2, 1, 20)).unwrap();
+    cluster.tick(5);
+    cluster.server(leader).proposal_outcomes();
+    cluster.server(follower).proposal_outcomes();
+
+    // Client 1 retries through the follower and client 2 through the leader
+    let retry_1 = cluster.server(follower).append(command(1, 1, 10)).unwrap();
+    let retry_2 = cluster.server(leader).append(command(2, 1, 20)).unwrap();
+    cluster.server(leader).append(command(1, 2, 11)).unwrap();
+    cluster.tick(5);
+    assert_eq!(
+        cluster.server(follower).proposal_outcomes(),
+        vec![ProposalOutcome {
+            id: retry_1,
+            status: ProposalStatus::Duplicate
+        }]
+    );
+    let leader_outcomes = cluster.server(leader).proposal_outcomes();
+    assert!(
+        leader_outcomes.contains(&ProposalOutcome {
+            id: retry_2,
+            status: ProposalStatus::Duplicate
+        }),
+        "Unexpected outcomes: {:?}",
+        leader_outcomes
+    );
+    for pid in 1..=NUM_NODES {
+        assert_eq!(cluster.decided_values(pid), vec![10, 20, 11]);
+    }
+}
+
+/// Verifies that the client sessions survive compaction, so that a new leader filters out a
+/// duplicate of an entry that has been snapshotted.
+#[test]
+fn client_sessions_snapshot_test() {
+    let mut cluster = Cluster::new();
+    let leader = cluster.wait_for_leader();
+    for seq_num in 1..=3 {
+        cluster
+            .server(leader)
+            .append(command(1, seq_num, seq_num))
+            .unwrap();
+    }
+    cluster.tick(5);
+    for pid in 1..=NUM_NODES {
+        cluster.server(pid).snapshot(None, true).unwrap();
+    }
+    cluster.tick(5);
+
+    // Make another server the leader, which has to restore the client sessions from its snapshot
+    let new_leader = follower_of(leader);
+    cluster
+        .server(leader)
+        .transfer_leadership(new_leader)
+        .expect("Failed to transfer leadership");
+    cluster.tick(20);
+    assert_eq!(cluster.wait_for_leader(), new_leader);
+
+    let retry = cluster.server(new_leader).append(command(1, 3, 3)).unwrap();
+    cluster.server(new_leader).append(command(1, 4, 4)).unwrap();
+    cluster.tick(5);
+    assert!(cluster
+        .server(new_leader)
+        .proposal_outcomes()
+        .contains(&ProposalOutcome {
+            id: retry,
+            status: ProposalStatus::Duplicate
+        }));
+    assert_eq!(cluster.decided_values(new_leader), vec![1, 2, 3, 4]);
+}
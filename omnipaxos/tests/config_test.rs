@@ -35,6 +35,8 @@ fn config_all_fields_test() {
                 Some("logs/paxos_1.log".to_string())
             );
             assert_eq!(config.server_config.leader_priority, 2);
+            assert_eq!(config.server_config.max_pending_proposals, Some(100));
+            assert_eq!(config.server_config.max_sync_entries, Some(1000));
 
             // Make sure we pass asserts in build
             config.build(MemoryStorage::<Value>::default()).unwrap();
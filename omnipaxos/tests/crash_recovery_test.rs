@@ -0,0 +1,167 @@
+//! Tests that `PersistentStorage` recovers a valid state after the process is killed between any two writes to the
+//! disk. A child process runs a workload on the storage and aborts after a given number of disk writes, after which
+//! the storage is opened again.
+pub mod utils;
+
+use commitlog::LogOptions;
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Storage, StorageOp},
+};
+use omnipaxos_storage::{
+    memory_storage::MemoryStorage,
+    persistent_storage::{PersistentStorage, PersistentStorageConfig},
+};
+use sled::Config;
+use std::{
+    env,
+    process::{Command, Stdio},
+};
+use utils::{create_temp_dir, Value};
+
+const CRASH_PATH: &str = "CRASH_PATH";
+const CRASH_AFTER: &str = "CRASH_AFTER";
+const MAX_DISK_WRITES: u64 = 200;
+
+/// The state of a storage that must be consistent after a crash.
+#[derive(Debug, PartialEq)]
+struct StorageState {
+    promise: Ballot,
+    accepted_round: Ballot,
+    decided_idx: u64,
+    compacted_idx: u64,
+    log: Vec<Value>,
+}
+
+impl StorageState {
+    fn read<S: Storage<Value>>(storage: &S) -> Self {
+        Self {
+            promise: storage.get_promise().unwrap().unwrap_or_default(),
+            accepted_round: storage.get_accepted_round().unwrap().unwrap_or_default(),
+            decided_idx: storage.get_decided_idx().unwrap(),
+            compacted_idx: storage.get_compacted_idx().unwrap(),
+            log: storage.get_suffix(0).unwrap(),
+        }
+    }
+}
+
+enum Write {
+    Atomic(Vec<StorageOp<Value>>),
+    AppendEntries(Vec<Value>),
+    AppendOnPrefix(u64, Vec<Value>),
+    CompactedIdx(u64),
+    Trim(u64),
+}
+
+fn values(ids: std::ops::Range<u64>) -> Vec<Value> {
+    ids.map(Value::with_id).collect()
+}
+
+fn ballot(n: u32) -> Ballot {
+    Ballot::with(1, n, 0, 1)
+}
+
+/// Writes that cover appends, truncations and trimming together with changes of the metadata.
+fn workload() -> Vec<Write> {
+    vec![
+        Write::Atomic(vec![StorageOp::SetPromise(ballot(1))]),
+        Write::AppendEntries(values(0..3)),
+        Write::Atomic(vec![
+            StorageOp::SetAcceptedRound(ballot(1)),
+            StorageOp::SetDecidedIdx(2),
+            StorageOp::AppendOnPrefix(2, values(10..13)),
+        ]),
+        Write::Atomic(vec![StorageOp::SetPromise(ballot(2))]),
+        Write::Atomic(vec![
+            StorageOp::SetAcceptedRound(ballot(2)),
+            StorageOp::AppendEntries(values(20..22)),
+        ]),
+        Write::Atomic(vec![
+            StorageOp::SetDecidedIdx(4),
+            StorageOp::AppendEntries(values(22..23)),
+        ]),
+        Write::CompactedIdx(2),
+        Write::Trim(2),
+        Write::AppendOnPrefix(1, values(30..31)),
+        Write::Atomic(vec![
+            StorageOp::SetAcceptedRound(ballot(3)),
+            StorageOp::AppendOnPrefix(0, values(40..42)),
+        ]),
+    ]
+}
+
+fn apply<S: Storage<Value>>(storage: &mut S, write: Write) {
+    match write {
+        Write::Atomic(ops) => storage.write_atomically(ops).map(|_| ()),
+        Write::AppendEntries(entries) => storage.append_entries(entries).map(|_| ()),
+        Write::AppendOnPrefix(from_idx, entries) => {
+            storage.append_on_prefix(from_idx, entries).map(|_| ())
+        }
+        Write::CompactedIdx(idx) => storage.set_compacted_idx(idx),
+        Write::Trim(idx) => storage.trim(idx),
+    }
+    .expect("Failed to write to storage");
+}
+
+fn storage_config(path: &str) -> PersistentStorageConfig {
+    let log_opts = LogOptions::new(format!("{path}/commitlog/"));
+    PersistentStorageConfig::with(path.to_string(), log_opts, Config::new())
+}
+
+/// Runs the workload on the storage in `CRASH_PATH` and aborts after `CRASH_AFTER` disk writes. Only run as a child
+/// process of `crash_recovery_test`.
+#[test]
+#[ignore = "run in a child process by crash_recovery_test"]
+fn crash_workload() {
+    let (path, crash_after) = match (env::var(CRASH_PATH), env::var(CRASH_AFTER)) {
+        (Ok(path), Ok(crash_after)) => (path, crash_after.parse().unwrap()),
+        _ => return,
+    };
+    let mut config = storage_config(&path);
+    config.set_crash_after(crash_after);
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(config);
+    for write in workload() {
+        apply(&mut storage, write);
+    }
+}
+
+/// Kills the workload after every disk write and verifies that the storage is opened in the state before or after
+/// one of the writes of the workload.
+#[test]
+fn crash_recovery_test() {
+    let mut memory_storage = MemoryStorage::default();
+    let mut valid_states = vec![StorageState::read(&memory_storage)];
+    for write in workload() {
+        apply(&mut memory_storage, write);
+        valid_states.push(StorageState::read(&memory_storage));
+    }
+
+    for crash_after in 1..=MAX_DISK_WRITES {
+        let path = create_temp_dir();
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["crash_workload", "--exact", "--ignored", "--test-threads=1"])
+            .env(CRASH_PATH, &path)
+            .env(CRASH_AFTER, crash_after.to_string())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .expect("Failed to run the workload");
+        let storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+        let state = StorageState::read(&storage);
+        if status.success() {
+            // The workload completed before the crash point was reached
+            assert_eq!(&state, valid_states.last().unwrap());
+            return;
+        }
+        assert!(
+            valid_states.contains(&state),
+            "Invalid state after crashing after {} disk writes: {:?}",
+            crash_after,
+            state
+        );
+    }
+    panic!(
+        "The workload did not complete in {} disk writes",
+        MAX_DISK_WRITES
+    );
+}
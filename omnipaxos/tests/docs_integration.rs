@@ -361,6 +361,7 @@ mod docs_integration_test {
             configuration_id: 1,
             nodes: vec![1, 2, 3, 4, 5, 6, 7],
             flexible_quorum: Some(flex_quorum),
+            ..Default::default()
         };
         let server_config = ServerConfig {
             pid: 1,
@@ -1,11 +1,20 @@
 pub mod utils;
 
 use kompact::prelude::{promise, Ask};
-use omnipaxos::ballot_leader_election::Ballot;
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    util::{LogEntry, ProposalOutcome, ProposalStatus},
+};
 use rand::Rng;
 use serial_test::serial;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
 use utils::{TestConfig, TestSystem, Value};
 
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
 /// Verifies if the follower nodes forwards the proposal message to a leader
 /// so it can get decided.
 #[test]
@@ -55,3 +64,66 @@ fn forward_proposal_test() {
         Err(e) => panic!("Error on kompact shutdown: {}", e),
     };
 }
+
+/// Verifies that the outcome of a proposal forwarded by a follower is reported to the follower
+/// with the index that the entry was decided at.
+#[test]
+#[serial]
+fn forward_proposal_outcome_test() {
+    let cfg = TestConfig::load("proposal_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+
+    let first_node = sys.nodes.get(&1).unwrap();
+    let (kprom_ble, kfuture_ble) = promise::<Ballot>();
+    first_node.on_definition(|x| x.election_futures.push(Ask::new(kprom_ble, ())));
+
+    sys.start_all_nodes();
+
+    let elected_leader = kfuture_ble
+        .wait_timeout(cfg.wait_timeout)
+        .expect("No leader has been elected in the allocated time!");
+    let proposal_node = (1..=cfg.num_nodes as u64)
+        .find(|pid| *pid != elected_leader.pid)
+        .expect("No follower found");
+
+    let px = sys.nodes.get(&proposal_node).unwrap();
+    let v = Value::with_id(proposal_node);
+    let (kprom, kfuture) = promise();
+    let id = px.on_definition(|x| {
+        x.insert_decided_future(Ask::new(kprom, v.clone()));
+        x.paxos.append(v.clone()).expect("Failed to call Append")
+    });
+    kfuture
+        .wait_timeout(cfg.wait_timeout)
+        .expect("The message was not proposed in the allocated time!");
+
+    let start = Instant::now();
+    let mut outcomes = vec![];
+    while outcomes.is_empty() {
+        assert!(
+            start.elapsed() < cfg.wait_timeout,
+            "The outcome of the proposal was not reported in the allocated time!"
+        );
+        thread::sleep(POLL_INTERVAL);
+        outcomes = px.on_definition(|x| x.paxos.proposal_outcomes());
+    }
+    let decided_idx = match outcomes.as_slice() {
+        [ProposalOutcome {
+            id: outcome_id,
+            status: ProposalStatus::Decided(idx),
+        }] if *outcome_id == id => *idx,
+        _ => panic!("Unexpected proposal outcomes: {:?}", outcomes),
+    };
+    let entry = px.on_definition(|x| x.paxos.read(decided_idx));
+    match entry {
+        Some(LogEntry::Decided(e)) => assert_eq!(e, v, "Wrong entry at the decided index"),
+        e => panic!("Unexpected entry at the decided index: {:?}", e),
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
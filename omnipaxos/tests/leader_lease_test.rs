@@ -0,0 +1,119 @@
+pub mod utils;
+
+use kompact::prelude::*;
+use serial_test::serial;
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use utils::{omnireplica::OmniPaxosComponent, TestConfig, TestSystem};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Verifies that a leader with a valid lease confirms reads locally without waiting for its followers.
+#[test]
+#[serial]
+fn leader_lease_read_test() {
+    let cfg = TestConfig::load("leader_lease_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, proposals, cfg.wait_timeout);
+
+    let node = sys.nodes.get(&leader).unwrap();
+    assert!(
+        wait_for_lease(node, true, cfg.wait_timeout),
+        "Leader {} never acquired a lease",
+        leader
+    );
+    let (read_id, confirmed) = node.on_definition(|x| {
+        let read_id = x.paxos.read_index();
+        (read_id, x.paxos.confirmed_reads())
+    });
+    let read_index = confirmed
+        .into_iter()
+        .find(|r| r.id == read_id)
+        .expect("Read was not confirmed locally");
+    assert!(
+        read_index.read_idx >= cfg.num_proposals,
+        "Read index {} does not cover the {} decided proposals",
+        read_index.read_idx,
+        cfg.num_proposals
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the lease of a disconnected leader expires before the rest of the cluster elects a new leader.
+#[test]
+#[serial]
+fn leader_lease_expiry_test() {
+    let cfg = TestConfig::load("leader_lease_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let node = sys.nodes.get(&leader).unwrap();
+    assert!(
+        wait_for_lease(node, true, cfg.wait_timeout),
+        "Leader {} never acquired a lease",
+        leader
+    );
+    sys.set_node_connections(leader, false);
+    assert!(
+        wait_for_lease(node, false, cfg.wait_timeout),
+        "Disconnected leader {} kept its lease",
+        leader
+    );
+    let follower = sys
+        .nodes
+        .keys()
+        .find(|pid| **pid != leader)
+        .copied()
+        .expect("No follower found");
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let start = Instant::now();
+    while follower_node.on_definition(|x| x.paxos.get_current_leader()) == Some(leader) {
+        assert!(
+            start.elapsed() < cfg.wait_timeout,
+            "No new leader was elected after the lease expired"
+        );
+        thread::sleep(POLL_INTERVAL);
+    }
+    assert!(
+        !node.on_definition(|x| x.paxos.has_leader_lease()),
+        "Old leader {} holds a lease after a new leader was elected",
+        leader
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Polls `node` until its lease status is `has_lease` or `timeout` has passed.
+fn wait_for_lease(
+    node: &Arc<Component<OmniPaxosComponent>>,
+    has_lease: bool,
+    timeout: Duration,
+) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if node.on_definition(|x| x.paxos.has_leader_lease()) == has_lease {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    false
+}
@@ -0,0 +1,95 @@
+pub mod utils;
+
+use omnipaxos::TransferLeadershipErr;
+use serial_test::serial;
+use utils::{TestConfig, TestSystem};
+
+/// Verifies that the leadership is transferred to a follower and that proposals made during the
+/// transfer are decided by the new leader.
+#[test]
+#[serial]
+fn leader_transfer_test() {
+    let cfg = TestConfig::load("leader_transfer_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let first_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, first_proposals, cfg.wait_timeout);
+
+    let target = (1..=cfg.num_nodes as u64)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        x.paxos
+            .transfer_leadership(target)
+            .expect("Failed to transfer leadership")
+    });
+    let new_leader = sys.get_next_leader(target, cfg.wait_timeout);
+    assert_eq!(
+        new_leader, target,
+        "Leadership was transferred to {} instead of {}",
+        new_leader, target
+    );
+
+    let second_proposals = utils::create_proposals(cfg.num_proposals + 1, 2 * cfg.num_proposals);
+    sys.make_proposals(leader, second_proposals, cfg.wait_timeout);
+    let leader_log = sys
+        .nodes
+        .get(&target)
+        .unwrap()
+        .on_definition(|x| x.read_decided_log());
+    utils::verification::verify_log(
+        leader_log,
+        utils::create_proposals(1, 2 * cfg.num_proposals),
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a transfer is rejected by followers and for targets outside of the configuration.
+#[test]
+#[serial]
+fn leader_transfer_invalid_test() {
+    let cfg = TestConfig::load("leader_transfer_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as u64)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let res = sys
+        .nodes
+        .get(&follower)
+        .unwrap()
+        .on_definition(|x| x.paxos.transfer_leadership(leader));
+    assert!(
+        matches!(res, Err(TransferLeadershipErr::NotCurrentLeader(_))),
+        "Follower accepted a leadership transfer: {:?}",
+        res
+    );
+    let invalid_target = cfg.num_nodes as u64 + 1;
+    let res = sys
+        .nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.transfer_leadership(invalid_target));
+    assert!(
+        matches!(res, Err(TransferLeadershipErr::InvalidTarget(pid)) if pid == invalid_target),
+        "Leadership transfer to a server outside of the configuration was accepted: {:?}",
+        res
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
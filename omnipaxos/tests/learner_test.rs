@@ -0,0 +1,153 @@
+pub mod utils;
+
+use kompact::prelude::{promise, Ask, FutureCollection};
+use omnipaxos::ClusterConfig;
+use serial_test::serial;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use utils::{TestConfig, TestSystem};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Verifies that learners replicate the decided log but are never elected as leader.
+#[test]
+#[serial]
+fn learner_replication_test() {
+    let cfg = TestConfig::load("learner_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    assert!(
+        leader <= cfg.num_nodes as u64,
+        "Learner {} was elected as leader",
+        leader
+    );
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+
+    let learners = cfg.num_nodes as u64 + 1..=(cfg.num_nodes + cfg.num_learners) as u64;
+    for learner in learners {
+        let node = sys.nodes.get(&learner).unwrap();
+        let start = Instant::now();
+        while node.on_definition(|x| x.paxos.get_decided_idx()) < cfg.num_proposals {
+            assert!(
+                start.elapsed() < cfg.wait_timeout,
+                "Learner {} never decided the proposals",
+                learner
+            );
+            thread::sleep(POLL_INTERVAL);
+        }
+        let learner_log = node.on_definition(|x| x.read_decided_log());
+        utils::verification::verify_log(learner_log, proposals.clone());
+        assert_eq!(
+            node.on_definition(|x| x.paxos.get_current_leader()),
+            Some(leader),
+            "Learner {} does not follow the leader",
+            learner
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that learners are not counted in the quorum needed to decide entries.
+#[test]
+#[serial]
+fn learner_quorum_test() {
+    let cfg = TestConfig::load("learner_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    // Together with the learners, the leader is connected to a majority of all the servers but
+    // only to a minority of the voters.
+    for voter in 1..=cfg.num_nodes as u64 {
+        if voter != leader {
+            sys.set_node_connections(voter, false);
+        }
+    }
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    let decided_futures = utils::create_proposals(1, cfg.num_proposals)
+        .into_iter()
+        .map(|v| {
+            leader_node.on_definition(|x| {
+                let (kprom, kfuture) = promise::<()>();
+                x.paxos.append(v.clone()).expect("Failed to append");
+                x.insert_decided_future(Ask::new(kprom, v));
+                kfuture
+            })
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        FutureCollection::collect_with_timeout::<Vec<_>>(decided_futures, cfg.wait_timeout)
+            .is_err(),
+        "Entries were decided without a quorum of voters"
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that learners see the StopSign of a reconfiguration that promotes them to voters.
+#[test]
+#[serial]
+fn learner_promotion_test() {
+    let cfg = TestConfig::load("learner_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    let new_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: (1..=(cfg.num_nodes + cfg.num_learners) as u64).collect(),
+        ..Default::default()
+    };
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        x.paxos
+            .reconfigure(new_config.clone(), None)
+            .expect("Failed to reconfigure")
+    });
+
+    let learners = cfg.num_nodes as u64 + 1..=(cfg.num_nodes + cfg.num_learners) as u64;
+    for learner in learners {
+        let node = sys.nodes.get(&learner).unwrap();
+        let start = Instant::now();
+        let stopsign = loop {
+            if let Some(ss) = node.on_definition(|x| x.paxos.is_reconfigured()) {
+                break ss;
+            }
+            assert!(
+                start.elapsed() < cfg.wait_timeout,
+                "Learner {} never decided the StopSign",
+                learner
+            );
+            thread::sleep(POLL_INTERVAL);
+        };
+        assert_eq!(stopsign.next_config, new_config);
+        assert!(stopsign.next_config.nodes.contains(&learner));
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
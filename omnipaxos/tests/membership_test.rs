@@ -0,0 +1,232 @@
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    ClusterConfig, ProposeErr, ServerConfig,
+};
+use serial_test::serial;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use utils::{
+    direct::{
+        agreed_leader, create_servers, deliver, follower_of, tick, wait_for_leader, Servers,
+        MAX_TICKS, NUM_NODES,
+    },
+    StorageType, TestConfig, TestSystem, Value,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Verifies that a server can be added in place and is synchronized with the log of the cluster.
+#[test]
+#[serial]
+fn membership_add_node_test() {
+    let cfg = TestConfig::load("membership_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let first_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, first_proposals.clone(), cfg.wait_timeout);
+
+    // The new server is started with the new configuration
+    let new_pid = cfg.num_nodes as u64 + 1;
+    let new_cfg = TestConfig {
+        num_nodes: cfg.num_nodes + 1,
+        ..cfg
+    };
+    let storage = StorageType::with(cfg.storage_type, &format!("{}{new_pid}", sys.temp_dir_path));
+    sys.create_node(new_pid, &new_cfg, storage);
+    sys.start_node(new_pid);
+
+    let new_config = ClusterConfig {
+        configuration_id: 1,
+        nodes: (1..=new_pid).collect(),
+        ..Default::default()
+    };
+    change_membership(&sys, leader, &new_config, cfg.wait_timeout);
+    for pid in 1..=new_pid {
+        wait_for_cluster_config(&sys, pid, &new_config, cfg.wait_timeout);
+    }
+
+    // The new server forwards its proposals to the leader and decides the whole log
+    let second_proposals = utils::create_proposals(cfg.num_proposals + 1, 2 * cfg.num_proposals);
+    sys.make_proposals(new_pid, second_proposals.clone(), cfg.wait_timeout);
+    let new_node = sys.nodes.get(&new_pid).unwrap();
+    let new_node_log = new_node.on_definition(|x| x.read_decided_log());
+    utils::verification::verify_log(new_node_log, [first_proposals, second_proposals].concat());
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a removed server is no longer needed to decide entries.
+#[test]
+#[serial]
+fn membership_remove_node_test() {
+    let cfg = TestConfig::load("membership_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let removed = (1..=cfg.num_nodes as u64)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let new_config = ClusterConfig {
+        configuration_id: 1,
+        nodes: (1..=cfg.num_nodes as u64)
+            .filter(|pid| *pid != removed)
+            .collect(),
+        ..Default::default()
+    };
+    change_membership(&sys, leader, &new_config, cfg.wait_timeout);
+    for pid in 1..=cfg.num_nodes as u64 {
+        wait_for_cluster_config(&sys, pid, &new_config, cfg.wait_timeout);
+    }
+
+    // The remaining servers are a quorum of the new configuration
+    sys.kill_node(removed);
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+    let leader_log = sys
+        .nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.read_decided_log());
+    utils::verification::verify_log(leader_log, proposals);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the leader resends the membership change to a removed server until it has accepted it, so that it
+/// stops using the old configuration. The servers are driven directly so that the message can be dropped.
+#[test]
+fn membership_resend_to_removed_node_test() {
+    let mut servers: Servers<Value> = create_servers(ServerConfig::default());
+    let old_config = servers[&1].get_cluster_config();
+    let leader = wait_for_leader(&mut servers);
+    let removed = follower_of(leader);
+    let new_config = ClusterConfig {
+        configuration_id: 1,
+        nodes: (1..=NUM_NODES).filter(|pid| *pid != removed).collect(),
+        ..Default::default()
+    };
+    servers
+        .get_mut(&leader)
+        .unwrap()
+        .change_membership(new_config.clone())
+        .expect("Failed to change membership");
+
+    let mut dropped = false;
+    let mut drop_first_change = |msg: &Message<Value>| {
+        let drop = !dropped
+            && msg.get_receiver() == removed
+            && matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::AcceptMembership(_)));
+        dropped |= drop;
+        drop
+    };
+    servers.values_mut().for_each(|s| s.tick());
+    deliver(&mut servers, &mut drop_first_change);
+    assert!(dropped);
+    assert_eq!(servers[&removed].get_cluster_config(), old_config);
+    for _ in 0..50 {
+        tick(&mut servers);
+    }
+    assert_eq!(servers[&removed].get_cluster_config(), new_config);
+}
+
+/// Verifies that a new leader does not allow membership changes until a quorum has accepted the current membership
+/// in its round, since a change of an earlier round might only have been accepted by a minority.
+#[test]
+fn membership_change_after_election_test() {
+    let mut servers: Servers<Value> = create_servers(ServerConfig::default());
+    let drop_accepted_membership = |msg: &Message<Value>| matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::AcceptedMembership(_)));
+    let mut leader = None;
+    for _ in 0..MAX_TICKS {
+        servers.values_mut().for_each(|s| s.tick());
+        deliver(&mut servers, drop_accepted_membership);
+        leader = agreed_leader(&servers);
+        if leader.is_some() {
+            break;
+        }
+    }
+    let leader = leader.expect("No leader has been elected");
+    let new_config = ClusterConfig {
+        configuration_id: 1,
+        nodes: (1..=NUM_NODES + 1).collect(),
+        ..Default::default()
+    };
+    let result = servers
+        .get_mut(&leader)
+        .unwrap()
+        .change_membership(new_config.clone());
+    assert!(matches!(
+        result,
+        Err(ProposeErr::PendingMembershipChange(_))
+    ));
+
+    // The leader resends the membership until a quorum has accepted it
+    for _ in 0..50 {
+        tick(&mut servers);
+    }
+    servers
+        .get_mut(&leader)
+        .unwrap()
+        .change_membership(new_config.clone())
+        .expect("Failed to change membership");
+    assert_eq!(servers[&leader].get_cluster_config(), new_config);
+}
+
+/// Proposes the membership change at `leader`, retrying while the leader has a pending membership change.
+fn change_membership(
+    sys: &TestSystem,
+    leader: u64,
+    config: &ClusterConfig,
+    wait_timeout: Duration,
+) {
+    let node = sys.nodes.get(&leader).unwrap();
+    let start = Instant::now();
+    loop {
+        match node.on_definition(|x| x.paxos.change_membership(config.clone())) {
+            Ok(_) => break,
+            Err(ProposeErr::PendingMembershipChange(_)) => {
+                assert!(
+                    start.elapsed() < wait_timeout,
+                    "Node {} never accepted the membership change",
+                    leader
+                );
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => panic!("Failed to change membership: {:?}", e),
+        }
+    }
+}
+
+fn wait_for_cluster_config(
+    sys: &TestSystem,
+    pid: u64,
+    config: &ClusterConfig,
+    wait_timeout: Duration,
+) {
+    let node = sys.nodes.get(&pid).unwrap();
+    let start = Instant::now();
+    while &node.on_definition(|x| x.paxos.get_cluster_config()) != config {
+        assert!(
+            start.elapsed() < wait_timeout,
+            "Node {} never changed its membership",
+            pid
+        );
+        thread::sleep(POLL_INTERVAL);
+    }
+}
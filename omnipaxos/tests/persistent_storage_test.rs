@@ -0,0 +1,374 @@
+//! Tests that trimming and truncating the log of `PersistentStorage` reclaims the disk space of the commitlog, that
+//! the storage is written and validated with the configured codec, and that corrupted entries are detected.
+pub mod utils;
+
+use commitlog::LogOptions;
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Snapshot, Storage, StorageResult},
+};
+use omnipaxos_storage::{
+    codec::{BincodeCodec, Codec, JsonCodec, PostcardCodec},
+    persistent_storage::{CorruptedEntry, PersistentStorage, PersistentStorageConfig},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sled::Config;
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+use utils::{create_temp_dir, Value, ValueSnapshot};
+
+const NUM_ENTRIES: u64 = 1000;
+const TRIM_IDX: u64 = 900;
+const SEGMENT_MAX_BYTES: usize = 1024;
+const INDEX_MAX_ITEMS: usize = 100;
+
+fn storage_config(path: &str) -> PersistentStorageConfig {
+    let mut log_opts = LogOptions::new(format!("{path}/commitlog/"));
+    log_opts.segment_max_bytes(SEGMENT_MAX_BYTES);
+    log_opts.index_max_items(INDEX_MAX_ITEMS);
+    PersistentStorageConfig::with(path.to_string(), log_opts, Config::new())
+}
+
+fn codec_storage_config<C: Codec>(path: &str, codec: C) -> PersistentStorageConfig<C> {
+    let log_opts = LogOptions::new(format!("{path}/commitlog/"));
+    PersistentStorageConfig::with_codec(path.to_string(), log_opts, Config::new(), codec)
+}
+
+fn values(ids: std::ops::Range<u64>) -> Vec<Value> {
+    ids.map(Value::with_id).collect()
+}
+
+/// Returns the number of bytes of the commitlog on the disk.
+fn commitlog_size(path: &str) -> u64 {
+    fs::read_dir(Path::new(path).join("commitlog"))
+        .expect("Failed to read commitlog directory")
+        .map(|file| file.unwrap().metadata().unwrap().len())
+        .sum()
+}
+
+/// Verifies that trimming deletes the segments of the trimmed entries, and that the remaining entries keep their
+/// indices after the storage is opened again.
+#[test]
+fn trim_reclaims_disk_space_test() {
+    let path = create_temp_dir();
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    storage
+        .append_entries(values(0..NUM_ENTRIES))
+        .expect("Failed to append");
+    let size_before_trim = commitlog_size(&path);
+
+    storage.trim(TRIM_IDX).expect("Failed to trim");
+    let size_after_trim = commitlog_size(&path);
+    assert!(
+        size_after_trim < size_before_trim / 2,
+        "The commitlog was not shrunk by trimming: {} bytes before and {} bytes after",
+        size_before_trim,
+        size_after_trim
+    );
+    assert_eq!(storage.get_log_len().unwrap(), NUM_ENTRIES - TRIM_IDX);
+    assert_eq!(
+        storage.get_suffix(0).unwrap(),
+        values(TRIM_IDX..NUM_ENTRIES)
+    );
+
+    drop(storage);
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    assert_eq!(storage.get_log_len().unwrap(), NUM_ENTRIES - TRIM_IDX);
+    assert_eq!(
+        storage.get_entries(10, 20).unwrap(),
+        values(TRIM_IDX + 10..TRIM_IDX + 20)
+    );
+
+    // Truncating and trimming again works on the remaining entries
+    storage
+        .append_on_prefix(0, values(0..10))
+        .expect("Failed to append");
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..10));
+    storage.trim(5).expect("Failed to trim");
+    assert_eq!(storage.get_suffix(0).unwrap(), values(5..10));
+}
+
+/// Verifies that truncating the end of the log deletes the segments after the new end.
+#[test]
+fn truncate_reclaims_disk_space_test() {
+    let path = create_temp_dir();
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    storage
+        .append_entries(values(0..NUM_ENTRIES))
+        .expect("Failed to append");
+    let size_before_truncate = commitlog_size(&path);
+
+    let truncated_len = NUM_ENTRIES - TRIM_IDX;
+    storage
+        .append_on_prefix(truncated_len, vec![])
+        .expect("Failed to truncate");
+    let size_after_truncate = commitlog_size(&path);
+    assert!(
+        size_after_truncate < size_before_truncate / 2,
+        "The commitlog was not shrunk by truncating: {} bytes before and {} bytes after",
+        size_before_truncate,
+        size_after_truncate
+    );
+    assert_eq!(storage.get_log_len().unwrap(), truncated_len);
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..truncated_len));
+}
+
+/// Verifies that the commitlog keeps the configured options when the whole log is truncated.
+#[test]
+fn truncate_whole_log_test() {
+    let path = create_temp_dir();
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    storage
+        .append_entries(values(0..10))
+        .expect("Failed to append");
+    storage
+        .append_on_prefix(0, vec![])
+        .expect("Failed to truncate");
+    assert_eq!(storage.get_log_len().unwrap(), 0);
+
+    storage
+        .append_entries(values(0..NUM_ENTRIES))
+        .expect("Failed to append");
+    let num_segments = fs::read_dir(Path::new(&path).join("commitlog"))
+        .expect("Failed to read commitlog directory")
+        .filter(|file| file.as_ref().unwrap().path().extension().unwrap() == "log")
+        .count();
+    assert!(
+        num_segments > 1,
+        "The commitlog has {} segments",
+        num_segments
+    );
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..NUM_ENTRIES));
+}
+
+/// Writes entries and state with the codec and verifies that they are read back after the storage is opened again.
+fn check_codec<C: Codec + Copy>(codec: C) {
+    let path = create_temp_dir();
+    let ballot = Ballot::with(1, 2, 3, 4);
+    let snapshot = ValueSnapshot::create(&values(0..5));
+    let mut storage: PersistentStorage<Value, C> =
+        PersistentStorage::open(codec_storage_config(&path, codec));
+    storage
+        .append_entries(values(0..10))
+        .expect("Failed to append");
+    storage.set_promise(ballot).expect("Failed to set promise");
+    storage
+        .set_accepted_round(ballot)
+        .expect("Failed to set accepted round");
+    storage
+        .set_snapshot(Some(snapshot.clone()))
+        .expect("Failed to set snapshot");
+
+    drop(storage);
+    let storage: PersistentStorage<Value, C> =
+        PersistentStorage::open(codec_storage_config(&path, codec));
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..10));
+    assert_eq!(storage.get_promise().unwrap(), Some(ballot));
+    assert_eq!(storage.get_accepted_round().unwrap(), Some(ballot));
+    assert_eq!(storage.get_snapshot().unwrap(), Some(snapshot));
+}
+
+#[test]
+fn json_codec_test() {
+    check_codec(JsonCodec);
+}
+
+#[test]
+fn postcard_codec_test() {
+    check_codec(PostcardCodec);
+}
+
+/// A custom codec that encodes with bincode and appends a marker byte.
+#[derive(Clone, Copy)]
+struct MarkedCodec;
+
+impl Codec for MarkedCodec {
+    fn name(&self) -> &str {
+        "marked"
+    }
+
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> StorageResult<Vec<u8>> {
+        let mut bytes = BincodeCodec.encode(value)?;
+        bytes.push(42);
+        Ok(bytes)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> StorageResult<V> {
+        match bytes.split_last() {
+            Some((42, value)) => BincodeCodec.decode(value),
+            _ => Err("Missing marker".into()),
+        }
+    }
+}
+
+#[test]
+fn custom_codec_test() {
+    check_codec(MarkedCodec);
+}
+
+/// Verifies that a storage cannot be opened with a different codec than the one it was created with.
+#[test]
+#[should_panic(expected = "Failed to open storage with the configured format")]
+fn codec_mismatch_test() {
+    let path = create_temp_dir();
+    let mut storage: PersistentStorage<Value, JsonCodec> =
+        PersistentStorage::open(codec_storage_config(&path, JsonCodec));
+    storage
+        .append_entries(values(0..10))
+        .expect("Failed to append");
+    drop(storage);
+    let _storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+}
+
+/// Verifies that a storage with an unknown format version cannot be opened.
+#[test]
+#[should_panic(expected = "Failed to open storage with the configured format")]
+fn format_version_mismatch_test() {
+    let path = create_temp_dir();
+    let storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    drop(storage);
+    {
+        let sled = Config::new()
+            .path(format!("{path}/database/"))
+            .open()
+            .unwrap();
+        let mut header = 3u32.to_le_bytes().to_vec();
+        header.extend_from_slice(b"bincode");
+        sled.insert(b"FORMAT", header).unwrap();
+        sled.flush().unwrap();
+    }
+    let _storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+}
+
+/// Flips the last byte of the commitlog, which belongs to the last entry.
+fn corrupt_last_entry(path: &str) {
+    let last_segment = fs::read_dir(Path::new(path).join("commitlog"))
+        .unwrap()
+        .map(|file| file.unwrap().path())
+        .filter(|file| file.extension().unwrap() == "log")
+        .max()
+        .unwrap();
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(last_segment)
+        .unwrap();
+    let mut byte = [0];
+    file.seek(SeekFrom::End(-1)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    file.seek(SeekFrom::End(-1)).unwrap();
+    file.write_all(&[!byte[0]]).unwrap();
+    file.sync_all().unwrap();
+}
+
+/// Verifies that a corrupted entry is reported when it is read and by `verify()`.
+#[test]
+fn corrupted_entry_test() {
+    let path = create_temp_dir();
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    storage
+        .append_entries(values(0..100))
+        .expect("Failed to append");
+    storage.verify().expect("Failed to verify storage");
+    drop(storage);
+    corrupt_last_entry(&path);
+
+    let storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    assert_eq!(storage.get_entries(0, 99).unwrap(), values(0..99));
+    let err = storage.get_suffix(90).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<CorruptedEntry>(),
+        Some(&CorruptedEntry { idx: 99 })
+    );
+    let err = storage.verify().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<CorruptedEntry>(),
+        Some(&CorruptedEntry { idx: 99 })
+    );
+}
+
+/// Verifies that the log is truncated at a corrupted entry when the storage is opened with `truncate_corrupted_tail`,
+/// that the decided index is lowered to the end of the remaining log, and that the accepted round is reset.
+#[test]
+fn truncate_corrupted_tail_test() {
+    let path = create_temp_dir();
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    storage
+        .append_entries(values(0..100))
+        .expect("Failed to append");
+    storage.set_decided_idx(100).expect("Failed to decide");
+    storage
+        .set_accepted_round(Ballot::with(1, 1, 0, 1))
+        .expect("Failed to set accepted round");
+    drop(storage);
+    corrupt_last_entry(&path);
+
+    let mut config = storage_config(&path);
+    config.set_truncate_corrupted_tail(true);
+    let mut storage: PersistentStorage<Value> = PersistentStorage::open(config);
+    storage.verify().expect("Failed to verify storage");
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..99));
+    assert_eq!(storage.get_decided_idx().unwrap(), 99);
+    assert_eq!(
+        storage.get_accepted_round().unwrap(),
+        Some(Ballot::default())
+    );
+
+    // The removed entries can be appended again
+    storage
+        .append_entries(values(99..100))
+        .expect("Failed to append");
+    drop(storage);
+    let storage: PersistentStorage<Value> = PersistentStorage::open(storage_config(&path));
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..100));
+}
+
+/// An entry that is larger than the first read of the commitlog.
+#[cfg(not(feature = "unicache"))]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct LargeValue(Vec<u8>);
+
+#[cfg(not(feature = "unicache"))]
+impl omnipaxos::storage::Entry for LargeValue {
+    type Snapshot = omnipaxos::storage::NoSnapshot;
+}
+
+/// Verifies that entries larger than 8 KiB are read, and that only the corrupted one of them is reported and
+/// truncated.
+#[cfg(not(feature = "unicache"))]
+#[test]
+fn large_entries_test() {
+    let path = create_temp_dir();
+    let config = || {
+        let log_opts = LogOptions::new(format!("{path}/commitlog/"));
+        PersistentStorageConfig::with(path.to_string(), log_opts, Config::new())
+    };
+    let entries: Vec<LargeValue> = (0..10).map(|i| LargeValue(vec![i; 20 * 1024])).collect();
+    let mut storage: PersistentStorage<LargeValue> = PersistentStorage::open(config());
+    storage
+        .append_entries(entries.clone())
+        .expect("Failed to append");
+    storage.set_decided_idx(10).expect("Failed to decide");
+    assert_eq!(storage.get_suffix(0).unwrap(), entries);
+    storage.verify().expect("Failed to verify storage");
+    drop(storage);
+    corrupt_last_entry(&path);
+
+    let storage: PersistentStorage<LargeValue> = PersistentStorage::open(config());
+    assert_eq!(storage.get_entries(0, 9).unwrap(), entries[..9]);
+    let err = storage.get_suffix(5).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<CorruptedEntry>(),
+        Some(&CorruptedEntry { idx: 9 })
+    );
+    drop(storage);
+
+    let mut truncate_config = config();
+    truncate_config.set_truncate_corrupted_tail(true);
+    let storage: PersistentStorage<LargeValue> = PersistentStorage::open(truncate_config);
+    assert_eq!(storage.get_suffix(0).unwrap(), entries[..9]);
+    assert_eq!(storage.get_decided_idx().unwrap(), 9);
+}
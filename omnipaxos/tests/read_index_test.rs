@@ -0,0 +1,106 @@
+pub mod utils;
+
+use kompact::prelude::*;
+use omnipaxos::util::{ReadId, ReadIndex};
+use serial_test::serial;
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use utils::{omnireplica::OmniPaxosComponent, TestConfig, TestSystem};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Verifies that read requests on both the leader and a follower are confirmed with a read index
+/// that covers all the entries that were decided before the read was requested.
+#[test]
+#[serial]
+fn read_index_test() {
+    let cfg = TestConfig::load("read_index_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, proposals, cfg.wait_timeout);
+
+    let follower = sys
+        .nodes
+        .keys()
+        .find(|pid| **pid != leader)
+        .copied()
+        .expect("No follower found");
+    for pid in [leader, follower] {
+        let node = sys.nodes.get(&pid).unwrap();
+        let read_id = node.on_definition(|x| x.paxos.read_index());
+        let read_index = wait_for_read_index(node, read_id, cfg.wait_timeout)
+            .unwrap_or_else(|| panic!("Read on node {} was not confirmed", pid));
+        assert!(
+            read_index.read_idx >= cfg.num_proposals,
+            "Read index {} does not cover the {} decided proposals",
+            read_index.read_idx,
+            cfg.num_proposals
+        );
+        let start = Instant::now();
+        while node.on_definition(|x| x.paxos.get_decided_idx()) < read_index.read_idx {
+            assert!(
+                start.elapsed() < cfg.wait_timeout,
+                "Node {} never decided up to the read index",
+                pid
+            );
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a leader that is disconnected from the rest of the cluster cannot confirm reads.
+#[test]
+#[serial]
+fn read_index_disconnected_leader_test() {
+    let cfg = TestConfig::load("read_index_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.set_node_connections(leader, false);
+    let node = sys.nodes.get(&leader).unwrap();
+    let read_id = node.on_definition(|x| x.paxos.read_index());
+    let read_index = wait_for_read_index(node, read_id, cfg.wait_timeout);
+    assert!(
+        read_index.is_none(),
+        "Disconnected leader confirmed read: {:?}",
+        read_index
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Polls `node` until the read `read_id` is confirmed or `timeout` has passed.
+fn wait_for_read_index(
+    node: &Arc<Component<OmniPaxosComponent>>,
+    read_id: ReadId,
+    timeout: Duration,
+) -> Option<ReadIndex> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        let confirmed = node.on_definition(|x| x.paxos.confirmed_reads());
+        if let Some(r) = confirmed.into_iter().find(|r| r.id == read_id) {
+            return Some(r);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    None
+}
@@ -2,7 +2,14 @@ pub mod utils;
 
 use crate::utils::STOPSIGN_ID;
 use kompact::prelude::{promise, Ask};
-use omnipaxos::{util::LogEntry, ClusterConfig};
+use omnipaxos::{
+    errors::ConfigError,
+    messages::migration::{LogPullRequest, LogPullResponse},
+    storage::StopSign,
+    util::LogEntry,
+    ClusterConfig, OmniPaxos, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
 use serial_test::serial;
 use utils::{TestConfig, TestSystem, Value};
 
@@ -27,6 +34,7 @@ fn reconfig_test() {
         configuration_id: new_config_id,
         nodes: new_nodes,
         flexible_quorum: None,
+        learners: vec![],
     };
     let metadata = Some(vec![SS_METADATA]);
 
@@ -87,3 +95,87 @@ fn reconfig_test() {
         Err(e) => panic!("Error on kompact shutdown: {}", e),
     };
 }
+
+/// Verifies that the servers of the next configuration start with the decided log of the stopped configuration,
+/// both when migrating an old server and when pulling the log to a new server.
+#[test]
+#[serial]
+fn reconfig_migrate_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(1, proposals.clone(), cfg.wait_timeout);
+
+    let new_pid = cfg.num_nodes as u64 + 1;
+    let new_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: (1..=new_pid).filter(|pid| *pid != 2).collect(),
+        ..Default::default()
+    };
+    sys.reconfigure(1, new_config.clone(), None, cfg.wait_timeout);
+
+    let first_node = sys.nodes.get(&1).unwrap();
+    let (migrated, pulled) = first_node.on_definition(|x| {
+        let migrated = x
+            .paxos
+            .migrate(
+                cfg.into_omnipaxos_config(1).server_config,
+                MemoryStorage::default(),
+            )
+            .expect("Failed to migrate");
+        let log = x
+            .paxos
+            .handle_log_pull(LogPullRequest {
+                from: new_pid,
+                to: 1,
+            })
+            .expect("No log for a decided StopSign");
+        assert_eq!(log.stopsign.next_config, new_config);
+        let pulled = OmniPaxos::from_log_pull(
+            log,
+            cfg.into_omnipaxos_config(new_pid).server_config,
+            MemoryStorage::default(),
+        )
+        .expect("Failed to create server from pulled log");
+        (migrated, pulled)
+    });
+    for server in [migrated, pulled] {
+        let log = server
+            .read_decided_suffix(0)
+            .expect("Failed to read decided suffix");
+        utils::verification::verify_log(log, proposals.clone());
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server of the next configuration is not started from a log that was trimmed without a snapshot,
+/// since it would start with a compacted index but without the state of the compacted entries.
+#[test]
+fn log_pull_trimmed_without_snapshot_test() {
+    let next_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: vec![1, 2, 3],
+        ..Default::default()
+    };
+    let log = LogPullResponse {
+        from: 1,
+        to: 3,
+        stopsign: StopSign::with(next_config, None),
+        compacted_idx: 10,
+        snapshot: None,
+        entries: vec![Value::with_id(10)],
+    };
+    let server_config = ServerConfig {
+        pid: 3,
+        ..Default::default()
+    };
+    let result = OmniPaxos::from_log_pull(log, server_config, MemoryStorage::default());
+    assert!(matches!(result, Err(ConfigError::InvalidConfig(_))));
+}
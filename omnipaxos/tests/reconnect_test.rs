@@ -6,7 +6,7 @@ use omnipaxos::{
     messages::{sequence_paxos::PaxosMsg, Message},
     storage::StopSign,
     util::{LogEntry, SequenceNumber},
-    ClusterConfig,
+    ClusterConfig, TransferLeadershipErr,
 };
 use serial_test::serial;
 use std::{thread, time::Duration};
@@ -393,6 +393,7 @@ fn resync_after_dropped_acceptstopsign_test() {
         configuration_id: 2,
         nodes: vec![1, 2],
         flexible_quorum: None,
+        learners: vec![],
     };
     leader.on_definition(|x| {
         x.set_connection(follower_id, false);
@@ -461,6 +462,7 @@ fn reconnect_after_dropped_acceptstopsign_test() {
         configuration_id: 2,
         nodes: vec![1, 2],
         flexible_quorum: None,
+        learners: vec![],
     };
     let leader = sys.nodes.get(&leader_id).unwrap();
     leader.on_definition(|x| {
@@ -518,6 +520,7 @@ fn reconnect_after_dropped_decidestopsign_test() {
         configuration_id: 2,
         nodes: vec![1, 2],
         flexible_quorum: None,
+        learners: vec![],
     };
     for other_follower in followers.clone() {
         sys.nodes.get(&other_follower).unwrap().on_definition(|x| {
@@ -557,3 +560,96 @@ fn reconnect_after_dropped_decidestopsign_test() {
         Err(e) => panic!("Error on kompact shutdown: {}", e),
     };
 }
+
+/// Verifies that with pre-vote enabled, a follower that rejoins after being partitioned does not
+/// increase its ballot and thereby disrupt the current leader.
+#[test]
+#[serial]
+fn pre_vote_rejoining_follower_test() {
+    // Start Kompact system
+    let cfg = TestConfig::load("pre_vote_test").expect("Test config couldn't be loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let initial_proposals = (0..INITIAL_PROPOSALS).map(Value::with_id).collect();
+    sys.make_proposals(1, initial_proposals, cfg.wait_timeout);
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let leader = sys.nodes.get(&leader_id).unwrap();
+    let leader_ballot = leader.on_definition(|x| x.paxos.get_promise());
+    let follower_id = (1..=cfg.num_nodes as u64)
+        .find(|x| *x != leader_id)
+        .expect("No followers found!");
+
+    // Partition the follower and let it rejoin
+    sys.set_node_connections(follower_id, false);
+    thread::sleep(SLEEP_TIMEOUT);
+    sys.set_node_connections(follower_id, true);
+    thread::sleep(SLEEP_TIMEOUT);
+
+    // Verify that the leader was not disrupted
+    for node in sys.nodes.values() {
+        let promise = node.on_definition(|x| x.paxos.get_promise());
+        assert_eq!(
+            promise, leader_ballot,
+            "The rejoining follower changed the leader ballot"
+        );
+    }
+
+    // Shutdown system
+    println!("Passed pre_vote_rejoining_follower_test!");
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that with check-quorum enabled, a leader that loses contact with a quorum steps down
+/// and that its proposals are decided once it rejoins the cluster.
+#[test]
+#[serial]
+fn check_quorum_partitioned_leader_test() {
+    // Start Kompact system
+    let cfg = TestConfig::load("pre_vote_test").expect("Test config couldn't be loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let initial_proposals = (0..INITIAL_PROPOSALS).map(Value::with_id).collect();
+    let leaders_proposals: Vec<Value> = (INITIAL_PROPOSALS..INITIAL_PROPOSALS + SECOND_PROPOSALS)
+        .map(Value::with_id)
+        .collect();
+    let expected_log = (0..INITIAL_PROPOSALS + SECOND_PROPOSALS)
+        .map(Value::with_id)
+        .collect();
+    sys.make_proposals(1, initial_proposals, cfg.wait_timeout);
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let leader = sys.nodes.get(&leader_id).unwrap();
+
+    // Partition the leader and wait for it to step down
+    sys.set_node_connections(leader_id, false);
+    thread::sleep(SLEEP_TIMEOUT);
+    let transfer_result = leader.on_definition(|x| x.paxos.transfer_leadership(leader_id));
+    assert!(
+        matches!(
+            transfer_result,
+            Err(TransferLeadershipErr::NotCurrentLeader(_))
+        ),
+        "Partitioned leader did not step down"
+    );
+
+    // Proposals of the old leader are decided after it rejoins
+    sys.set_node_connections(leader_id, true);
+    sys.make_proposals(leader_id, leaders_proposals, cfg.wait_timeout);
+    let leaders_log: Vec<LogEntry<Value>> = leader.on_definition(|x| x.read_decided_log());
+    verify_log(leaders_log, expected_log);
+
+    // Shutdown system
+    println!("Passed check_quorum_partitioned_leader_test!");
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
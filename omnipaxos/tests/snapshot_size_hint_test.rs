@@ -0,0 +1,170 @@
+//! Tests for choosing between a snapshot and the log entries when synchronizing a follower with
+//! `Snapshot::size_hint()`. The servers are driven directly so that the messages to the follower can be inspected.
+#![cfg(not(feature = "unicache"))]
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::{Entry, Snapshot},
+    util::SyncMetrics,
+    ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use utils::{
+    direct::{create_servers, deliver, follower_of, wait_for_leader},
+    Value,
+};
+
+const SNAPSHOT_SIZE: usize = 400;
+
+thread_local! {
+    // The number of snapshots created by the servers of a test, which are driven by the thread of the test
+    static CREATED_SNAPSHOTS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Command(u64);
+
+impl Entry for Command {
+    type Snapshot = SumSnapshot;
+}
+
+/// A snapshot of a fixed size that is larger than a few entries but smaller than many entries.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct SumSnapshot {
+    sum: u64,
+}
+
+impl Snapshot<Command> for SumSnapshot {
+    fn create(entries: &[Command]) -> Self {
+        CREATED_SNAPSHOTS.with(|c| c.set(c.get() + 1));
+        Self {
+            sum: entries.iter().map(|c| c.0).sum(),
+        }
+    }
+
+    fn merge(&mut self, delta: Self) {
+        self.sum += delta.sum;
+    }
+
+    fn use_snapshots() -> bool {
+        true
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(SNAPSHOT_SIZE)
+    }
+}
+
+/// Decides `entries` while the messages to and from a follower are dropped, then reconnects the follower. Returns
+/// whether the follower was synchronized with a snapshot and the sync metrics of the leader.
+fn sync_follower<T: Entry>(entries: impl Iterator<Item = T>) -> (bool, SyncMetrics) {
+    let mut servers = create_servers::<T>(ServerConfig::default());
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    let server = servers.get_mut(&leader).unwrap();
+    for entry in entries {
+        server.append(entry).expect("Failed to append");
+    }
+    deliver(&mut servers, |msg| {
+        msg.get_receiver() == follower || msg.get_sender() == follower
+    });
+    let metrics = servers[&leader].get_sync_metrics();
+
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    let msgs = deliver(&mut servers, |_| false);
+    let snapshot_sync = msgs
+        .iter()
+        .find_map(|msg| match msg {
+            Message::SequencePaxos(m) if m.to == follower => match &m.msg {
+                PaxosMsg::AcceptSync(a) => Some(a.decided_snapshot.is_some()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .expect("The follower was not synchronized");
+    assert_eq!(
+        servers[&follower].get_decided_idx(),
+        servers[&leader].get_decided_idx()
+    );
+    let sync_metrics = servers[&leader].get_sync_metrics();
+    let new_metrics = SyncMetrics {
+        snapshot_syncs: sync_metrics.snapshot_syncs - metrics.snapshot_syncs,
+        log_syncs: sync_metrics.log_syncs - metrics.log_syncs,
+        larger_snapshots: sync_metrics.larger_snapshots - metrics.larger_snapshots,
+    };
+    (snapshot_sync, new_metrics)
+}
+
+/// Verifies that a follower that misses only a few entries is sent the entries instead of a larger snapshot.
+#[test]
+fn sync_smaller_entries_test() {
+    let num_entries = SNAPSHOT_SIZE / Command(0).size_hint() / 2;
+    let (snapshot_sync, metrics) = sync_follower((0..num_entries as u64).map(Command));
+    assert!(!snapshot_sync);
+    assert_eq!(
+        metrics,
+        SyncMetrics {
+            snapshot_syncs: 0,
+            log_syncs: 1,
+            larger_snapshots: 1,
+        }
+    );
+}
+
+/// Verifies that a follower that misses many entries is sent a snapshot that is smaller than the entries.
+#[test]
+fn sync_smaller_snapshot_test() {
+    let num_entries = SNAPSHOT_SIZE / Command(0).size_hint() * 2;
+    let (snapshot_sync, metrics) = sync_follower((0..num_entries as u64).map(Command));
+    assert!(snapshot_sync);
+    assert_eq!(
+        metrics,
+        SyncMetrics {
+            snapshot_syncs: 1,
+            log_syncs: 0,
+            larger_snapshots: 0,
+        }
+    );
+}
+
+/// Verifies that a snapshot without a size hint is always used to synchronize a follower that is behind.
+#[test]
+fn sync_without_size_hint_test() {
+    let (snapshot_sync, metrics) = sync_follower((0..2).map(Value::with_id));
+    assert!(snapshot_sync);
+    assert_eq!(metrics.snapshot_syncs, 1);
+    assert_eq!(metrics.larger_snapshots, 0);
+}
+
+/// Verifies that no snapshot is created to synchronize a follower if a snapshot of the same entries was already larger
+/// than the entries that the follower misses.
+#[test]
+fn skip_larger_snapshot_test() {
+    let num_entries = (SNAPSHOT_SIZE / Command(0).size_hint() / 4) as u64;
+    let mut servers = create_servers::<Command>(ServerConfig::default());
+    let leader = wait_for_leader(&mut servers);
+    let follower = follower_of(leader);
+    let server = servers.get_mut(&leader).unwrap();
+    for i in 0..num_entries {
+        server.append(Command(i)).expect("Failed to append");
+    }
+    deliver(&mut servers, |msg| {
+        msg.get_receiver() == follower || msg.get_sender() == follower
+    });
+    // The first synchronization is lost, so the follower is synchronized from the same index again
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    deliver(
+        &mut servers,
+        |msg| matches!(msg, Message::SequencePaxos(m) if m.to == follower && matches!(m.msg, PaxosMsg::AcceptSync(_))),
+    );
+    assert_eq!(servers[&follower].get_decided_idx(), 0);
+    servers.get_mut(&follower).unwrap().reconnected(leader);
+    deliver(&mut servers, |_| false);
+    assert_eq!(servers[&follower].get_decided_idx(), num_entries);
+    assert_eq!(CREATED_SNAPSHOTS.with(|c| c.get()), 1);
+    let metrics = servers[&leader].get_sync_metrics();
+    assert_eq!(metrics.snapshot_syncs, 0);
+    assert_eq!(metrics.larger_snapshots, 2);
+}
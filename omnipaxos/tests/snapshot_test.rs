@@ -125,6 +125,68 @@ fn double_snapshot_test() {
     };
 }
 
+/// Verifies that `poll_decided` returns each decided entry exactly once and that a server whose
+/// applied index lags behind a snapshot gets the snapshot in place of the compacted entries.
+#[test]
+#[serial]
+fn poll_decided_snapshot_test() {
+    let cfg = TestConfig::load("trim_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let elected_pid = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower_pid = (1..=cfg.num_nodes as u64)
+        .find(|pid| *pid != elected_pid)
+        .unwrap();
+    let follower = sys.nodes.get(&follower_pid).unwrap();
+
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let (first_proposals, last_proposals) = vec_proposals.split_at(cfg.trim_idx as usize / 2);
+    sys.make_proposals(elected_pid, first_proposals.to_vec(), cfg.wait_timeout);
+    thread::sleep(cfg.wait_timeout); // wait for the follower to decide the proposals
+    let polled = follower.on_definition(|x| x.paxos.poll_decided());
+    let polled_values: Vec<Value> = polled
+        .into_iter()
+        .map(|e| match e {
+            LogEntry::Decided(v) => v,
+            e => panic!("Unexpected entry: {:?}", e),
+        })
+        .collect();
+    assert_eq!(polled_values, first_proposals);
+    assert!(follower
+        .on_definition(|x| x.paxos.poll_decided())
+        .is_empty());
+
+    sys.make_proposals(elected_pid, last_proposals.to_vec(), cfg.wait_timeout);
+    sys.nodes.get(&elected_pid).unwrap().on_definition(|x| {
+        x.paxos
+            .snapshot(Some(cfg.trim_idx), false)
+            .expect("Failed to snapshot");
+    });
+    thread::sleep(cfg.wait_timeout);
+
+    let exp_snapshot = ValueSnapshot::create(&vec_proposals[0..cfg.trim_idx as usize]);
+    follower.on_definition(|x| {
+        let polled = x.paxos.poll_decided();
+        match polled.first() {
+            Some(LogEntry::Snapshotted(s))
+                if s.snapshot == exp_snapshot && s.trimmed_idx == cfg.trim_idx => {}
+            e => panic!("First polled entry should be the snapshot, but was {:?}", e),
+        }
+        assert_eq!(
+            polled.len(),
+            (cfg.num_proposals - cfg.trim_idx) as usize + 1
+        );
+        assert_eq!(x.paxos.get_applied_idx(), cfg.num_proposals);
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
 fn check_snapshot(
     vec_proposals: &Vec<Value>,
     snapshot_idx: u64,
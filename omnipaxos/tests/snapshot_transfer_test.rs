@@ -0,0 +1,297 @@
+//! Tests for the out-of-band transfer of snapshots of the application state with `OmniPaxos::set_snapshot_transfer`.
+//! The servers are driven directly so that the messages to the follower can be inspected and dropped.
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::{SnapshotInstaller, SnapshotMetadata, SnapshotProvider, StorageResult},
+    util::{LogEntry, NodeId},
+    ServerConfig,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use utils::{
+    direct::{
+        create_server, deliver, deliver_round, follower_of, tick, wait_for_leader, Servers,
+        MAX_TICKS, NUM_NODES,
+    },
+    Value,
+};
+
+const NUM_PROPOSALS: u64 = 100;
+const CHUNK_SIZE: usize = 64;
+type StoredSnapshot = (SnapshotMetadata, Vec<u8>);
+
+/// The snapshots of the application state of a server. The state is the list of the decided values.
+#[derive(Clone, Default)]
+struct TestProvider {
+    snapshot: Arc<Mutex<Option<StoredSnapshot>>>,
+}
+
+impl SnapshotProvider for TestProvider {
+    fn latest_snapshot(&mut self) -> StorageResult<Option<SnapshotMetadata>> {
+        Ok(self.snapshot.lock().unwrap().as_ref().map(|(m, _)| *m))
+    }
+
+    fn read_chunk(&mut self, metadata: &SnapshotMetadata, offset: u64) -> StorageResult<Vec<u8>> {
+        let snapshot = self.snapshot.lock().unwrap();
+        match snapshot.as_ref() {
+            Some((m, bytes)) if m == metadata => {
+                let end = (offset as usize + CHUNK_SIZE).min(bytes.len());
+                Ok(bytes[offset as usize..end].to_vec())
+            }
+            _ => Err("The snapshot does not exist".into()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct InstallerState {
+    num_begins: usize,
+    received: Vec<u8>,
+    installed: Option<StoredSnapshot>,
+}
+
+#[derive(Clone, Default)]
+struct TestInstaller {
+    state: Arc<Mutex<InstallerState>>,
+}
+
+impl SnapshotInstaller for TestInstaller {
+    fn begin(&mut self, _metadata: &SnapshotMetadata) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.num_begins += 1;
+        state.received.clear();
+        Ok(())
+    }
+
+    fn write_chunk(
+        &mut self,
+        _metadata: &SnapshotMetadata,
+        offset: u64,
+        data: &[u8],
+    ) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+        assert_eq!(offset as usize, state.received.len(), "Chunk out of order");
+        state.received.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn install(&mut self, metadata: &SnapshotMetadata) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+        assert_eq!(metadata.size as usize, state.received.len());
+        let received = std::mem::take(&mut state.received);
+        state.installed = Some((*metadata, received));
+        Ok(())
+    }
+}
+
+struct Cluster {
+    servers: Servers<Value>,
+    providers: HashMap<NodeId, TestProvider>,
+    installers: HashMap<NodeId, TestInstaller>,
+}
+
+impl Cluster {
+    fn new() -> Self {
+        let mut cluster = Cluster {
+            servers: HashMap::new(),
+            providers: HashMap::new(),
+            installers: HashMap::new(),
+        };
+        for pid in 1..=NUM_NODES {
+            cluster.start_server(pid);
+        }
+        cluster
+    }
+
+    /// Starts the server `pid` with an empty storage, replacing the server if it exists.
+    fn start_server(&mut self, pid: NodeId) {
+        let mut server = create_server(pid, ServerConfig::default());
+        let provider = TestProvider::default();
+        let installer = TestInstaller::default();
+        server.set_snapshot_transfer(provider.clone(), installer.clone());
+        self.servers.insert(pid, server);
+        self.providers.insert(pid, provider);
+        self.installers.insert(pid, installer);
+    }
+
+    fn propose(&mut self, leader: NodeId, ids: std::ops::Range<u64>) {
+        let server = self.servers.get_mut(&leader).unwrap();
+        for i in ids {
+            server.append(Value::with_id(i)).expect("Failed to append");
+        }
+    }
+
+    /// Makes the application of `pid` take a snapshot of its state at the decided index.
+    fn take_snapshot(&mut self, pid: NodeId, id: u64) -> SnapshotMetadata {
+        let server = &self.servers[&pid];
+        let idx = server.get_decided_idx();
+        let (prev_idx, mut bytes) = match self.providers[&pid].snapshot.lock().unwrap().clone() {
+            Some((metadata, bytes)) => (metadata.idx, bytes),
+            None => (0, vec![]),
+        };
+        for entry in server.read_entries(prev_idx..idx).unwrap() {
+            match entry {
+                LogEntry::Decided(v) => bytes.extend(format!("{:?}\n", v).into_bytes()),
+                e => panic!("The log is compacted: {:?}", e),
+            }
+        }
+        let metadata = SnapshotMetadata {
+            idx,
+            size: bytes.len() as u64,
+            id,
+        };
+        *self.providers[&pid].snapshot.lock().unwrap() = Some((metadata, bytes));
+        metadata
+    }
+
+    /// Decides `NUM_PROPOSALS` entries, of which the leader takes a snapshot and trims the log, and then replaces a
+    /// follower with a new server that has lost its storage. Returns the leader, the follower, and the snapshot.
+    fn replace_follower_after_trim(&mut self) -> (NodeId, NodeId, SnapshotMetadata) {
+        let leader = wait_for_leader(&mut self.servers);
+        let follower = follower_of(leader);
+        self.propose(leader, 0..NUM_PROPOSALS);
+        deliver(&mut self.servers, |_| false);
+        let metadata = self.take_snapshot(leader, 1);
+        self.servers
+            .get_mut(&leader)
+            .unwrap()
+            .trim(Some(metadata.idx))
+            .expect("Failed to trim");
+        self.propose(leader, NUM_PROPOSALS..NUM_PROPOSALS + 10);
+        deliver(&mut self.servers, |_| false);
+        assert_eq!(self.servers[&leader].get_compacted_idx(), NUM_PROPOSALS);
+        self.start_server(follower);
+        // The new server asks the leader to be synchronized
+        self.servers.get_mut(&follower).unwrap().reconnected(leader);
+        (leader, follower, metadata)
+    }
+
+    fn installed_snapshot(&self, pid: NodeId) -> Option<StoredSnapshot> {
+        self.installers[&pid]
+            .state
+            .lock()
+            .unwrap()
+            .installed
+            .clone()
+    }
+
+    fn num_begins(&self, pid: NodeId) -> usize {
+        self.installers[&pid].state.lock().unwrap().num_begins
+    }
+
+    fn decided_entries(&self, pid: NodeId) -> Vec<Value> {
+        let server = &self.servers[&pid];
+        server
+            .read_decided_suffix(server.get_compacted_idx())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                LogEntry::Decided(value) => Some(value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Checks that `follower` has installed the latest snapshot of the leader and has the rest of the log.
+    fn assert_synchronized(&self, leader: NodeId, follower: NodeId) {
+        let (metadata, bytes) = self.providers[&leader]
+            .snapshot
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap();
+        assert_eq!(self.installed_snapshot(follower), Some((metadata, bytes)));
+        assert_eq!(self.servers[&follower].get_compacted_idx(), metadata.idx);
+        assert_eq!(
+            self.servers[&follower].get_decided_idx(),
+            self.servers[&leader].get_decided_idx(),
+            "The follower did not decide the entries of the leader"
+        );
+        let leader_entries = self.decided_entries(leader);
+        let skip = (metadata.idx - self.servers[&leader].get_compacted_idx()) as usize;
+        assert_eq!(self.decided_entries(follower), leader_entries[skip..]);
+    }
+}
+
+fn is_snapshot_chunk(msg: &Message<Value>) -> bool {
+    matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::SnapshotChunk(_)))
+}
+
+/// Verifies that a follower whose missing entries have been trimmed pulls the snapshot of the application state in
+/// chunks and is then synchronized with the log from the index of the snapshot.
+#[test]
+fn snapshot_transfer_test() {
+    let mut cluster = Cluster::new();
+    let (leader, follower, metadata) = cluster.replace_follower_after_trim();
+    let msgs = deliver(&mut cluster.servers, |_| false);
+    cluster.assert_synchronized(leader, follower);
+    assert_eq!(cluster.num_begins(follower), 1);
+    let num_chunks = msgs.iter().filter(|msg| is_snapshot_chunk(msg)).count();
+    assert_eq!(num_chunks, (metadata.size as usize).div_ceil(CHUNK_SIZE));
+    // The snapshot is not sent in the messages of OmniPaxos
+    for msg in msgs {
+        if let Message::SequencePaxos(m) = msg {
+            if let PaxosMsg::AcceptSync(a) = m.msg {
+                assert!(a.decided_snapshot.is_none());
+                assert_eq!(a.sync_idx, metadata.idx);
+            }
+        }
+    }
+    let decided = cluster.servers.get_mut(&follower).unwrap().poll_decided();
+    assert!(matches!(decided.first(), Some(LogEntry::Trimmed(idx)) if *idx == metadata.idx));
+    assert_eq!(decided.len(), 11);
+}
+
+/// Verifies that the transfer is resumed from the lost chunk.
+#[test]
+fn resume_snapshot_transfer_test() {
+    let mut cluster = Cluster::new();
+    let (leader, follower, _) = cluster.replace_follower_after_trim();
+    let mut dropped = false;
+    while !deliver_round(&mut cluster.servers, &mut |msg| {
+        let drop = !dropped && is_snapshot_chunk(msg);
+        dropped |= drop;
+        drop
+    })
+    .is_empty()
+    {}
+    assert!(dropped);
+    assert!(cluster.installed_snapshot(follower).is_none());
+
+    for _ in 0..MAX_TICKS {
+        tick(&mut cluster.servers);
+        if cluster.installed_snapshot(follower).is_some() {
+            break;
+        }
+    }
+    deliver(&mut cluster.servers, |_| false);
+    cluster.assert_synchronized(leader, follower);
+    assert_eq!(cluster.num_begins(follower), 1);
+}
+
+/// Verifies that the follower pulls the new snapshot if the leader's snapshot is replaced during the transfer.
+#[test]
+fn replaced_snapshot_transfer_test() {
+    let mut cluster = Cluster::new();
+    let (leader, follower, metadata) = cluster.replace_follower_after_trim();
+    for _ in 0..4 {
+        deliver_round(&mut cluster.servers, &mut |_| false);
+    }
+    assert_eq!(cluster.num_begins(follower), 1);
+    assert!(cluster.installed_snapshot(follower).is_none());
+    cluster.propose(leader, NUM_PROPOSALS + 10..NUM_PROPOSALS + 20);
+    while cluster.servers[&leader].get_decided_idx() < NUM_PROPOSALS + 20 {
+        deliver_round(&mut cluster.servers, &mut |_| false);
+    }
+    assert!(cluster.installed_snapshot(follower).is_none());
+    let new_metadata = cluster.take_snapshot(leader, 2);
+    assert!(new_metadata.idx > metadata.idx);
+
+    deliver(&mut cluster.servers, |_| false);
+    cluster.assert_synchronized(leader, follower);
+    assert_eq!(cluster.num_begins(follower), 2);
+}
@@ -0,0 +1,258 @@
+//! Tests that `SledStorage` and `FileStorage` behave like `MemoryStorage` and recover their state when they are opened
+//! again.
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Membership, Snapshot, Storage, StorageOp},
+    ClusterConfig,
+};
+use omnipaxos_storage::{
+    file_storage::{FileStorage, FileStorageConfig},
+    memory_storage::MemoryStorage,
+    sled_storage::{SledStorage, SledStorageConfig},
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::Path,
+};
+use utils::{create_temp_dir, Value, ValueSnapshot};
+
+const NUM_ENTRIES: u64 = 1000;
+const TRIM_IDX: u64 = 900;
+const SEGMENT_MAX_BYTES: u64 = 1024;
+
+/// The state of a storage that is compared between the backends.
+#[derive(Debug, PartialEq)]
+struct StorageState {
+    promise: Ballot,
+    accepted_round: Ballot,
+    decided_idx: u64,
+    compacted_idx: u64,
+    membership: Option<Membership>,
+    snapshot: Option<ValueSnapshot>,
+    log: Vec<Value>,
+}
+
+impl StorageState {
+    fn read<S: Storage<Value>>(storage: &S) -> Self {
+        Self {
+            promise: storage.get_promise().unwrap().unwrap_or_default(),
+            accepted_round: storage.get_accepted_round().unwrap().unwrap_or_default(),
+            decided_idx: storage.get_decided_idx().unwrap(),
+            compacted_idx: storage.get_compacted_idx().unwrap(),
+            membership: storage.get_membership().unwrap(),
+            snapshot: storage.get_snapshot().unwrap(),
+            log: storage.get_suffix(0).unwrap(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Write {
+    Atomic(Vec<StorageOp<Value>>),
+    AppendEntries(Vec<Value>),
+    AppendOnPrefix(u64, Vec<Value>),
+    DecidedIdx(u64),
+    Membership(Membership),
+    Snapshot(ValueSnapshot),
+    CompactedIdx(u64),
+    Trim(u64),
+}
+
+fn values(ids: std::ops::Range<u64>) -> Vec<Value> {
+    ids.map(Value::with_id).collect()
+}
+
+fn ballot(n: u32) -> Ballot {
+    Ballot::with(1, n, 0, 1)
+}
+
+fn workload() -> Vec<Write> {
+    vec![
+        Write::Atomic(vec![StorageOp::SetPromise(ballot(1))]),
+        Write::AppendEntries(values(0..5)),
+        Write::Atomic(vec![
+            StorageOp::SetAcceptedRound(ballot(1)),
+            StorageOp::SetDecidedIdx(2),
+            StorageOp::AppendOnPrefix(2, values(10..13)),
+        ]),
+        Write::Atomic(vec![
+            StorageOp::AppendEntries(values(20..22)),
+            StorageOp::AppendOnPrefix(4, values(30..31)),
+        ]),
+        Write::DecidedIdx(5),
+        Write::Membership(Membership {
+            config: ClusterConfig {
+                configuration_id: 1,
+                nodes: vec![1, 2, 3, 4],
+                ..Default::default()
+            },
+            version: 1,
+            n: ballot(1),
+        }),
+        Write::Snapshot(ValueSnapshot::create(&values(0..2))),
+        Write::CompactedIdx(2),
+        Write::Trim(2),
+        Write::AppendOnPrefix(1, values(40..43)),
+        Write::AppendOnPrefix(10, values(50..51)),
+        Write::Atomic(vec![
+            StorageOp::SetAcceptedRound(ballot(2)),
+            StorageOp::AppendOnPrefix(0, values(60..62)),
+        ]),
+        Write::Trim(10),
+        Write::AppendEntries(values(70..72)),
+    ]
+}
+
+fn apply<S: Storage<Value>>(storage: &mut S, write: Write) {
+    match write {
+        Write::Atomic(ops) => storage.write_atomically(ops).map(|_| ()),
+        Write::AppendEntries(entries) => storage.append_entries(entries).map(|_| ()),
+        Write::AppendOnPrefix(from_idx, entries) => {
+            storage.append_on_prefix(from_idx, entries).map(|_| ())
+        }
+        Write::DecidedIdx(idx) => storage.set_decided_idx(idx),
+        Write::Membership(m) => storage.set_membership(Some(m)),
+        Write::Snapshot(snapshot) => storage.set_snapshot(Some(snapshot)),
+        Write::CompactedIdx(idx) => storage.set_compacted_idx(idx),
+        Write::Trim(idx) => storage.trim(idx),
+    }
+    .expect("Failed to write to storage");
+}
+
+/// Runs the workload on the storage and on a `MemoryStorage` and compares them after every write and after the
+/// storage is opened again.
+fn check_storage<S: Storage<Value>>(open: impl Fn() -> S) {
+    let mut memory_storage = MemoryStorage::default();
+    let mut storage = open();
+    assert_eq!(
+        StorageState::read(&storage),
+        StorageState::read(&memory_storage)
+    );
+    for write in workload() {
+        apply(&mut memory_storage, write.clone());
+        apply(&mut storage, write);
+        assert_eq!(
+            StorageState::read(&storage),
+            StorageState::read(&memory_storage)
+        );
+        assert_eq!(
+            storage.get_log_len().unwrap(),
+            memory_storage.get_log_len().unwrap()
+        );
+    }
+    drop(storage);
+    let storage = open();
+    assert_eq!(
+        StorageState::read(&storage),
+        StorageState::read(&memory_storage)
+    );
+}
+
+fn file_storage_config(path: &str) -> FileStorageConfig {
+    let mut config = FileStorageConfig::with(path.to_string());
+    config.set_segment_max_bytes(SEGMENT_MAX_BYTES);
+    config
+}
+
+/// Returns the number of bytes of the files in the directory.
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .expect("Failed to read directory")
+        .map(|file| file.unwrap().metadata().unwrap().len())
+        .sum()
+}
+
+#[test]
+fn sled_storage_test() {
+    let path = create_temp_dir();
+    check_storage(|| SledStorage::open(SledStorageConfig::with(path.clone(), sled::Config::new())));
+}
+
+#[test]
+fn file_storage_test() {
+    let path = create_temp_dir();
+    check_storage(|| FileStorage::open(file_storage_config(&path)));
+}
+
+/// Verifies that trimming and truncating the log of `FileStorage` deletes its segments.
+#[test]
+fn file_storage_segments_test() {
+    let path = create_temp_dir();
+    let log_dir = Path::new(&path).join("log");
+    let mut storage: FileStorage<Value> = FileStorage::open(file_storage_config(&path));
+    storage
+        .append_entries(values(0..NUM_ENTRIES))
+        .expect("Failed to append");
+    let size_before_trim = dir_size(&log_dir);
+
+    storage.trim(TRIM_IDX).expect("Failed to trim");
+    let size_after_trim = dir_size(&log_dir);
+    assert!(
+        size_after_trim < size_before_trim / 2,
+        "The log was not shrunk by trimming: {} bytes before and {} bytes after",
+        size_before_trim,
+        size_after_trim
+    );
+    assert_eq!(
+        storage.get_suffix(0).unwrap(),
+        values(TRIM_IDX..NUM_ENTRIES)
+    );
+
+    storage
+        .append_entries(values(NUM_ENTRIES..2 * NUM_ENTRIES))
+        .expect("Failed to append");
+    let size_before_truncate = dir_size(&log_dir);
+    storage
+        .append_on_prefix(10, vec![])
+        .expect("Failed to truncate");
+    let size_after_truncate = dir_size(&log_dir);
+    assert!(
+        size_after_truncate < size_before_truncate / 2,
+        "The log was not shrunk by truncating: {} bytes before and {} bytes after",
+        size_before_truncate,
+        size_after_truncate
+    );
+
+    drop(storage);
+    let storage: FileStorage<Value> = FileStorage::open(file_storage_config(&path));
+    assert_eq!(
+        storage.get_suffix(0).unwrap(),
+        values(TRIM_IDX..TRIM_IDX + 10)
+    );
+}
+
+/// Verifies that `FileStorage` removes a partially written entry at the end of the log when it is opened.
+#[test]
+fn file_storage_torn_write_test() {
+    let path = create_temp_dir();
+    let log_dir = Path::new(&path).join("log");
+    let mut storage: FileStorage<Value> = FileStorage::open(file_storage_config(&path));
+    storage
+        .append_entries(values(0..10))
+        .expect("Failed to append");
+    drop(storage);
+
+    let last_segment = fs::read_dir(&log_dir)
+        .unwrap()
+        .map(|file| file.unwrap().path())
+        .max()
+        .unwrap();
+    OpenOptions::new()
+        .append(true)
+        .open(last_segment)
+        .unwrap()
+        .write_all(&[42, 0, 0, 0, 1, 2])
+        .unwrap();
+
+    let mut storage: FileStorage<Value> = FileStorage::open(file_storage_config(&path));
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..10));
+    storage
+        .append_entries(values(10..12))
+        .expect("Failed to append");
+    drop(storage);
+    let storage: FileStorage<Value> = FileStorage::open(file_storage_config(&path));
+    assert_eq!(storage.get_suffix(0).unwrap(), values(0..12));
+}
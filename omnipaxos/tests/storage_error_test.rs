@@ -0,0 +1,211 @@
+//! Tests for halting a server on storage errors with `halt_on_storage_error` and resuming it afterwards. Storage
+//! errors are injected with the mock-broken storage while the server is driven directly with messages.
+pub mod utils;
+
+#[cfg(feature = "unicache")]
+use omnipaxos::storage::Entry;
+#[cfg(feature = "unicache")]
+use omnipaxos::unicache::UniCache;
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    messages::{
+        sequence_paxos::{AcceptDecide, AcceptSync, PaxosMessage, PaxosMsg, Prepare},
+        Message,
+    },
+    storage::Storage,
+    util::{NodeId, ProposalStatus, SequenceNumber},
+    OmniPaxos, OmniPaxosConfig, ProposeErr,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+use utils::{BrokenStorageConfig, StorageType, TestConfig, Value};
+
+type MemoryStore = Arc<Mutex<MemoryStorage<Value>>>;
+type BrokenStore = Arc<Mutex<BrokenStorageConfig>>;
+
+/// Creates a new OmniPaxos instance with `BrokenStorage` that halts on storage errors and makes it a follower of
+/// server 2 in the `FOLLOWER ACCEPT` state. The next expected sequence number is `SequenceNumber{session: 1, counter: 2}`.
+fn setup_follower() -> (
+    MemoryStore,
+    BrokenStore,
+    OmniPaxos<Value, StorageType<Value>>,
+) {
+    let cfg = TestConfig::load("storage_error_test").expect("Test config loaded");
+    let storage = StorageType::with(cfg.storage_type, "");
+    let (mem_storage, storage_conf) = if let StorageType::Broken(ref s, ref c) = storage {
+        (s.clone(), c.clone())
+    } else {
+        panic!("using wrong storage for storage_error_test")
+    };
+    let mut op_config = OmniPaxosConfig::default();
+    op_config.server_config.pid = 1;
+    op_config.server_config.halt_on_storage_error = true;
+    op_config.cluster_config.nodes = (1..=cfg.num_nodes as NodeId).collect();
+    op_config.cluster_config.configuration_id = 1;
+    let mut op = op_config.build(storage).unwrap();
+
+    let mut n = mem_storage.lock().unwrap().get_promise().unwrap().unwrap();
+    n.config_id = 1;
+    n.n += 1;
+    n.pid = 2;
+    op.handle_incoming(prepare(n, 0));
+    op.handle_incoming(Message::SequencePaxos(PaxosMessage {
+        from: 2,
+        to: 1,
+        msg: PaxosMsg::AcceptSync(AcceptSync {
+            n,
+            seq_num: SequenceNumber {
+                session: 1,
+                counter: 1,
+            },
+            decided_snapshot: None,
+            suffix: vec![],
+            sync_idx: 0,
+            decided_idx: 0,
+            stopsign: None,
+            membership: None,
+            #[cfg(feature = "unicache")]
+            unicache: <Value as Entry>::UniCache::new(),
+        }),
+    }));
+    op.outgoing_messages();
+    assert_eq!(op.get_current_leader(), Some(2), "node 2 should be leader");
+    (mem_storage, storage_conf, op)
+}
+
+fn prepare(n: Ballot, accepted_idx: u64) -> Message<Value> {
+    Message::SequencePaxos(PaxosMessage {
+        from: 2,
+        to: 1,
+        msg: PaxosMsg::Prepare(Prepare {
+            n,
+            decided_idx: 0,
+            accepted_idx,
+            n_accepted: n,
+        }),
+    })
+}
+
+fn accept_decide(
+    mem_storage: &MemoryStore,
+    counter: u64,
+    decided_idx: u64,
+    ids: std::ops::Range<u64>,
+) -> Message<Value> {
+    Message::SequencePaxos(PaxosMessage {
+        from: 2,
+        to: 1,
+        msg: PaxosMsg::AcceptDecide(AcceptDecide {
+            n: mem_storage.lock().unwrap().get_promise().unwrap().unwrap(),
+            seq_num: SequenceNumber {
+                session: 1,
+                counter,
+            },
+            decided_idx,
+            entries: ids.map(Value::with_id).collect(),
+        }),
+    })
+}
+
+/// Verifies that a failed write halts the server instead of panicking, that a halted server neither handles messages
+/// nor accepts proposals, and that it rejoins the cluster with a consistent storage once it is resumed.
+#[test]
+#[serial]
+fn halt_and_resume_test() {
+    let (mem_storage, storage_conf, mut op) = setup_follower();
+    let proposal = op
+        .append(Value::with_id(100))
+        .expect("Failed to append before the storage error");
+    op.handle_incoming(accept_decide(&mem_storage, 2, 0, 1..3));
+    let old_decided_idx = mem_storage.lock().unwrap().get_decided_idx().unwrap();
+    let old_log_len = mem_storage.lock().unwrap().get_log_len().unwrap();
+
+    // Fail the write of the entries after the decided index has been written
+    storage_conf.lock().unwrap().schedule_failure_in(2);
+    op.handle_incoming(accept_decide(&mem_storage, 3, 2, 3..5));
+    assert!(op.storage_error().is_some(), "server should be halted");
+    assert!(op.outgoing_messages().is_empty());
+    {
+        let s = mem_storage.lock().unwrap();
+        assert_eq!(s.get_decided_idx().unwrap(), old_decided_idx);
+        assert_eq!(s.get_log_len().unwrap(), old_log_len);
+    }
+
+    // A halted server ignores messages and rejects proposals
+    op.handle_incoming(accept_decide(&mem_storage, 3, 2, 3..5));
+    op.tick();
+    assert!(op.outgoing_messages().is_empty());
+    assert_eq!(
+        mem_storage.lock().unwrap().get_log_len().unwrap(),
+        old_log_len
+    );
+    match op.append(Value::with_id(101)) {
+        Err(ProposeErr::HaltedEntry(v)) => assert_eq!(v, Value::with_id(101)),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+
+    // The resumed server asks the peers for the leader to synchronize it
+    op.resume().expect("Failed to resume");
+    assert!(op.storage_error().is_none());
+    let outcomes = op.proposal_outcomes();
+    assert!(outcomes
+        .iter()
+        .any(|o| o.id == proposal && matches!(o.status, ProposalStatus::Dropped)));
+    let prepare_reqs: Vec<NodeId> = op
+        .outgoing_messages()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            Message::SequencePaxos(PaxosMessage {
+                to,
+                msg: PaxosMsg::PrepareReq(_),
+                ..
+            }) => Some(to),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(prepare_reqs, vec![2, 3]);
+
+    // The leader synchronizes the resumed server again
+    let n = op.get_promise();
+    op.handle_incoming(prepare(n, old_log_len));
+    let promised = op.outgoing_messages().into_iter().any(|msg| {
+        matches!(
+            msg,
+            Message::SequencePaxos(PaxosMessage {
+                msg: PaxosMsg::Promise(_),
+                ..
+            })
+        )
+    });
+    assert!(promised, "resumed server should promise the leader");
+}
+
+/// Verifies that a server stays halted if the storage still fails when it is resumed.
+#[test]
+#[serial]
+fn resume_failing_storage_test() {
+    let (mem_storage, storage_conf, mut op) = setup_follower();
+    storage_conf.lock().unwrap().schedule_failure_in(1);
+    op.handle_incoming(accept_decide(&mem_storage, 2, 0, 1..3));
+    assert!(op.storage_error().is_some(), "server should be halted");
+
+    storage_conf.lock().unwrap().schedule_failure_in(1);
+    assert!(op.resume().is_err(), "resume should fail");
+    assert!(
+        op.storage_error().is_some(),
+        "server should still be halted"
+    );
+    assert!(op.outgoing_messages().is_empty());
+
+    op.resume().expect("Failed to resume");
+    assert!(op.storage_error().is_none());
+}
+
+/// Verifies at compile time that a server, which stores its last storage error, can be moved to another thread,
+/// e.g., with `tokio::spawn`.
+#[test]
+fn omnipaxos_is_send_test() {
+    fn assert_send<T: Send>() {}
+    assert_send::<OmniPaxos<Value, MemoryStorage<Value>>>();
+}
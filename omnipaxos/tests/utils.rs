@@ -76,6 +76,7 @@ pub fn create_proposals(from: u64, to: u64) -> Vec<Value> {
 pub struct TestConfig {
     pub num_threads: usize,
     pub num_nodes: usize,
+    pub num_learners: usize,
     #[serde(rename(deserialize = "wait_timeout_ms"))]
     #[serde(deserialize_with = "deserialize_duration_millis")]
     pub wait_timeout: Duration,
@@ -91,6 +92,9 @@ pub struct TestConfig {
     pub trim_idx: u64,
     pub flexible_quorum: Option<(usize, usize)>,
     pub batch_size: usize,
+    pub leader_lease_tick_duration: Option<u64>,
+    pub pre_vote: bool,
+    pub check_quorum: bool,
     // #[cfg(feature = "unicache")]
     pub num_iterations: u64,
 }
@@ -114,10 +118,13 @@ impl TestConfig {
                 read_quorum_size,
                 write_quorum_size,
             });
+        let learners: Vec<u64> =
+            (self.num_nodes as u64 + 1..=(self.num_nodes + self.num_learners) as u64).collect();
         let cluster_config = ClusterConfig {
             configuration_id: 1,
             nodes: all_pids,
             flexible_quorum,
+            learners,
         };
         let server_config = ServerConfig {
             pid,
@@ -126,6 +133,9 @@ impl TestConfig {
             resend_message_tick_timeout: self.resend_message_timeout.as_millis() as u64
                 / self.election_timeout.as_millis() as u64,
             batch_size: self.batch_size,
+            leader_lease_tick_duration: self.leader_lease_tick_duration,
+            pre_vote: self.pre_vote,
+            check_quorum: self.check_quorum,
             ..Default::default()
         };
         OmniPaxosConfig {
@@ -140,6 +150,7 @@ impl Default for TestConfig {
         Self {
             num_threads: 3,
             num_nodes: 3,
+            num_learners: 0,
             wait_timeout: Duration::from_millis(5000),
             election_timeout: Duration::from_millis(200),
             resend_message_timeout: Duration::from_millis(500),
@@ -149,6 +160,9 @@ impl Default for TestConfig {
             trim_idx: 0,
             flexible_quorum: None,
             batch_size: 1,
+            leader_lease_tick_duration: None,
+            pre_vote: false,
+            check_quorum: false,
             num_iterations: 0,
         }
     }
@@ -396,6 +410,28 @@ where
         }
     }
 
+    fn set_membership(&mut self, m: Option<omnipaxos::storage::Membership>) -> StorageResult<()> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.set_membership(m),
+            StorageType::Memory(mem_s) => mem_s.set_membership(m),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().set_membership(m)
+            }
+        }
+    }
+
+    fn get_membership(&self) -> StorageResult<Option<omnipaxos::storage::Membership>> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.get_membership(),
+            StorageType::Memory(mem_s) => mem_s.get_membership(),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().get_membership()
+            }
+        }
+    }
+
     fn trim(&mut self, idx: u64) -> StorageResult<()> {
         match self {
             StorageType::Persistent(persist_s) => persist_s.trim(idx),
@@ -476,7 +512,7 @@ impl TestSystem {
         let mut nodes = HashMap::new();
         let mut omni_refs: HashMap<u64, ActorRef<Message<Value>>> = HashMap::new();
 
-        for pid in 1..=test_config.num_nodes as u64 {
+        for pid in 1..=(test_config.num_nodes + test_config.num_learners) as u64 {
             let op_config = test_config.into_omnipaxos_config(pid);
             let storage: StorageType<Value> =
                 StorageType::with(test_config.storage_type, &format!("{temp_dir_path}{pid}"));
@@ -978,6 +1014,126 @@ pub fn create_temp_dir() -> String {
     dir_path.to_string_lossy().to_string()
 }
 
+/// Helpers for tests that drive the servers directly, without a `TestSystem`, so that their messages can be inspected
+/// or dropped. The servers are in one configuration with the pids `1..=NUM_NODES`.
+pub mod direct {
+    use omnipaxos::{
+        messages::Message,
+        storage::{Entry, Storage},
+        util::NodeId,
+        ClusterConfig, OmniPaxos, OmniPaxosConfig, ServerConfig,
+    };
+    use omnipaxos_storage::memory_storage::MemoryStorage;
+    use std::collections::HashMap;
+
+    pub const NUM_NODES: u64 = 3;
+    pub const MAX_TICKS: usize = 500;
+
+    pub type Servers<T, B = MemoryStorage<T>> = HashMap<NodeId, OmniPaxos<T, B>>;
+
+    /// Returns the config of the server `pid` with short timeouts and the remaining fields of `server_config`.
+    pub fn server_config(pid: NodeId, server_config: ServerConfig) -> OmniPaxosConfig {
+        OmniPaxosConfig {
+            cluster_config: ClusterConfig {
+                configuration_id: 1,
+                nodes: (1..=NUM_NODES).collect(),
+                ..Default::default()
+            },
+            server_config: ServerConfig {
+                pid,
+                election_tick_timeout: 5,
+                resend_message_tick_timeout: 5,
+                ..server_config
+            },
+        }
+    }
+
+    /// Creates the server `pid` with an empty `MemoryStorage`.
+    pub fn create_server<T: Entry>(
+        pid: NodeId,
+        server_config: ServerConfig,
+    ) -> OmniPaxos<T, MemoryStorage<T>> {
+        self::server_config(pid, server_config)
+            .build(MemoryStorage::default())
+            .unwrap()
+    }
+
+    /// Creates all servers with an empty `MemoryStorage`.
+    pub fn create_servers<T: Entry>(server_config: ServerConfig) -> Servers<T> {
+        (1..=NUM_NODES)
+            .map(|pid| (pid, create_server(pid, server_config.clone())))
+            .collect()
+    }
+
+    /// Delivers the outgoing messages of all servers once and returns them. The messages for which `drop` returns
+    /// `true` are not delivered.
+    pub fn deliver_round<T: Entry, B: Storage<T>>(
+        servers: &mut Servers<T, B>,
+        drop: &mut impl FnMut(&Message<T>) -> bool,
+    ) -> Vec<Message<T>> {
+        let msgs: Vec<Message<T>> = servers
+            .values_mut()
+            .flat_map(|s| s.outgoing_messages())
+            .collect();
+        for msg in &msgs {
+            if !drop(msg) {
+                servers
+                    .get_mut(&msg.get_receiver())
+                    .unwrap()
+                    .handle_incoming(msg.clone());
+            }
+        }
+        msgs
+    }
+
+    /// Delivers messages until there are none left and returns them, including the dropped ones. The messages for
+    /// which `drop` returns `true` are not delivered.
+    pub fn deliver<T: Entry, B: Storage<T>>(
+        servers: &mut Servers<T, B>,
+        mut drop: impl FnMut(&Message<T>) -> bool,
+    ) -> Vec<Message<T>> {
+        let mut msgs = vec![];
+        loop {
+            let round = deliver_round(servers, &mut drop);
+            if round.is_empty() {
+                return msgs;
+            }
+            msgs.extend(round);
+        }
+    }
+
+    /// Ticks all servers once and delivers their messages.
+    pub fn tick<T: Entry, B: Storage<T>>(servers: &mut Servers<T, B>) {
+        servers.values_mut().for_each(|s| s.tick());
+        deliver(servers, |_| false);
+    }
+
+    /// Returns the leader if all servers agree on one.
+    pub fn agreed_leader<T: Entry, B: Storage<T>>(servers: &Servers<T, B>) -> Option<NodeId> {
+        let leaders: Vec<_> = servers.values().map(|s| s.get_current_leader()).collect();
+        match leaders.first() {
+            Some(Some(leader)) if leaders.iter().all(|l| *l == Some(*leader)) => Some(*leader),
+            _ => None,
+        }
+    }
+
+    /// Ticks the servers until all of them agree on a leader and returns it.
+    pub fn wait_for_leader<T: Entry, B: Storage<T>>(servers: &mut Servers<T, B>) -> NodeId {
+        for _ in 0..MAX_TICKS {
+            tick(servers);
+            if let Some(leader) = agreed_leader(servers) {
+                return leader;
+            }
+        }
+        panic!("No leader has been elected in {} ticks", MAX_TICKS);
+    }
+
+    /// Returns a server other than `leader`.
+    pub fn follower_of(leader: NodeId) -> NodeId {
+        (1..=NUM_NODES).find(|pid| *pid != leader).unwrap()
+    }
+}
+
 pub mod verification {
     use super::{Value, ValueSnapshot};
     use omnipaxos::{
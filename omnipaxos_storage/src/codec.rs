@@ -0,0 +1,75 @@
+use omnipaxos::storage::StorageResult;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The encoding of the entries and the replica state that a storage writes to disk. The name of the codec is stored
+/// when a storage is created, and the storage can only be opened again with a codec of the same name.
+///
+/// A custom codec, e.g., a zero-copy encoding of the entry type, can be used by implementing this trait.
+pub trait Codec {
+    /// The name of the encoding. Must be changed if the encoding changes in an incompatible way.
+    fn name(&self) -> &str;
+
+    /// Encodes `value`.
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> StorageResult<Vec<u8>>;
+
+    /// Decodes a value from `bytes`.
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> StorageResult<V>;
+}
+
+/// Encodes with [bincode](https://crates.io/crates/bincode) 1.x. The default codec.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn name(&self) -> &str {
+        "bincode"
+    }
+
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> StorageResult<Vec<u8>> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> StorageResult<V> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// Encodes with [postcard](https://crates.io/crates/postcard), a compact encoding.
+#[cfg(feature = "postcard")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PostcardCodec;
+
+#[cfg(feature = "postcard")]
+impl Codec for PostcardCodec {
+    fn name(&self) -> &str {
+        "postcard"
+    }
+
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> StorageResult<Vec<u8>> {
+        Ok(postcard::to_stdvec(value)?)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> StorageResult<V> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+/// Encodes as JSON, which makes the stored values readable for debugging.
+#[cfg(feature = "json")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl Codec for JsonCodec {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> StorageResult<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> StorageResult<V> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
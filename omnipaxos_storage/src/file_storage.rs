@@ -0,0 +1,650 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Entry, Membership, StopSign, Storage, StorageOp, StorageResult},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT: &str = "/default_file_storage/";
+const LOG_DIR: &str = "log";
+const STATE_FILE: &str = "state";
+const SNAPSHOT_FILE: &str = "snapshot";
+const SEGMENT_EXTENSION: &str = "log";
+const TMP_EXTENSION: &str = "tmp";
+const DEFAULT_SEGMENT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+/// The length and the checksum of a record.
+const HEADER_LEN: usize = 8;
+
+/// Configuration for `FileStorage`.
+/// # Fields
+/// * `path`: Path to the directory of the log and the replica state
+/// * `segment_max_bytes` : The size in bytes after which a new segment of the log is started
+pub struct FileStorageConfig {
+    path: Option<String>,
+    segment_max_bytes: u64,
+}
+
+impl FileStorageConfig {
+    /// Returns the current path to the storage directory.
+    pub fn get_path(&self) -> Option<&String> {
+        self.path.as_ref()
+    }
+
+    /// Sets the path to the storage directory.
+    pub fn set_path(&mut self, path: String) {
+        self.path = Some(path);
+    }
+
+    /// Returns the size in bytes after which a new segment of the log is started.
+    pub fn get_segment_max_bytes(&self) -> u64 {
+        self.segment_max_bytes
+    }
+
+    /// Sets the size in bytes after which a new segment of the log is started.
+    pub fn set_segment_max_bytes(&mut self, segment_max_bytes: u64) {
+        self.segment_max_bytes = segment_max_bytes;
+    }
+
+    /// Creates a configuration for `FileStorage` with the given path
+    pub fn with(path: String) -> Self {
+        Self {
+            path: Some(path),
+            segment_max_bytes: DEFAULT_SEGMENT_MAX_BYTES,
+        }
+    }
+}
+
+impl Default for FileStorageConfig {
+    fn default() -> Self {
+        Self::with(DEFAULT.to_string())
+    }
+}
+
+/// The replica state, which is rewritten as a whole on every change.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct State {
+    promise: Ballot,
+    accepted_round: Ballot,
+    decided_idx: u64,
+    compacted_idx: u64,
+    stopsign: Option<StopSign>,
+    membership: Option<Membership>,
+    /// The index of the first entry in the log files, i.e., the number of trimmed entries
+    log_start: u64,
+    /// The index after the last entry that has been written completely
+    log_end: u64,
+    /// A write set that truncates the log and must be completed after a crash
+    pending: Option<PendingWrite>,
+}
+
+/// The entries of a write set that truncates the log.
+#[derive(Clone, Serialize, Deserialize)]
+struct PendingWrite {
+    /// The index that the log is truncated to.
+    log_len: u64,
+    /// The serialized entries that are appended after `log_len`.
+    entries: Vec<Vec<u8>>,
+}
+
+/// A serialized write set.
+struct WriteSet {
+    /// The index that the log is truncated to before the entries are appended.
+    log_len: u64,
+    /// The serialized entries that are appended.
+    entries: Vec<Vec<u8>>,
+    promise: Option<Ballot>,
+    accepted_round: Option<Ballot>,
+    decided_idx: Option<u64>,
+}
+
+/// A file of the log that contains the entries from index `base` onwards.
+struct Segment {
+    /// The index of the first entry in the segment
+    base: u64,
+    /// The path of the segment file
+    path: PathBuf,
+    /// The segment file, opened for appending
+    file: File,
+    /// The position of every entry in the file
+    positions: Vec<u64>,
+    /// The size of the file in bytes
+    size: u64,
+}
+
+impl Segment {
+    fn path(log_dir: &Path, base: u64) -> PathBuf {
+        log_dir.join(format!("{base:020}.{SEGMENT_EXTENSION}"))
+    }
+
+    fn open_file(path: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+    }
+
+    /// Creates an empty segment.
+    fn create(log_dir: &Path, base: u64) -> io::Result<Self> {
+        let path = Self::path(log_dir, base);
+        let file = Self::open_file(&path)?;
+        file.set_len(0)?;
+        sync_dir(log_dir)?;
+        Ok(Self {
+            base,
+            path,
+            file,
+            positions: vec![],
+            size: 0,
+        })
+    }
+
+    /// Opens an existing segment and builds its index. A record that was not written completely is removed if
+    /// `is_last` is set.
+    fn open(log_dir: &Path, base: u64, is_last: bool) -> StorageResult<Self> {
+        let path = Self::path(log_dir, base);
+        let bytes = fs::read(&path)?;
+        let (positions, valid_len) = read_records(&bytes);
+        let file = Self::open_file(&path)?;
+        if valid_len < bytes.len() {
+            if !is_last {
+                return Err(corrupted(format!(
+                    "segment {} is corrupted",
+                    path.display()
+                )));
+            }
+            file.set_len(valid_len as u64)?;
+            file.sync_all()?;
+        }
+        Ok(Self {
+            base,
+            path,
+            file,
+            positions: positions.into_iter().map(|pos| pos as u64).collect(),
+            size: valid_len as u64,
+        })
+    }
+
+    /// The index after the last entry in the segment.
+    fn end(&self) -> u64 {
+        self.base + self.positions.len() as u64
+    }
+
+    /// The position of the entry at `idx` in the file, or the size of the file if `idx` is the end of the segment.
+    fn position(&self, idx: u64) -> u64 {
+        let i = (idx - self.base) as usize;
+        self.positions.get(i).copied().unwrap_or(self.size)
+    }
+
+    /// Reads the serialized entries in the index interval of [from, to) and verifies their checksums.
+    fn read(&self, from: u64, to: u64) -> StorageResult<Vec<Vec<u8>>> {
+        let start = self.position(from);
+        let mut bytes = vec![0; (self.position(to) - start) as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut bytes)?;
+        let (positions, valid_len) = read_records(&bytes);
+        if valid_len < bytes.len() || positions.len() as u64 != to - from {
+            return Err(corrupted(format!(
+                "segment {} is corrupted",
+                self.path.display()
+            )));
+        }
+        Ok(positions
+            .into_iter()
+            .map(|pos| payload(&bytes, pos).to_vec())
+            .collect())
+    }
+}
+
+/// Returns the positions of the records in `bytes` and the number of bytes that they span. Stops at the first record
+/// that is incomplete or does not match its checksum.
+fn read_records(bytes: &[u8]) -> (Vec<usize>, usize) {
+    let mut positions = vec![];
+    let mut pos = 0;
+    while pos + HEADER_LEN <= bytes.len() {
+        let len = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(bytes[pos + 4..pos + HEADER_LEN].try_into().unwrap());
+        let end = pos + HEADER_LEN + len;
+        if end > bytes.len() || crc32fast::hash(&bytes[pos + HEADER_LEN..end]) != checksum {
+            break;
+        }
+        positions.push(pos);
+        pos = end;
+    }
+    (positions, pos)
+}
+
+/// Returns the payload of the record at `pos`.
+fn payload(bytes: &[u8], pos: usize) -> &[u8] {
+    let len = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+    &bytes[pos + HEADER_LEN..pos + HEADER_LEN + len]
+}
+
+/// Appends `payload` as a record with its length and checksum to `buffer`.
+fn write_record(buffer: &mut Vec<u8>, payload: &[u8]) {
+    buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    buffer.extend_from_slice(payload);
+}
+
+/// Replaces the file at `path` with a single record of `payload`. The file is either replaced completely or not at
+/// all.
+fn write_file(path: &Path, payload: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension(TMP_EXTENSION);
+    let mut buffer = Vec::with_capacity(HEADER_LEN + payload.len());
+    write_record(&mut buffer, payload);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&buffer)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    sync_dir(path.parent().expect("File has no directory"))
+}
+
+/// Reads the record of a file written with `write_file()`.
+fn read_file(path: &Path) -> StorageResult<Option<Vec<u8>>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let (positions, valid_len) = read_records(&bytes);
+    if positions.len() != 1 || valid_len != bytes.len() {
+        return Err(corrupted(format!("{} is corrupted", path.display())));
+    }
+    Ok(Some(payload(&bytes, 0).to_vec()))
+}
+
+/// Makes the creation, removal and renaming of files in the directory durable.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    // Directories cannot be opened as files on every platform
+    #[cfg(unix)]
+    File::open(path)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn corrupted(msg: String) -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// A persistent storage implementation without any dependencies on other storage engines. The log is stored in
+/// segmented append-only files where every entry is prefixed by its length and a CRC32 checksum, and the index of the
+/// entries is rebuilt from the segments when the storage is opened. The replica state and the snapshot are stored
+/// in their own files, which are replaced atomically on every change.
+///
+/// The replica state contains the end of the log, so the entries and the state of a write set become durable
+/// together when the state is replaced. Entries after the end of the log are removed when the storage is opened.
+/// A write set that truncates the log is stored in the state before the log is changed and completed when the storage
+/// is opened after a crash.
+pub struct FileStorage<T>
+where
+    T: Entry,
+{
+    /// The directory of the segments of the log
+    log_dir: PathBuf,
+    /// The path of the file with the replica state
+    state_path: PathBuf,
+    /// The path of the file with the snapshot
+    snapshot_path: PathBuf,
+    /// The segments of the log, ordered by the index of their first entry
+    segments: Vec<Segment>,
+    /// The replica state
+    state: State,
+    /// The size in bytes after which a new segment of the log is started
+    segment_max_bytes: u64,
+    /// A placeholder for the T: Entry
+    t: PhantomData<T>,
+}
+
+impl<T: Entry> FileStorage<T> {
+    /// Creates or opens an existing storage. Writes that were interrupted by a crash are completed or undone.
+    pub fn open(storage_config: FileStorageConfig) -> Self {
+        let path = PathBuf::from(storage_config.path.expect("No path found in config"));
+        let log_dir = path.join(LOG_DIR);
+        fs::create_dir_all(&log_dir).expect("Failed to create log directory");
+        let state_path = path.join(STATE_FILE);
+        let state = match read_file(&state_path).expect("Failed to read state") {
+            Some(bytes) => bincode::deserialize(&bytes).expect("Failed to read state"),
+            None => State::default(),
+        };
+
+        let mut storage = Self {
+            log_dir,
+            state_path,
+            snapshot_path: path.join(SNAPSHOT_FILE),
+            segments: vec![],
+            state,
+            segment_max_bytes: storage_config.segment_max_bytes,
+            t: PhantomData,
+        };
+        storage.recover().expect("Failed to recover log");
+        storage
+    }
+
+    /// Creates a new storage instance, panics if a storage already exists in the given path
+    pub fn new(storage_config: FileStorageConfig) -> Self {
+        let path = storage_config
+            .path
+            .as_ref()
+            .expect("No path found in config");
+
+        std::fs::metadata(Path::new(path).join(STATE_FILE)).expect_err(&format!(
+            "Cannot create new instance, storage already exists in {}",
+            path
+        ));
+
+        Self::open(storage_config)
+    }
+
+    /// Opens the segments of the log and completes or undoes the write set that was interrupted by a crash, if any.
+    fn recover(&mut self) -> StorageResult<()> {
+        let mut bases: Vec<u64> = fs::read_dir(&self.log_dir)?
+            .filter_map(|file| {
+                let path = file.ok()?.path();
+                if path.extension()? != SEGMENT_EXTENSION {
+                    return None;
+                }
+                path.file_stem()?.to_str()?.parse().ok()
+            })
+            .collect();
+        bases.sort_unstable();
+        for (i, base) in bases.iter().enumerate() {
+            let segment = Segment::open(&self.log_dir, *base, i + 1 == bases.len())?;
+            self.segments.push(segment);
+        }
+        if self.segments.is_empty() {
+            self.segments
+                .push(Segment::create(&self.log_dir, self.state.log_start)?);
+        }
+
+        if let Some(pending) = self.state.pending.clone() {
+            self.write_entries(pending.log_len, pending.entries)?;
+            self.update_state(|state| state.pending = None)?;
+        } else if self.log_end() > self.state.log_end {
+            self.truncate_log(self.state.log_end)?;
+        }
+        if self.log_end() != self.state.log_end {
+            return Err(corrupted(format!(
+                "the log ends at {} instead of {}",
+                self.log_end(),
+                self.state.log_end
+            )));
+        }
+        // The segments might not have been removed if the process crashed while trimming
+        self.remove_trimmed_segments()
+    }
+
+    /// The index after the last entry in the segments.
+    fn log_end(&self) -> u64 {
+        self.segments.last().expect("The log has no segment").end()
+    }
+
+    fn write_state(&self, state: &State) -> StorageResult<()> {
+        write_file(&self.state_path, &bincode::serialize(state)?)?;
+        Ok(())
+    }
+
+    /// Changes the replica state and makes it durable. The state is unchanged if the write fails.
+    fn update_state(&mut self, update: impl FnOnce(&mut State)) -> StorageResult<()> {
+        let mut state = self.state.clone();
+        update(&mut state);
+        self.write_state(&state)?;
+        self.state = state;
+        Ok(())
+    }
+
+    /// Serializes the writes of a write set. The truncations and appends are combined into a single truncation
+    /// followed by an append.
+    fn serialize_write_set(&self, ops: Vec<StorageOp<T>>) -> StorageResult<WriteSet>
+    where
+        T: Serialize,
+    {
+        let mut write = WriteSet {
+            log_len: self.state.log_end,
+            entries: vec![],
+            promise: None,
+            accepted_round: None,
+            decided_idx: None,
+        };
+        for op in ops {
+            match op {
+                StorageOp::AppendEntries(entries) => {
+                    for entry in entries {
+                        write.entries.push(bincode::serialize(&entry)?);
+                    }
+                }
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    let from = self.state.log_start + from_idx;
+                    if from <= write.log_len {
+                        write.log_len = from;
+                        write.entries.clear();
+                    } else {
+                        let appended = (from - write.log_len) as usize;
+                        write.entries.truncate(appended);
+                    }
+                    for entry in entries {
+                        write.entries.push(bincode::serialize(&entry)?);
+                    }
+                }
+                StorageOp::SetPromise(n_prom) => write.promise = Some(n_prom),
+                StorageOp::SetAcceptedRound(na) => write.accepted_round = Some(na),
+                StorageOp::SetDecidedIdx(ld) => write.decided_idx = Some(ld),
+            }
+        }
+        Ok(write)
+    }
+
+    /// Truncates the log to `log_len` and appends the entries durably.
+    fn write_entries(&mut self, log_len: u64, entries: Vec<Vec<u8>>) -> StorageResult<()> {
+        if log_len < self.log_end() {
+            self.truncate_log(log_len)?;
+        }
+        let mut buffer = vec![];
+        for entry in entries {
+            let segment = self.segments.last().expect("The log has no segment");
+            if !segment.positions.is_empty()
+                && segment.size + buffer.len() as u64 >= self.segment_max_bytes
+            {
+                self.write_to_last_segment(&mut buffer)?;
+                let base = self.log_end();
+                self.segments.push(Segment::create(&self.log_dir, base)?);
+            }
+            let segment = self.segments.last_mut().expect("The log has no segment");
+            segment.positions.push(segment.size + buffer.len() as u64);
+            write_record(&mut buffer, &entry);
+        }
+        self.write_to_last_segment(&mut buffer)
+    }
+
+    fn write_to_last_segment(&mut self, buffer: &mut Vec<u8>) -> StorageResult<()> {
+        if !buffer.is_empty() {
+            let segment = self.segments.last_mut().expect("The log has no segment");
+            (&segment.file).write_all(buffer)?;
+            segment.file.sync_data()?;
+            segment.size += buffer.len() as u64;
+            buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Removes the entries from `log_len` onwards. Only the segment that contains the new end of the log is rewritten,
+    /// the segments after it are deleted.
+    fn truncate_log(&mut self, log_len: u64) -> StorageResult<()> {
+        while self.segments.len() > 1 && self.segments.last().unwrap().base >= log_len {
+            let segment = self.segments.pop().unwrap();
+            fs::remove_file(&segment.path)?;
+        }
+        sync_dir(&self.log_dir)?;
+        let segment = self.segments.last_mut().expect("The log has no segment");
+        if log_len < segment.end() {
+            let size = segment.position(log_len);
+            segment.file.set_len(size)?;
+            segment.file.sync_all()?;
+            segment
+                .positions
+                .truncate((log_len - segment.base) as usize);
+            segment.size = size;
+        }
+        Ok(())
+    }
+
+    /// Deletes the segments that only contain trimmed entries.
+    fn remove_trimmed_segments(&mut self) -> StorageResult<()> {
+        while self.segments.len() > 1 && self.segments[1].base <= self.state.log_start {
+            let segment = self.segments.remove(0);
+            fs::remove_file(&segment.path)?;
+        }
+        sync_dir(&self.log_dir)?;
+        Ok(())
+    }
+}
+
+impl<T> Storage<T> for FileStorage<T>
+where
+    T: Entry + Serialize + for<'a> Deserialize<'a>,
+    T::Snapshot: Serialize + for<'a> Deserialize<'a>,
+{
+    fn append_entry(&mut self, entry: T) -> StorageResult<u64> {
+        self.append_entries(vec![entry])
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<u64> {
+        self.write_atomically(vec![StorageOp::AppendEntries(entries)])
+    }
+
+    fn append_on_prefix(&mut self, from_idx: u64, entries: Vec<T>) -> StorageResult<u64> {
+        self.write_atomically(vec![StorageOp::AppendOnPrefix(from_idx, entries)])
+    }
+
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.update_state(|state| state.promise = n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: u64) -> StorageResult<()> {
+        self.update_state(|state| state.decided_idx = ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<u64> {
+        Ok(self.state.decided_idx)
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.update_state(|state| state.accepted_round = na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        Ok(Some(self.state.accepted_round))
+    }
+
+    fn get_entries(&self, from: u64, to: u64) -> StorageResult<Vec<T>> {
+        let (from, to) = (self.state.log_start + from, self.state.log_start + to);
+        if to > self.state.log_end || from >= to {
+            return Ok(vec![]);
+        }
+        let mut entries = Vec::with_capacity((to - from) as usize);
+        for segment in &self.segments {
+            if segment.end() <= from || segment.base >= to {
+                continue;
+            }
+            for bytes in segment.read(from.max(segment.base), to.min(segment.end()))? {
+                entries.push(bincode::deserialize(&bytes)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn get_log_len(&self) -> StorageResult<u64> {
+        Ok(self.state.log_end - self.state.log_start)
+    }
+
+    fn get_suffix(&self, from: u64) -> StorageResult<Vec<T>> {
+        self.get_entries(from, self.get_log_len()?)
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(Some(self.state.promise))
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.update_state(|state| state.stopsign = s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        Ok(self.state.stopsign.clone())
+    }
+
+    fn set_membership(&mut self, m: Option<Membership>) -> StorageResult<()> {
+        self.update_state(|state| state.membership = m)
+    }
+
+    fn get_membership(&self) -> StorageResult<Option<Membership>> {
+        Ok(self.state.membership.clone())
+    }
+
+    fn trim(&mut self, trimmed_idx: u64) -> StorageResult<()> {
+        let log_start = self.state.log_start + trimmed_idx.min(self.get_log_len()?);
+        if log_start == self.state.log_start {
+            return Ok(());
+        }
+        // The new start of the log is durable before any segment is deleted
+        self.update_state(|state| state.log_start = log_start)?;
+        self.remove_trimmed_segments()
+    }
+
+    fn set_compacted_idx(&mut self, idx: u64) -> StorageResult<()> {
+        self.update_state(|state| state.compacted_idx = idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<u64> {
+        Ok(self.state.compacted_idx)
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        write_file(&self.snapshot_path, &bincode::serialize(&snapshot)?)?;
+        Ok(())
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        match read_file(&self.snapshot_path)? {
+            Some(bytes) => Ok(bincode::deserialize(&bytes)?),
+            None => Ok(None),
+        }
+    }
+
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        let write = self.serialize_write_set(ops)?;
+        let mut state = self.state.clone();
+        if let Some(n_prom) = write.promise {
+            state.promise = n_prom;
+        }
+        if let Some(na) = write.accepted_round {
+            state.accepted_round = na;
+        }
+        if let Some(ld) = write.decided_idx {
+            state.decided_idx = ld;
+        }
+        state.log_end = write.log_len + write.entries.len() as u64;
+        if write.log_len < self.state.log_end {
+            // A truncated log cannot be restored, so the write set is completed after a crash instead
+            state.pending = Some(PendingWrite {
+                log_len: write.log_len,
+                entries: write.entries.clone(),
+            });
+            self.write_state(&state)?;
+            state.pending = None;
+        }
+        self.write_entries(write.log_len, write.entries)?;
+        // The write set is durable once the state with the new end of the log is written
+        self.write_state(&state)?;
+        self.state = state;
+        self.get_log_len()
+    }
+}
@@ -5,5 +5,17 @@
 /// an in-memory storage implementation with fast read and writes
 pub mod memory_storage;
 
+/// the encodings of the entries and the replica state on disk
+pub mod codec;
+
 /// an on-disk storage implementation with persistence for the replica state and the log.
+#[cfg(feature = "persistent_storage")]
 pub mod persistent_storage;
+
+/// an on-disk storage implementation that keeps both the replica state and the log in a single sled database.
+#[cfg(feature = "sled_storage")]
+pub mod sled_storage;
+
+/// an on-disk storage implementation that keeps the log in segmented append-only files.
+#[cfg(feature = "file_storage")]
+pub mod file_storage;
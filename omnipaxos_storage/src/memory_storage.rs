@@ -1,6 +1,6 @@
 use omnipaxos::{
     ballot_leader_election::Ballot,
-    storage::{Entry, StopSign, Storage, StorageResult},
+    storage::{Entry, Membership, StopSign, Storage, StorageResult},
 };
 /// An in-memory storage implementation for SequencePaxos.
 #[derive(Clone)]
@@ -22,6 +22,8 @@ where
     snapshot: Option<T::Snapshot>,
     /// Stored StopSign
     stopsign: Option<StopSign>,
+    /// Stored cluster configuration of in-place membership changes
+    membership: Option<Membership>,
 }
 
 impl<T> Storage<T> for MemoryStorage<T>
@@ -99,6 +101,15 @@ where
         Ok(self.stopsign.clone())
     }
 
+    fn set_membership(&mut self, m: Option<Membership>) -> StorageResult<()> {
+        self.membership = m;
+        Ok(())
+    }
+
+    fn get_membership(&self) -> StorageResult<Option<Membership>> {
+        Ok(self.membership.clone())
+    }
+
     fn trim(&mut self, trimmed_idx: u64) -> StorageResult<()> {
         self.log
             .drain(0..(trimmed_idx as usize).min(self.log.len()));
@@ -134,6 +145,7 @@ impl<T: Entry> Default for MemoryStorage<T> {
             trimmed_idx: 0,
             snapshot: None,
             stopsign: None,
+            membership: None,
         }
     }
 }
@@ -1,13 +1,14 @@
+use crate::codec::{BincodeCodec, Codec};
 use commitlog::{
     message::{MessageBuf, MessageSet},
-    CommitLog, LogOptions, ReadLimit,
+    CommitLog, LogOptions, ReadError, ReadLimit,
 };
 use omnipaxos::{
     ballot_leader_election::Ballot,
-    storage::{Entry, StopSign, Storage, StorageResult},
+    storage::{Entry, Membership, StopSign, Storage, StorageOp, StorageResult},
 };
-use serde::{Deserialize, Serialize};
-use sled::{Config, Db};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sled::{Batch, Config, Db};
 use std::{iter::FromIterator, marker::PhantomData};
 use zerocopy::{AsBytes, FromBytes};
 
@@ -20,19 +21,42 @@ const DECIDE: &[u8] = b"DECIDE";
 const TRIM: &[u8] = b"TRIM";
 const STOPSIGN: &[u8] = b"STOPSIGN";
 const SNAPSHOT: &[u8] = b"SNAPSHOT";
+const MEMBERSHIP: &[u8] = b"MEMBERSHIP";
+const PENDING_WRITE: &[u8] = b"PENDING_WRITE";
+const LOG_START: &[u8] = b"LOG_START";
+const FORMAT: &[u8] = b"FORMAT";
+/// The version of the layout of the storage on disk, which is written to the format header.
+const FORMAT_VERSION: u32 = 2;
+/// The first format version, in which the entries have no checksums.
+const UNCHECKED_FORMAT_VERSION: u32 = 1;
+/// The length of the checksum that precedes every entry in the commitlog.
+const CHECKSUM_LEN: usize = 4;
+/// The number of entries that are read at once by `verify()`.
+const VERIFY_BATCH_LEN: u64 = 1024;
+/// The number of bytes that a read of the commitlog is limited to at first.
+const READ_BYTES: usize = 8 * 1024;
+/// The upper bound of the size of a message in the commitlog, whose sizes and positions are 32-bit.
+const MAX_MESSAGE_BYTES: usize = u32::MAX as usize;
 
 // Configuration for `PersistentStorage`.
 /// # Fields
 /// * `path`: Path to the Commitlog and state storage
 /// * `commitlog_options`: Options for the Commitlog
 /// * `sled_options` : Options for the sled store, enabled by default
-pub struct PersistentStorageConfig {
+/// * `codec` : The encoding of the entries and the replica state, bincode by default
+/// * `truncate_corrupted_tail` : Whether `open()` removes the log from the first corrupted entry onwards
+/// * `crash_after` : The number of disk writes after which the process is aborted, to test crash recovery
+pub struct PersistentStorageConfig<C = BincodeCodec> {
     path: Option<String>,
     commitlog_options: LogOptions,
     sled_options: Config,
+    codec: C,
+    truncate_corrupted_tail: bool,
+    #[cfg(feature = "crash_injection")]
+    crash_after: Option<u64>,
 }
 
-impl PersistentStorageConfig {
+impl<C: Codec> PersistentStorageConfig<C> {
     /// Returns the current path to the persistent storage.
     pub fn get_path(&self) -> Option<&String> {
         self.path.as_ref()
@@ -63,32 +87,80 @@ impl PersistentStorageConfig {
         self.sled_options = opts;
     }
 
-    /// Creates a configuration for `PersistentStorage` with the given path and options for Commitlog and sled
-    pub fn with(path: String, commitlog_options: LogOptions, sled_options: Config) -> Self {
+    /// Aborts the process after the given number of disk writes. Only meant for testing that `PersistentStorage::open()`
+    /// recovers from crashes at any point.
+    #[cfg(feature = "crash_injection")]
+    pub fn set_crash_after(&mut self, disk_writes: u64) {
+        self.crash_after = Some(disk_writes);
+    }
+
+    /// Returns whether the log is truncated at the first corrupted entry when the storage is opened.
+    pub fn get_truncate_corrupted_tail(&self) -> bool {
+        self.truncate_corrupted_tail
+    }
+
+    /// Sets whether the log is truncated at the first corrupted entry when the storage is opened. The decided index
+    /// is lowered to the end of the remaining log, so that the removed entries are synchronized from the leader when
+    /// the server recovers. The accepted round is reset, i.e., the server gives up the acceptances of its earlier
+    /// rounds and only its decided entries are considered valid. Like losing the disk of a server, this is only safe
+    /// as long as a majority of the servers has not lost accepted entries at the same time.
+    pub fn set_truncate_corrupted_tail(&mut self, truncate: bool) {
+        self.truncate_corrupted_tail = truncate;
+    }
+
+    /// Returns the codec of the entries and the replica state.
+    pub fn get_codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Creates a configuration for `PersistentStorage` with the given path, options for Commitlog and sled, and codec
+    pub fn with_codec(
+        path: String,
+        commitlog_options: LogOptions,
+        sled_options: Config,
+        codec: C,
+    ) -> Self {
         Self {
             path: Some(path),
             commitlog_options,
             sled_options,
+            codec,
+            truncate_corrupted_tail: false,
+            #[cfg(feature = "crash_injection")]
+            crash_after: None,
         }
     }
 }
 
+impl PersistentStorageConfig {
+    /// Creates a configuration for `PersistentStorage` with the given path and options for Commitlog and sled
+    pub fn with(path: String, commitlog_options: LogOptions, sled_options: Config) -> Self {
+        Self::with_codec(path, commitlog_options, sled_options, BincodeCodec)
+    }
+}
+
 impl Default for PersistentStorageConfig {
     fn default() -> Self {
         let commitlog_options = LogOptions::new(format!("{DEFAULT}{COMMITLOG}"));
-
-        Self {
-            path: Some(DEFAULT.to_string()),
-            commitlog_options,
-            sled_options: Config::new(),
-        }
+        Self::with(DEFAULT.to_string(), commitlog_options, Config::new())
     }
 }
 
 /// A persistent storage implementation, lets sequence paxos write the log
 /// and current state to disk. Log entries are serialized and de-serialized
 /// into slice of bytes when read or written from the log.
-pub struct PersistentStorage<T>
+///
+/// The entries and the replica state are encoded with the codec `C`. The version of the on-disk format and the name of
+/// the codec are written when the storage is created, and `open()` panics if they do not match. Every entry is
+/// preceded by a CRC32 checksum of its encoding, which is verified when the entry is read.
+///
+/// Trimming the log does not rewrite the commitlog. Instead, the commitlog offset of the first entry in the log is
+/// stored in sled and the segments of the commitlog that only contain trimmed entries are deleted.
+///
+/// Writes that change both the commitlog and sled are recorded in sled before the commitlog is changed. If the
+/// process crashes during such a write, `open()` completes the write if the commitlog was truncated and undoes it
+/// otherwise, so that the accepted round always matches the log.
+pub struct PersistentStorage<T, C = BincodeCodec>
 where
     T: Entry,
 {
@@ -96,35 +168,109 @@ where
     commitlog: CommitLog,
     /// The path to the directory containing a commitlog
     log_path: String,
+    /// The configured options of the commitlog, which is created again with them when the whole log is truncated
+    log_options: LogOptions,
+    /// The commitlog offset of the first entry in the log, i.e., the number of trimmed entries
+    log_start: u64,
     /// Local sled key-value store, enabled by default
     sled: Db,
+    /// The encoding of the entries and the replica state
+    codec: C,
+    /// Whether the entries are preceded by checksums, which is the case unless the storage has the first format
+    checksums: bool,
+    /// Whether there are write sets from `write_atomically_async()` that have not been flushed yet
+    unflushed: bool,
+    /// The metadata of the unflushed write sets, which is only written once their entries are flushed
+    unflushed_metadata: Vec<(&'static [u8], Vec<u8>)>,
+    /// The number of disk writes after which the process is aborted
+    #[cfg(feature = "crash_injection")]
+    crash_after: Option<u64>,
+    /// The number of disk writes so far
+    #[cfg(feature = "crash_injection")]
+    disk_writes: u64,
     /// A placeholder for the T: Entry
     t: PhantomData<T>,
 }
 
-impl<T: Entry> PersistentStorage<T> {
-    /// Creates or opens an existing storage
-    pub fn open(storage_config: PersistentStorageConfig) -> Self {
-        let path = storage_config.path.expect("No path found in config");
+/// A write to both the commitlog and sled that has been started but might not have been completed.
+#[derive(Serialize, Deserialize)]
+struct PendingWrite {
+    /// The length that the commitlog is truncated to.
+    log_len: u64,
+    /// The serialized entries that are appended after `log_len` to complete the write. `None` if the write is undone
+    /// instead.
+    entries: Option<Vec<Vec<u8>>>,
+    /// The metadata that is written once the entries are durable.
+    metadata: Vec<(Vec<u8>, Vec<u8>)>,
+}
 
-        let commitlog =
-            CommitLog::new(storage_config.commitlog_options).expect("Failed to create Commitlog");
+/// A serialized write set.
+struct WriteSet {
+    /// The length that the commitlog is truncated to before the entries are appended.
+    log_len: u64,
+    /// The serialized entries that are appended.
+    entries: Vec<Vec<u8>>,
+    /// The serialized metadata.
+    metadata: Vec<(&'static [u8], Vec<u8>)>,
+    /// Whether the accepted round is changed.
+    sets_accepted_round: bool,
+}
 
-        Self {
+impl<T: Entry, C: Codec> PersistentStorage<T, C> {
+    /// Creates or opens an existing storage. Writes that were interrupted by a crash are completed or undone. Panics if
+    /// the storage was written with a different format version or codec.
+    pub fn open(storage_config: PersistentStorageConfig<C>) -> Self {
+        let path = storage_config.path.expect("No path found in config");
+
+        let log_options = storage_config.commitlog_options;
+        let commitlog = CommitLog::new(log_options.clone()).expect("Failed to create Commitlog");
+        let sled = {
+            let opts = storage_config
+                .sled_options
+                .path(format!("{path}{DATABASE}"));
+            Config::open(&opts).expect("Failed to create sled database")
+        };
+        let log_start = match sled.get(LOG_START).expect("Failed to read log start") {
+            Some(bytes) => u64::read_from(bytes.as_bytes()).expect("Failed to read log start"),
+            None => 0,
+        };
+
+        let mut storage = Self {
             commitlog,
             log_path: format!("{path}{COMMITLOG}"),
-            sled: {
-                let opts = storage_config
-                    .sled_options
-                    .path(format!("{path}{DATABASE}"));
-                Config::open(&opts).expect("Failed to create sled database")
-            },
+            log_options,
+            log_start,
+            sled,
+            codec: storage_config.codec,
+            checksums: false,
+            unflushed: false,
+            unflushed_metadata: vec![],
+            #[cfg(feature = "crash_injection")]
+            crash_after: storage_config.crash_after,
+            #[cfg(feature = "crash_injection")]
+            disk_writes: 0,
             t: PhantomData,
+        };
+        storage
+            .check_format()
+            .expect("Failed to open storage with the configured format");
+        storage
+            .recover_pending_write()
+            .expect("Failed to recover interrupted write");
+        // The segments might not have been deleted if the process crashed while trimming
+        storage
+            .remove_trimmed_segments()
+            .expect("Failed to remove trimmed segments");
+        if storage_config.truncate_corrupted_tail {
+            storage
+                .truncate_corrupted_tail()
+                .expect("Failed to truncate corrupted log");
         }
+        storage
     }
 
     /// Creates a new storage instance, panics if a commitlog or sled instance already exists in the given path
-    pub fn new(storage_config: PersistentStorageConfig) -> Self {
+    pub fn new(storage_config: PersistentStorageConfig<C>) -> Self {
         let path = storage_config
             .path
             .as_ref()
@@ -141,6 +287,456 @@ impl<T: Entry> PersistentStorage<T> {
 
         Self::open(storage_config)
     }
+
+    /// Writes the format header if the storage is new and validates it otherwise. Storages that were created before
+    /// the format header was introduced are treated as version 1 with bincode, and their entries have no checksums.
+    fn check_format(&mut self) -> StorageResult<()> {
+        match self.sled.get(FORMAT)? {
+            Some(header) => {
+                if header.len() < 4 {
+                    return Err("Invalid format header".into());
+                }
+                let (version, codec) = header.split_at(4);
+                let version = u32::from_le_bytes(version.try_into()?);
+                if !(UNCHECKED_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+                    return Err(format!(
+                        "Unsupported format version {}, expected at most {}",
+                        version, FORMAT_VERSION
+                    )
+                    .into());
+                }
+                let codec = std::str::from_utf8(codec)?;
+                if codec != self.codec.name() {
+                    return Err(format!(
+                        "The storage was written with codec {}, but {} is configured",
+                        codec,
+                        self.codec.name()
+                    )
+                    .into());
+                }
+                self.checksums = version > UNCHECKED_FORMAT_VERSION;
+            }
+            None => {
+                let is_empty = self.sled.is_empty() && self.commitlog.next_offset() == 0;
+                if !is_empty && self.codec.name() != BincodeCodec.name() {
+                    return Err(format!(
+                        "The storage was written with codec {}, but {} is configured",
+                        BincodeCodec.name(),
+                        self.codec.name()
+                    )
+                    .into());
+                }
+                let version = if is_empty {
+                    FORMAT_VERSION
+                } else {
+                    UNCHECKED_FORMAT_VERSION
+                };
+                let mut header = version.to_le_bytes().to_vec();
+                header.extend_from_slice(self.codec.name().as_bytes());
+                self.checksums = is_empty;
+                self.sled.insert(FORMAT, header)?;
+                self.sled.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Completes or undoes the write that was interrupted by a crash, if any.
+    fn recover_pending_write(&mut self) -> StorageResult<()> {
+        if let Some(bytes) = self.sled.get(PENDING_WRITE)? {
+            let pending: PendingWrite = self.codec.decode(&bytes)?;
+            if pending.log_len < self.commitlog.next_offset() {
+                self.truncate_log(pending.log_len)?;
+            }
+            let mut batch = Batch::default();
+            if let Some(entries) = pending.entries {
+                if !entries.is_empty() {
+                    self.commitlog.append(&mut MessageBuf::from_iter(entries))?;
+                }
+                self.commitlog.flush()?;
+                for (key, value) in pending.metadata {
+                    batch.insert(key, value);
+                }
+            }
+            batch.remove(PENDING_WRITE);
+            self.sled.apply_batch(batch)?;
+            self.sled.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the writes of a write set. The truncations and appends are combined into a single truncation
+    /// followed by an append.
+    fn serialize_write_set(&self, ops: Vec<StorageOp<T>>) -> StorageResult<WriteSet>
+    where
+        T: Serialize,
+    {
+        let mut write = WriteSet {
+            log_len: self.commitlog.next_offset(),
+            entries: vec![],
+            metadata: vec![],
+            sets_accepted_round: false,
+        };
+        for op in ops {
+            match op {
+                StorageOp::AppendEntries(entries) => {
+                    for entry in entries {
+                        write.entries.push(self.encode_entry(&entry)?);
+                    }
+                }
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    let from_offset = self.log_start + from_idx;
+                    if from_offset <= write.log_len {
+                        write.log_len = from_offset;
+                        write.entries.clear();
+                    } else {
+                        let appended = (from_offset - write.log_len) as usize;
+                        write.entries.truncate(appended);
+                    }
+                    for entry in entries {
+                        write.entries.push(self.encode_entry(&entry)?);
+                    }
+                }
+                StorageOp::SetPromise(n_prom) => {
+                    write.metadata.push((NPROM, self.codec.encode(&n_prom)?))
+                }
+                StorageOp::SetAcceptedRound(na) => {
+                    write.metadata.push((ACC, self.codec.encode(&na)?));
+                    write.sets_accepted_round = true;
+                }
+                StorageOp::SetDecidedIdx(ld) => {
+                    write.metadata.push((DECIDE, u64::as_bytes(&ld).to_vec()))
+                }
+            }
+        }
+        Ok(write)
+    }
+
+    /// Returns the record of `write` that must be stored before the commitlog is changed, if any. A truncated log
+    /// cannot be restored, so such writes are completed on recovery. Entries that are appended together with
+    /// metadata are removed on recovery instead.
+    fn pending_write(&self, write: &WriteSet) -> Option<PendingWrite> {
+        if write.log_len < self.commitlog.next_offset() {
+            Some(PendingWrite {
+                log_len: write.log_len,
+                entries: Some(write.entries.clone()),
+                metadata: write
+                    .metadata
+                    .iter()
+                    .map(|(key, value)| (key.to_vec(), value.clone()))
+                    .collect(),
+            })
+        } else if !write.entries.is_empty() && !write.metadata.is_empty() {
+            Some(PendingWrite {
+                log_len: write.log_len,
+                entries: None,
+                metadata: vec![],
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Encodes an entry for the commitlog, preceded by the checksum of its encoding.
+    fn encode_entry(&self, entry: &T) -> StorageResult<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        let bytes = self.codec.encode(entry)?;
+        if !self.checksums {
+            return Ok(bytes);
+        }
+        let mut payload = Vec::with_capacity(CHECKSUM_LEN + bytes.len());
+        payload.extend_from_slice(&crc32fast::hash(&bytes).to_le_bytes());
+        payload.extend_from_slice(&bytes);
+        Ok(payload)
+    }
+
+    /// Verifies the checksum of the entry at `idx` and returns its encoding.
+    fn verify_entry<'a>(&self, idx: u64, payload: &'a [u8]) -> Result<&'a [u8], CorruptedEntry> {
+        if !self.checksums {
+            return Ok(payload);
+        }
+        if payload.len() < CHECKSUM_LEN {
+            return Err(CorruptedEntry { idx });
+        }
+        let (checksum, bytes) = payload.split_at(CHECKSUM_LEN);
+        if u32::from_le_bytes(checksum.try_into().unwrap()) != crc32fast::hash(bytes) {
+            return Err(CorruptedEntry { idx });
+        }
+        Ok(bytes)
+    }
+
+    /// Verifies the checksum of the entry at `idx` and decodes it.
+    fn decode_entry(&self, idx: u64, payload: &[u8]) -> StorageResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let bytes = self.verify_entry(idx, payload)?;
+        self.codec.decode(bytes)
+    }
+
+    /// Reads the entries from the commitlog offset onwards. A read does not continue into the next segment. The
+    /// commitlog only returns the entries that fit into the read limit, and it verifies its own checksums and fails
+    /// the whole read if an entry is corrupted. So if the entry at the offset is larger than `READ_BYTES` or is
+    /// followed by a corrupted entry, the entries are read again with a limit that fits them.
+    fn read_log(&self, offset: u64) -> StorageResult<MessageBuf> {
+        match self
+            .commitlog
+            .read(offset, ReadLimit::max_bytes(READ_BYTES))
+        {
+            Ok(buffer)
+                if buffer.iter().next().is_some() || offset >= self.commitlog.next_offset() =>
+            {
+                Ok(buffer)
+            }
+            Ok(_) | Err(ReadError::CorruptLog) => self.read_before_corruption(offset),
+            res => Ok(res?),
+        }
+    }
+
+    /// Reads the entries from the commitlog offset onwards that precede a corrupted entry. A read with a limit that
+    /// is smaller than the entry at the offset is empty and a read that includes a corrupted entry fails, so the limit
+    /// is searched for in between. Returns a `CorruptedEntry` error only if there is no such limit, i.e., if the
+    /// entry at the offset is corrupted itself.
+    fn read_before_corruption(&self, offset: u64) -> StorageResult<MessageBuf> {
+        // A read of `low` bytes is empty and a read of `high` bytes fails
+        let (mut low, mut high) = (0, MAX_MESSAGE_BYTES);
+        // The limit is doubled first, so that a large entry is read without most of the rest of the segment
+        let mut limit = READ_BYTES;
+        while limit < high {
+            match self.commitlog.read(offset, ReadLimit::max_bytes(limit)) {
+                Ok(buffer) if buffer.iter().next().is_some() => return Ok(buffer),
+                Ok(_) => {
+                    low = limit;
+                    limit = limit.saturating_mul(2);
+                }
+                Err(_) => high = limit,
+            }
+        }
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            match self.commitlog.read(offset, ReadLimit::max_bytes(mid)) {
+                Ok(buffer) if buffer.iter().next().is_some() => return Ok(buffer),
+                Ok(_) => low = mid,
+                Err(_) => high = mid,
+            }
+        }
+        Err(Box::new(CorruptedEntry {
+            idx: offset - self.log_start,
+        }))
+    }
+
+    /// Returns the index of the first entry in the log that does not match its checksum, if any.
+    fn find_corrupted_entry(&self) -> StorageResult<Option<u64>> {
+        let mut offset = self.log_start;
+        while offset < self.commitlog.next_offset() {
+            let buffer = match self.read_log(offset) {
+                Ok(buffer) => buffer,
+                Err(e) => match e.downcast::<CorruptedEntry>() {
+                    Ok(corrupted) => return Ok(Some(corrupted.idx)),
+                    Err(e) => return Err(e),
+                },
+            };
+            let read_from = offset;
+            for msg in buffer.iter() {
+                let idx = offset - self.log_start;
+                if self.verify_entry(idx, msg.payload()).is_err() {
+                    return Ok(Some(idx));
+                }
+                offset += 1;
+            }
+            if offset == read_from {
+                return Err(Box::new(ErrHelper {}));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Removes the log from the first corrupted entry onwards. The decided index is lowered to the end of the
+    /// remaining log first, so that the removed entries are synchronized from the leader again. The accepted round is
+    /// reset in the same write, as the removed entries might have been accepted in it.
+    fn truncate_corrupted_tail(&mut self) -> StorageResult<()> {
+        if let Some(idx) = self.find_corrupted_entry()? {
+            let read_idx = |key: &[u8]| -> StorageResult<u64> {
+                match self.sled.get(key)? {
+                    Some(bytes) => Ok(u64::read_from(bytes.as_bytes()).ok_or(ErrHelper {})?),
+                    None => Ok(0),
+                }
+            };
+            // The indices of OmniPaxos start after the compacted entries
+            let log_end = read_idx(TRIM)? + idx;
+            let decided_idx = read_idx(DECIDE)?.min(log_end);
+            self.write_metadata(
+                vec![
+                    (DECIDE, u64::as_bytes(&decided_idx).to_vec()),
+                    (ACC, self.codec.encode(&Ballot::default())?),
+                ],
+                false,
+            )?;
+            self.truncate_log(self.log_start + idx)?;
+            self.commitlog.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Performs a write set and makes it durable.
+    fn write_synchronously(&mut self, write: WriteSet) -> StorageResult<u64> {
+        self.flush_async_writes()?;
+        let pending = self.pending_write(&write);
+        if let Some(pending) = &pending {
+            self.sled
+                .insert(PENDING_WRITE, self.codec.encode(pending)?)?;
+            self.sled.flush()?;
+            self.crash_point();
+        }
+        self.write_entries(write.log_len, write.entries)?;
+        // The entries are flushed before the metadata that might refer to them
+        self.commitlog.flush()?;
+        self.crash_point();
+        self.write_metadata(write.metadata, pending.is_some())?;
+        Ok(self.commitlog.next_offset() - self.log_start)
+    }
+
+    /// Truncates the commitlog to the offset `log_len` and appends the entries without flushing.
+    fn write_entries(&mut self, log_len: u64, entries: Vec<Vec<u8>>) -> StorageResult<()> {
+        if log_len < self.commitlog.next_offset() {
+            self.truncate_log(log_len)?;
+            self.crash_point();
+        }
+        if !entries.is_empty() {
+            self.commitlog.append(&mut MessageBuf::from_iter(entries))?;
+            self.crash_point();
+        }
+        Ok(())
+    }
+
+    /// Removes the entries from the offset `log_len` onwards from the commitlog. Only the segments after the offset are
+    /// deleted and rewritten.
+    fn truncate_log(&mut self, log_len: u64) -> StorageResult<()> {
+        if log_len == 0 {
+            // The commitlog cannot remove its first entry, so it is created again
+            std::fs::remove_dir_all(&self.log_path)?;
+            self.commitlog = CommitLog::new(self.log_options.clone())?;
+        } else {
+            // Keeps the entry at the given offset
+            self.commitlog.truncate(log_len - 1)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the segments of the commitlog that only contain trimmed entries. The entry before the start of the log
+    /// is kept so that the log can still be truncated to its start.
+    fn remove_trimmed_segments(&mut self) -> StorageResult<()> {
+        if self.log_start > 1 {
+            self.commitlog.trim_segments_before(self.log_start - 1)?;
+            self.crash_point();
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata atomically as one sled batch and removes the record of the pending write.
+    fn write_metadata(
+        &mut self,
+        metadata: Vec<(&'static [u8], Vec<u8>)>,
+        remove_pending_write: bool,
+    ) -> StorageResult<()> {
+        if !metadata.is_empty() || remove_pending_write {
+            let mut batch = Batch::default();
+            for (key, value) in metadata {
+                batch.insert(key, value);
+            }
+            if remove_pending_write {
+                batch.remove(PENDING_WRITE);
+            }
+            self.sled.apply_batch(batch)?;
+            self.sled.flush()?;
+            self.crash_point();
+        }
+        Ok(())
+    }
+
+    /// Writes a single metadata value.
+    fn set_metadata(&mut self, key: &'static [u8], value: Vec<u8>) -> StorageResult<()> {
+        self.flush_async_writes()?;
+        self.write_metadata(vec![(key, value)], false)
+    }
+
+    /// Reads a metadata value, taking the unflushed write sets into account.
+    fn get_metadata(&self, key: &[u8]) -> StorageResult<Option<sled::IVec>> {
+        match self
+            .unflushed_metadata
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+        {
+            Some((_, value)) => Ok(Some(value.as_slice().into())),
+            None => Ok(self.sled.get(key)?),
+        }
+    }
+
+    /// Makes the write sets from `write_atomically_async()` durable.
+    fn flush_async_writes(&mut self) -> StorageResult<()> {
+        if self.unflushed {
+            self.commitlog.flush()?;
+            self.crash_point();
+            let metadata = std::mem::take(&mut self.unflushed_metadata);
+            self.write_metadata(metadata, false)?;
+            self.unflushed = false;
+        }
+        Ok(())
+    }
+
+    /// Called after every write to the disk. Aborts the process once the configured number of writes is reached.
+    fn crash_point(&mut self) {
+        #[cfg(feature = "crash_injection")]
+        {
+            self.disk_writes += 1;
+            if self.crash_after == Some(self.disk_writes) {
+                std::process::abort();
+            }
+        }
+    }
+}
+
+/// The error of reading an entry that does not match its checksum.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CorruptedEntry {
+    /// The index of the corrupted entry in the log
+    pub idx: u64,
+}
+impl std::error::Error for CorruptedEntry {}
+impl std::fmt::Display for CorruptedEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The entry at index {} of the log is corrupted", self.idx)
+    }
+}
+
+impl<T, C> PersistentStorage<T, C>
+where
+    C: Codec,
+    T: Entry + Serialize + for<'a> Deserialize<'a>,
+    T::Snapshot: Serialize + for<'a> Deserialize<'a>,
+{
+    /// Reads the whole log and the replica state and verifies that they can be decoded. Returns a `CorruptedEntry`
+    /// error with the index of the first entry that does not match its checksum.
+    pub fn verify(&self) -> StorageResult<()> {
+        let log_len = self.get_log_len()?;
+        let mut from = 0;
+        while from < log_len {
+            let to = (from + VERIFY_BATCH_LEN).min(log_len);
+            self.get_entries(from, to)?;
+            from = to;
+        }
+        self.get_promise()?;
+        self.get_accepted_round()?;
+        self.get_decided_idx()?;
+        self.get_compacted_idx()?;
+        self.get_stopsign()?;
+        self.get_membership()?;
+        self.get_snapshot()?;
+        Ok(())
+    }
 }
 
 /// An error returning the proposal that was failed due to that the current configuration is stopped.
@@ -153,72 +749,62 @@ impl std::fmt::Display for ErrHelper {
     }
 }
 
-impl<T> Storage<T> for PersistentStorage<T>
+impl<T, C> Storage<T> for PersistentStorage<T, C>
 where
+    C: Codec,
     T: Entry + Serialize + for<'a> Deserialize<'a>,
     T::Snapshot: Serialize + for<'a> Deserialize<'a>,
 {
     fn append_entry(&mut self, entry: T) -> StorageResult<u64> {
-        let entry_bytes = bincode::serialize(&entry)?;
-        let offset = self.commitlog.append_msg(entry_bytes)?;
-        self.commitlog.flush()?; // ensure durable writes
-        Ok(offset + 1) // +1 as commitlog returns the offset the entry was appended at, while we should return the index that the entry got in the log.
+        self.append_entries(vec![entry])
     }
 
     fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<u64> {
-        // Required check because Commitlog has a bug where appending an empty set of entries will
-        // always return an offset.first() with 0 despite entries being in the log.
-        if entries.is_empty() {
-            return self.get_log_len();
-        }
-        let mut serialized = vec![];
-        for entry in entries {
-            serialized.push(bincode::serialize(&entry)?)
-        }
-        let offset = self
-            .commitlog
-            .append(&mut MessageBuf::from_iter(serialized))?;
-        self.commitlog.flush()?; // ensure durable writes
-        Ok(offset.first() + offset.len() as u64)
+        self.write_atomically(vec![StorageOp::AppendEntries(entries)])
     }
 
     fn append_on_prefix(&mut self, from_idx: u64, entries: Vec<T>) -> StorageResult<u64> {
-        if from_idx > 0 && from_idx < self.get_log_len()? {
-            self.commitlog.truncate(from_idx)?;
-        }
-        self.append_entries(entries)
+        self.write_atomically(vec![StorageOp::AppendOnPrefix(from_idx, entries)])
     }
 
     fn get_entries(&self, from: u64, to: u64) -> StorageResult<Vec<T>> {
+        let (from, to) = (self.log_start + from, self.log_start + to);
         // Check if the commit log has entries up to the requested endpoint.
         if to > self.commitlog.next_offset() || from >= to {
             return Ok(vec![]); // Do an early return
         }
 
-        let buffer = self.commitlog.read(from, ReadLimit::default())?;
         let mut entries = Vec::<T>::with_capacity((to - from) as usize);
-        let mut iter = buffer.iter();
-        for _ in from..to {
-            let msg = iter.next().ok_or(ErrHelper {})?;
-            entries.push(bincode::deserialize(msg.payload())?);
+        let mut offset = from;
+        while offset < to {
+            let buffer = self.read_log(offset)?;
+            for msg in buffer.iter().take((to - offset) as usize) {
+                let idx = from + entries.len() as u64 - self.log_start;
+                entries.push(self.decode_entry(idx, msg.payload())?);
+            }
+            let read_to = from + entries.len() as u64;
+            if read_to == offset {
+                return Err(Box::new(ErrHelper {}));
+            }
+            offset = read_to;
         }
         Ok(entries)
     }
 
     fn get_log_len(&self) -> StorageResult<u64> {
-        Ok(self.commitlog.next_offset())
+        Ok(self.commitlog.next_offset() - self.log_start)
     }
 
     fn get_suffix(&self, from: u64) -> StorageResult<Vec<T>> {
-        self.get_entries(from, self.commitlog.next_offset())
+        self.get_entries(from, self.get_log_len()?)
     }
 
     fn get_promise(&self) -> StorageResult<Option<Ballot>> {
         {
-            let promised = self.sled.get(NPROM)?;
+            let promised = self.get_metadata(NPROM)?;
             match promised {
                 Some(prom_bytes) => {
-                    let ballot = bincode::deserialize(&prom_bytes)?;
+                    let ballot = self.codec.decode(&prom_bytes)?;
                     Ok(Some(ballot))
                 }
                 None => Ok(Some(Ballot::default())),
@@ -227,13 +813,12 @@ where
     }
 
     fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
-        let prom_bytes = bincode::serialize(&n_prom)?;
-        self.sled.insert(NPROM, prom_bytes)?;
-        Ok(())
+        let prom_bytes = self.codec.encode(&n_prom)?;
+        self.set_metadata(NPROM, prom_bytes)
     }
 
     fn get_decided_idx(&self) -> StorageResult<u64> {
-        let decided = self.sled.get(DECIDE)?;
+        let decided = self.get_metadata(DECIDE)?;
         match decided {
             Some(ld_bytes) => Ok(u64::read_from(ld_bytes.as_bytes()).ok_or(ErrHelper {})?),
             None => Ok(0),
@@ -242,15 +827,14 @@ where
 
     fn set_decided_idx(&mut self, ld: u64) -> StorageResult<()> {
         let ld_bytes = u64::as_bytes(&ld);
-        self.sled.insert(DECIDE, ld_bytes)?;
-        Ok(())
+        self.set_metadata(DECIDE, ld_bytes.to_vec())
     }
 
     fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
-        let accepted = self.sled.get(ACC)?;
+        let accepted = self.get_metadata(ACC)?;
         match accepted {
             Some(acc_bytes) => {
-                let ballot = bincode::deserialize(&acc_bytes)?;
+                let ballot = self.codec.decode(&acc_bytes)?;
                 Ok(Some(ballot))
             }
             None => Ok(Some(Ballot::default())),
@@ -258,9 +842,8 @@ where
     }
 
     fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
-        let acc_bytes = bincode::serialize(&na)?;
-        self.sled.insert(ACC, acc_bytes)?;
-        Ok(())
+        let acc_bytes = self.codec.encode(&na)?;
+        self.set_metadata(ACC, acc_bytes)
     }
 
     fn get_compacted_idx(&self) -> StorageResult<u64> {
@@ -273,46 +856,81 @@ where
 
     fn set_compacted_idx(&mut self, trimmed_idx: u64) -> StorageResult<()> {
         let trim_bytes = u64::as_bytes(&trimmed_idx);
-        self.sled.insert(TRIM, trim_bytes)?;
-        Ok(())
+        self.set_metadata(TRIM, trim_bytes.to_vec())
     }
 
     fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
         let stopsign = self.sled.get(STOPSIGN)?;
         match stopsign {
-            Some(ss_bytes) => Ok(bincode::deserialize(&ss_bytes)?),
+            Some(ss_bytes) => self.codec.decode(&ss_bytes),
             None => Ok(None),
         }
     }
 
     fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
-        let stopsign = bincode::serialize(&s)?;
-        self.sled.insert(STOPSIGN, stopsign)?;
-        Ok(())
+        let stopsign = self.codec.encode(&s)?;
+        self.set_metadata(STOPSIGN, stopsign)
+    }
+
+    fn get_membership(&self) -> StorageResult<Option<Membership>> {
+        let membership = self.sled.get(MEMBERSHIP)?;
+        match membership {
+            Some(m_bytes) => self.codec.decode(&m_bytes),
+            None => Ok(None),
+        }
+    }
+
+    fn set_membership(&mut self, m: Option<Membership>) -> StorageResult<()> {
+        let membership = self.codec.encode(&m)?;
+        self.set_metadata(MEMBERSHIP, membership)
     }
 
     fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
         let snapshot = self.sled.get(SNAPSHOT)?;
         if let Some(snapshot_bytes) = snapshot {
-            Ok(bincode::deserialize(snapshot_bytes.as_bytes())?)
+            self.codec.decode(snapshot_bytes.as_bytes())
         } else {
             Ok(None)
         }
     }
 
     fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
-        let stopsign = bincode::serialize(&snapshot)?;
-        self.sled.insert(SNAPSHOT, stopsign)?;
-        Ok(())
+        let snapshot_bytes = self.codec.encode(&snapshot)?;
+        self.set_metadata(SNAPSHOT, snapshot_bytes)
     }
 
-    // TODO: A way to trim the commitlog without deleting and recreating the log
     fn trim(&mut self, trimmed_idx: u64) -> StorageResult<()> {
-        let trimmed_log: Vec<T> = self.get_entries(trimmed_idx, self.commitlog.next_offset())?; // get the log entries from 'trimmed_idx' to latest
-        std::fs::remove_dir_all(&self.log_path)?; // remove old log
-        let c_opts = LogOptions::new(&self.log_path);
-        self.commitlog = CommitLog::new(c_opts)?; // create new commitlog
-        self.append_entries(trimmed_log)?;
-        Ok(())
+        let log_start = self.log_start + trimmed_idx.min(self.get_log_len()?);
+        if log_start == self.log_start {
+            return Ok(());
+        }
+        // The new start of the log is durable before any segment is deleted
+        self.set_metadata(LOG_START, u64::as_bytes(&log_start).to_vec())?;
+        self.log_start = log_start;
+        self.remove_trimmed_segments()
+    }
+
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        let write = self.serialize_write_set(ops)?;
+        self.write_synchronously(write)
+    }
+
+    fn write_atomically_async(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        let write = self.serialize_write_set(ops)?;
+        if write.log_len < self.commitlog.next_offset() || write.sets_accepted_round {
+            // The log must match the accepted round, so these writes are not deferred. The entries of other write
+            // sets might become durable before their metadata.
+            return self.write_synchronously(write);
+        }
+        self.write_entries(write.log_len, write.entries)?;
+        self.unflushed_metadata.extend(write.metadata);
+        self.unflushed = true;
+        self.get_log_len()
+    }
+
+    fn poll_flushed(&mut self) -> StorageResult<u64> {
+        // All pending write sets are flushed at once
+        self.flush_async_writes()?;
+        self.get_log_len()
     }
 }
@@ -0,0 +1,369 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Entry, Membership, StopSign, Storage, StorageOp, StorageResult},
+};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::ConflictableTransactionResult, Config, Db, Transactional, Tree};
+use std::{collections::BTreeMap, convert::TryInto, marker::PhantomData};
+
+const DEFAULT: &str = "/default_sled_storage/";
+const LOG: &[u8] = b"LOG";
+const STATE: &[u8] = b"STATE";
+const NPROM: &[u8] = b"NPROM";
+const ACC: &[u8] = b"ACC";
+const DECIDE: &[u8] = b"DECIDE";
+const TRIM: &[u8] = b"TRIM";
+const STOPSIGN: &[u8] = b"STOPSIGN";
+const SNAPSHOT: &[u8] = b"SNAPSHOT";
+const MEMBERSHIP: &[u8] = b"MEMBERSHIP";
+const LOG_START: &[u8] = b"LOG_START";
+
+/// Configuration for `SledStorage`.
+/// # Fields
+/// * `path`: Path to the sled database
+/// * `sled_options` : Options for the sled database
+pub struct SledStorageConfig {
+    path: Option<String>,
+    sled_options: Config,
+}
+
+impl SledStorageConfig {
+    /// Returns the current path to the sled database.
+    pub fn get_path(&self) -> Option<&String> {
+        self.path.as_ref()
+    }
+
+    /// Sets the path to the sled database.
+    pub fn set_path(&mut self, path: String) {
+        self.path = Some(path);
+    }
+
+    /// Returns the options for the sled database.
+    pub fn get_database_options(&self) -> Config {
+        self.sled_options.clone()
+    }
+
+    /// Sets the options for the sled database.
+    pub fn set_database_options(&mut self, opts: Config) {
+        self.sled_options = opts;
+    }
+
+    /// Creates a configuration for `SledStorage` with the given path and options for sled
+    pub fn with(path: String, sled_options: Config) -> Self {
+        Self {
+            path: Some(path),
+            sled_options,
+        }
+    }
+}
+
+impl Default for SledStorageConfig {
+    fn default() -> Self {
+        Self {
+            path: Some(DEFAULT.to_string()),
+            sled_options: Config::new(),
+        }
+    }
+}
+
+/// A persistent storage implementation that only depends on sled. The log is stored in its own tree with the
+/// big-endian index of each entry as key, so that the entries are ordered by their index. The replica state is stored
+/// in a second tree.
+///
+/// A write set is performed as a single sled transaction over both trees and is therefore atomic. Trimming the log
+/// removes the trimmed entries and stores the index of the first entry in the log.
+pub struct SledStorage<T>
+where
+    T: Entry,
+{
+    /// The sled database
+    db: Db,
+    /// The entries of the log
+    log: Tree,
+    /// The replica state
+    state: Tree,
+    /// The key of the first entry in the log, i.e., the number of trimmed entries
+    log_start: u64,
+    /// The key after the last entry in the log
+    log_end: u64,
+    /// A placeholder for the T: Entry
+    t: PhantomData<T>,
+}
+
+/// A serialized write set.
+struct WriteSet {
+    /// The entries that are written to the log by their key. `None` removes the entry.
+    log: BTreeMap<u64, Option<Vec<u8>>>,
+    /// The serialized replica state.
+    state: Vec<(&'static [u8], Vec<u8>)>,
+    /// The key after the last entry in the log once the write set is performed.
+    log_end: u64,
+}
+
+impl WriteSet {
+    fn append<T: Serialize>(&mut self, entries: Vec<T>) -> StorageResult<()> {
+        for entry in entries {
+            self.log
+                .insert(self.log_end, Some(bincode::serialize(&entry)?));
+            self.log_end += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key of the entry at `idx`.
+fn key(idx: u64) -> [u8; 8] {
+    idx.to_be_bytes()
+}
+
+impl<T: Entry> SledStorage<T> {
+    /// Creates or opens an existing storage.
+    pub fn open(storage_config: SledStorageConfig) -> Self {
+        let path = storage_config.path.expect("No path found in config");
+        let db = storage_config
+            .sled_options
+            .path(path)
+            .open()
+            .expect("Failed to create sled database");
+        let log = db.open_tree(LOG).expect("Failed to open log");
+        let state = db.open_tree(STATE).expect("Failed to open state");
+
+        let log_start = match state.get(LOG_START).expect("Failed to read log start") {
+            Some(bytes) => bincode::deserialize(&bytes).expect("Failed to read log start"),
+            None => 0,
+        };
+        let log_end = match log.last().expect("Failed to read log") {
+            Some((last_key, _)) => {
+                u64::from_be_bytes(last_key.as_ref().try_into().expect("Invalid log key")) + 1
+            }
+            None => log_start,
+        };
+        Self {
+            db,
+            log,
+            state,
+            log_start,
+            log_end,
+            t: PhantomData,
+        }
+    }
+
+    /// Creates a new storage instance, panics if a sled database already exists in the given path
+    pub fn new(storage_config: SledStorageConfig) -> Self {
+        let path = storage_config
+            .path
+            .as_ref()
+            .expect("No path found in config");
+
+        std::fs::metadata(path).expect_err(&format!(
+            "Cannot create new instance, database already exists in {}",
+            path
+        ));
+
+        Self::open(storage_config)
+    }
+
+    /// Reads a value of the replica state.
+    fn get_state<V: for<'a> Deserialize<'a>>(&self, key: &[u8]) -> StorageResult<Option<V>> {
+        match self.state.get(key)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes a value of the replica state and makes it durable.
+    fn set_state<V: Serialize>(&mut self, key: &[u8], value: &V) -> StorageResult<()> {
+        self.state.insert(key, bincode::serialize(value)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Serializes the writes of a write set.
+    fn serialize_write_set(&self, ops: Vec<StorageOp<T>>) -> StorageResult<WriteSet>
+    where
+        T: Serialize,
+    {
+        let mut write = WriteSet {
+            log: BTreeMap::new(),
+            state: vec![],
+            log_end: self.log_end,
+        };
+        for op in ops {
+            match op {
+                StorageOp::AppendEntries(entries) => write.append(entries)?,
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    let from = self.log_start + from_idx;
+                    for idx in from..write.log_end {
+                        write.log.insert(idx, None);
+                    }
+                    write.log_end = write.log_end.min(from);
+                    write.append(entries)?;
+                }
+                StorageOp::SetPromise(n_prom) => {
+                    write.state.push((NPROM, bincode::serialize(&n_prom)?))
+                }
+                StorageOp::SetAcceptedRound(na) => {
+                    write.state.push((ACC, bincode::serialize(&na)?))
+                }
+                StorageOp::SetDecidedIdx(ld) => {
+                    write.state.push((DECIDE, bincode::serialize(&ld)?))
+                }
+            }
+        }
+        Ok(write)
+    }
+
+    /// Performs a write set as a single transaction without flushing it.
+    fn apply_write_set(&mut self, write: WriteSet) -> StorageResult<u64> {
+        (&self.log, &self.state).transaction(
+            |(log, state)| -> ConflictableTransactionResult<(), sled::Error> {
+                for (idx, entry) in &write.log {
+                    match entry {
+                        Some(entry) => log.insert(&key(*idx), entry.as_slice())?,
+                        None => log.remove(&key(*idx))?,
+                    };
+                }
+                for (state_key, value) in &write.state {
+                    state.insert(*state_key, value.as_slice())?;
+                }
+                Ok(())
+            },
+        )?;
+        self.log_end = write.log_end;
+        Ok(self.log_end - self.log_start)
+    }
+}
+
+impl<T> Storage<T> for SledStorage<T>
+where
+    T: Entry + Serialize + for<'a> Deserialize<'a>,
+    T::Snapshot: Serialize + for<'a> Deserialize<'a>,
+{
+    fn append_entry(&mut self, entry: T) -> StorageResult<u64> {
+        self.append_entries(vec![entry])
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<u64> {
+        self.write_atomically(vec![StorageOp::AppendEntries(entries)])
+    }
+
+    fn append_on_prefix(&mut self, from_idx: u64, entries: Vec<T>) -> StorageResult<u64> {
+        self.write_atomically(vec![StorageOp::AppendOnPrefix(from_idx, entries)])
+    }
+
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.set_state(NPROM, &n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: u64) -> StorageResult<()> {
+        self.set_state(DECIDE, &ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<u64> {
+        Ok(self.get_state(DECIDE)?.unwrap_or_default())
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.set_state(ACC, &na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        Ok(Some(self.get_state(ACC)?.unwrap_or_default()))
+    }
+
+    fn get_entries(&self, from: u64, to: u64) -> StorageResult<Vec<T>> {
+        let (from, to) = (self.log_start + from, self.log_start + to);
+        if to > self.log_end || from >= to {
+            return Ok(vec![]);
+        }
+        let mut entries = Vec::with_capacity((to - from) as usize);
+        for entry in self.log.range(key(from)..key(to)) {
+            let (_, bytes) = entry?;
+            entries.push(bincode::deserialize(&bytes)?);
+        }
+        Ok(entries)
+    }
+
+    fn get_log_len(&self) -> StorageResult<u64> {
+        Ok(self.log_end - self.log_start)
+    }
+
+    fn get_suffix(&self, from: u64) -> StorageResult<Vec<T>> {
+        self.get_entries(from, self.get_log_len()?)
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(Some(self.get_state(NPROM)?.unwrap_or_default()))
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.set_state(STOPSIGN, &s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        Ok(self.get_state(STOPSIGN)?.flatten())
+    }
+
+    fn set_membership(&mut self, m: Option<Membership>) -> StorageResult<()> {
+        self.set_state(MEMBERSHIP, &m)
+    }
+
+    fn get_membership(&self) -> StorageResult<Option<Membership>> {
+        Ok(self.get_state(MEMBERSHIP)?.flatten())
+    }
+
+    fn trim(&mut self, trimmed_idx: u64) -> StorageResult<()> {
+        let log_start = self.log_start + trimmed_idx.min(self.get_log_len()?);
+        if log_start == self.log_start {
+            return Ok(());
+        }
+        let old_start = self.log_start;
+        let start_bytes = bincode::serialize(&log_start)?;
+        (&self.log, &self.state).transaction(
+            |(log, state)| -> ConflictableTransactionResult<(), sled::Error> {
+                for idx in old_start..log_start {
+                    log.remove(&key(idx))?;
+                }
+                state.insert(LOG_START, start_bytes.as_slice())?;
+                Ok(())
+            },
+        )?;
+        self.db.flush()?;
+        self.log_start = log_start;
+        Ok(())
+    }
+
+    fn set_compacted_idx(&mut self, idx: u64) -> StorageResult<()> {
+        self.set_state(TRIM, &idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<u64> {
+        Ok(self.get_state(TRIM)?.unwrap_or_default())
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        self.set_state(SNAPSHOT, &snapshot)
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        Ok(self.get_state(SNAPSHOT)?.flatten())
+    }
+
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        let write = self.serialize_write_set(ops)?;
+        let log_len = self.apply_write_set(write)?;
+        self.db.flush()?;
+        Ok(log_len)
+    }
+
+    fn write_atomically_async(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<u64> {
+        // sled recovers a prefix of the writes after a crash, so the write set is durable once a later flush completes
+        let write = self.serialize_write_set(ops)?;
+        self.apply_write_set(write)
+    }
+
+    fn poll_flushed(&mut self) -> StorageResult<u64> {
+        self.db.flush()?;
+        self.get_log_len()
+    }
+}
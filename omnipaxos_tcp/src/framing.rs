@@ -0,0 +1,51 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The size of the length prefix of a frame in bytes.
+const LENGTH_SIZE: usize = 4;
+
+/// Encodes `msg` as a frame consisting of the length of the serialized message followed by the message itself. Returns
+/// an error if the serialized message is larger than `max_frame_size` bytes.
+pub(crate) fn encode_frame<M>(msg: &M, max_frame_size: usize) -> io::Result<Vec<u8>>
+where
+    M: Serialize,
+{
+    let payload =
+        bincode::serialize(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if payload.len() > max_frame_size {
+        return Err(frame_too_large(payload.len(), max_frame_size));
+    }
+    let len = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "message too large"))?;
+    let mut frame = Vec::with_capacity(LENGTH_SIZE + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Reads a frame encoded by `encode_frame` and deserializes the message. Returns an error without reading the message
+/// if its length is larger than `max_frame_size` bytes.
+pub(crate) async fn read_frame<R, M>(reader: &mut R, max_frame_size: usize) -> io::Result<M>
+where
+    R: AsyncRead + Unpin,
+    M: DeserializeOwned,
+{
+    let len = reader.read_u32().await? as usize;
+    if len > max_frame_size {
+        return Err(frame_too_large(len, max_frame_size));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    bincode::deserialize(&payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn frame_too_large(len: usize, max_frame_size: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "frame of {} bytes exceeds the maximum frame size of {} bytes",
+            len, max_frame_size
+        ),
+    )
+}
@@ -0,0 +1,11 @@
+//! A TCP transport for OmniPaxos. The [`TcpTransport`](transport::TcpTransport) connects the channels of an
+//! `OmniPaxosDriver` from `omnipaxos_tokio` to the other servers. It keeps one connection to each peer, sends the
+//! messages as length-delimited frames, and re-establishes lost connections.
+
+#![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![deny(missing_docs)]
+mod framing;
+/// The transport and its configuration.
+pub mod transport;
+
+pub use transport::{TcpTransport, TransportConfig};
@@ -0,0 +1,277 @@
+use crate::framing::{encode_frame, read_frame};
+use omnipaxos::{
+    messages::Message,
+    storage::{Entry, Storage},
+    util::NodeId,
+    ServerConfig,
+};
+use omnipaxos_tokio::OmniPaxosHandle;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, io, net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+    time,
+};
+
+/// The time that a server that connects to this server has to identify itself.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Configuration for `TcpTransport`.
+/// # Fields
+/// * `pid`: The pid of this server.
+/// * `listen_address`: The address that this server accepts connections from its peers on.
+/// * `peers`: The addresses of the other servers.
+/// * `buffer_size`: The number of messages buffered for each peer and for the incoming messages.
+/// * `reconnect_interval`: The time between two attempts to connect to a peer.
+/// * `max_frame_size`: The maximum size of a serialized message in bytes.
+#[derive(Clone, Debug)]
+pub struct TransportConfig {
+    /// The pid of this server.
+    pub pid: NodeId,
+    /// The address that this server accepts connections from its peers on.
+    pub listen_address: SocketAddr,
+    /// The addresses of the other servers. Each pair of servers shares a single connection, which is established by the
+    /// server with the lower pid.
+    pub peers: HashMap<NodeId, SocketAddr>,
+    /// The number of messages buffered for each peer and for the incoming messages. Should be the `buffer_size` of the
+    /// [`ServerConfig`]. Outgoing messages to a peer whose buffer is full are dropped and later resent by OmniPaxos, while
+    /// a full incoming buffer stops reading from the connections until the driver has caught up.
+    pub buffer_size: usize,
+    /// The time between two attempts to connect to a peer.
+    pub reconnect_interval: Duration,
+    /// The maximum size of a serialized message in bytes. A peer that sends a larger message is disconnected, and
+    /// larger outgoing messages are dropped. Must be larger than the messages that OmniPaxos sends, e.g., the
+    /// synchronization of a follower and the chunks of a snapshot.
+    pub max_frame_size: usize,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            pid: 0,
+            listen_address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            peers: HashMap::new(),
+            buffer_size: ServerConfig::default().buffer_size,
+            reconnect_interval: Duration::from_millis(100),
+            max_frame_size: 64 << 20,
+        }
+    }
+}
+
+/// Sends the outgoing messages of an `OmniPaxosDriver` to the other servers over TCP and passes the messages received from
+/// them to the driver. When the connection to a peer is re-established after it was lost, the transport calls
+/// `OmniPaxos::reconnected()` through the handle of the driver. Messages received from a peer that are not sent by the
+/// pid that the peer identified itself with are dropped.
+pub struct TcpTransport<T>
+where
+    T: Entry,
+{
+    config: TransportConfig,
+    incoming: mpsc::Sender<Message<T>>,
+    outgoing: mpsc::Receiver<Message<T>>,
+}
+
+impl<T> TcpTransport<T>
+where
+    T: Entry + Serialize + DeserializeOwned + Send + 'static,
+    T::Snapshot: Send,
+{
+    /// Creates a transport with the given configuration. Returns the transport and the incoming and outgoing channels that
+    /// should be passed to `OmniPaxosDriver::new`.
+    pub fn new(
+        config: TransportConfig,
+    ) -> (Self, mpsc::Receiver<Message<T>>, mpsc::Sender<Message<T>>) {
+        let (incoming_sender, incoming_receiver) = mpsc::channel(config.buffer_size);
+        let (outgoing_sender, outgoing_receiver) = mpsc::channel(config.buffer_size);
+        let transport = Self {
+            config,
+            incoming: incoming_sender,
+            outgoing: outgoing_receiver,
+        };
+        (transport, incoming_receiver, outgoing_sender)
+    }
+
+    /// Runs the transport until the driver stops. `handle` is the handle of the driver that this transport was created for.
+    /// Returns an error if the listen address cannot be bound.
+    pub async fn run<B>(mut self, handle: OmniPaxosHandle<T, B>) -> io::Result<()>
+    where
+        B: Storage<T> + 'static,
+    {
+        let listener = TcpListener::bind(self.config.listen_address).await?;
+        let mut queues = HashMap::new();
+        let mut connections = HashMap::new();
+        let mut tasks = vec![];
+        for (&pid, &address) in &self.config.peers {
+            let (queue_sender, queue) = mpsc::channel(self.config.buffer_size);
+            let (connection_sender, connection_receiver) = mpsc::channel(1);
+            let peer = Peer {
+                pid: self.config.pid,
+                peer: pid,
+                address: (self.config.pid < pid).then_some(address),
+                reconnect_interval: self.config.reconnect_interval,
+                max_frame_size: self.config.max_frame_size,
+                queue,
+                connections: connection_receiver,
+                incoming: self.incoming.clone(),
+                handle: handle.clone(),
+            };
+            tasks.push(tokio::spawn(peer.run()));
+            queues.insert(pid, queue_sender);
+            connections.insert(pid, connection_sender);
+        }
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    if let Ok((stream, _)) = accepted {
+                        let connections = connections.clone();
+                        tokio::spawn(async move {
+                            if let Some((pid, stream)) = accept_handshake(stream).await {
+                                if let Some(connection_sender) = connections.get(&pid) {
+                                    let _ = connection_sender.send(stream).await;
+                                }
+                            }
+                        });
+                    }
+                },
+                msg = self.outgoing.recv() => match msg {
+                    Some(msg) => {
+                        if let Some(queue) = queues.get(&msg.get_receiver()) {
+                            // Dropped messages are resent by OmniPaxos
+                            let _ = queue.try_send(msg);
+                        }
+                    }
+                    None => break,
+                },
+            }
+        }
+        for task in tasks {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+/// Reads the pid of the server that connected to us.
+async fn accept_handshake(mut stream: TcpStream) -> Option<(NodeId, TcpStream)> {
+    match time::timeout(HANDSHAKE_TIMEOUT, stream.read_u64()).await {
+        Ok(Ok(pid)) => Some((pid, stream)),
+        _ => None,
+    }
+}
+
+/// Aborts the task that reads from a connection when the connection is replaced.
+struct ReaderTask(JoinHandle<()>);
+
+impl Drop for ReaderTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The connection to a single peer.
+struct Peer<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    pid: NodeId,
+    peer: NodeId,
+    /// The address of the peer if this server establishes the connection, `None` if the peer connects to us.
+    address: Option<SocketAddr>,
+    reconnect_interval: Duration,
+    max_frame_size: usize,
+    queue: mpsc::Receiver<Message<T>>,
+    connections: mpsc::Receiver<TcpStream>,
+    incoming: mpsc::Sender<Message<T>>,
+    handle: OmniPaxosHandle<T, B>,
+}
+
+impl<T, B> Peer<T, B>
+where
+    T: Entry + Serialize + DeserializeOwned + Send + 'static,
+    T::Snapshot: Send,
+    B: Storage<T>,
+{
+    async fn run(mut self) {
+        let mut connected_before = false;
+        let mut next_stream = None;
+        loop {
+            let stream = match next_stream.take() {
+                Some(stream) => stream,
+                None => match self.address {
+                    Some(address) => self.connect(address).await,
+                    None => match self.connections.recv().await {
+                        Some(stream) => stream,
+                        None => return,
+                    },
+                },
+            };
+            if connected_before {
+                let peer = self.peer;
+                if self
+                    .handle
+                    .with(move |omni_paxos| omni_paxos.reconnected(peer))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            connected_before = true;
+            let _ = stream.set_nodelay(true);
+            let (mut reader, mut writer) = stream.into_split();
+            let incoming = self.incoming.clone();
+            let max_frame_size = self.max_frame_size;
+            let peer = self.peer;
+            let mut reader_task = ReaderTask(tokio::spawn(async move {
+                while let Ok(msg) = read_frame::<_, Message<T>>(&mut reader, max_frame_size).await {
+                    // The peer identified itself in the handshake and may only send its own messages
+                    if msg.get_sender() != peer {
+                        continue;
+                    }
+                    if incoming.send(msg).await.is_err() {
+                        return;
+                    }
+                }
+            }));
+            loop {
+                tokio::select! {
+                    msg = self.queue.recv() => match msg {
+                        Some(msg) => {
+                            let frame = match encode_frame(&msg, self.max_frame_size) {
+                                Ok(frame) => frame,
+                                Err(_) => continue,
+                            };
+                            if writer.write_all(&frame).await.is_err() {
+                                break;
+                            }
+                        }
+                        None => return,
+                    },
+                    // The connection was closed
+                    _ = &mut reader_task.0 => break,
+                    // The peer connected again, e.g., after it restarted
+                    Some(stream) = self.connections.recv() => {
+                        next_stream = Some(stream);
+                        break;
+                    },
+                }
+            }
+        }
+    }
+
+    /// Connects to the peer, retrying until the connection is established.
+    async fn connect(&mut self, address: SocketAddr) -> TcpStream {
+        loop {
+            if let Ok(mut stream) = TcpStream::connect(address).await {
+                if stream.write_u64(self.pid).await.is_ok() {
+                    return stream;
+                }
+            }
+            time::sleep(self.reconnect_interval).await;
+        }
+    }
+}
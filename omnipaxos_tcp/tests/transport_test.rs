@@ -0,0 +1,258 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    macros::Entry,
+    messages::{
+        sequence_paxos::{PaxosMessage, PaxosMsg, Prepare},
+        Message,
+    },
+    util::NodeId,
+    ClusterConfig, OmniPaxos, OmniPaxosConfig, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use omnipaxos_tcp::{TcpTransport, TransportConfig};
+use omnipaxos_tokio::{OmniPaxosDriver, OmniPaxosHandle};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, future::Future, net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time,
+};
+
+const SERVERS: [NodeId; 3] = [1, 2, 3];
+const TICK_PERIOD: Duration = Duration::from_millis(10);
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+const NUM_PROPOSALS: u64 = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Entry)]
+struct Value(u64);
+
+type Handle = OmniPaxosHandle<Value, MemoryStorage<Value>>;
+
+/// Returns a free address on localhost.
+async fn free_address() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap()
+}
+
+/// Starts a server that uses `peers` as the addresses of the other servers.
+fn start_server(
+    pid: NodeId,
+    listen_address: SocketAddr,
+    peers: HashMap<NodeId, SocketAddr>,
+) -> Handle {
+    let server_config = ServerConfig {
+        pid,
+        election_tick_timeout: 5,
+        resend_message_tick_timeout: 20,
+        // Server 1 becomes the leader so that it is not cut off with server 3
+        leader_priority: if pid == 1 { 1 } else { 0 },
+        ..Default::default()
+    };
+    let transport_config = TransportConfig {
+        pid,
+        listen_address,
+        peers,
+        buffer_size: server_config.buffer_size,
+        reconnect_interval: Duration::from_millis(20),
+        ..Default::default()
+    };
+    let config = OmniPaxosConfig {
+        cluster_config: ClusterConfig {
+            configuration_id: 1,
+            nodes: SERVERS.into(),
+            ..Default::default()
+        },
+        server_config,
+    };
+    let omni_paxos: OmniPaxos<Value, MemoryStorage<Value>> =
+        config.build(MemoryStorage::default()).unwrap();
+    let (transport, incoming, outgoing) = TcpTransport::new(transport_config);
+    let (driver, handle) = OmniPaxosDriver::new(omni_paxos, TICK_PERIOD, incoming, outgoing);
+    tokio::spawn(driver.run());
+    tokio::spawn(transport.run(handle.clone()));
+    handle
+}
+
+/// A proxy that forwards the connections on `from` to `to` until it is stopped.
+fn start_proxy(from: SocketAddr, to: SocketAddr) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let listener = TcpListener::bind(from).await.unwrap();
+        let mut forwards = Forwards(vec![]);
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            forwards.0.push(tokio::spawn(async move {
+                if let Ok(mut outbound) = TcpStream::connect(to).await {
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                }
+            }));
+        }
+    })
+}
+
+/// The forwarded connections of a proxy, which are closed when the proxy is stopped.
+struct Forwards(Vec<JoinHandle<()>>);
+
+impl Drop for Forwards {
+    fn drop(&mut self) {
+        for forward in &self.0 {
+            forward.abort();
+        }
+    }
+}
+
+/// Connects to the server on `address`, retrying until it accepts connections.
+async fn connect(address: SocketAddr) -> TcpStream {
+    with_timeout(async {
+        loop {
+            if let Ok(stream) = TcpStream::connect(address).await {
+                return stream;
+            }
+            time::sleep(TICK_PERIOD).await;
+        }
+    })
+    .await
+}
+
+/// Writes `msg` to `stream` in the frame format of the transport.
+async fn write_frame(stream: &mut TcpStream, msg: &Message<Value>) {
+    let payload = bincode::serialize(msg).unwrap();
+    stream.write_u32(payload.len() as u32).await.unwrap();
+    stream.write_all(&payload).await.unwrap();
+}
+
+async fn with_timeout<F: Future>(f: F) -> F::Output {
+    time::timeout(WAIT_TIMEOUT, f)
+        .await
+        .expect("Timed out waiting for the cluster")
+}
+
+async fn wait_for_decided_idx(handle: &Handle, decided_idx: u64) {
+    with_timeout(async {
+        while handle.with(|op| op.get_decided_idx()).await.unwrap() < decided_idx {
+            time::sleep(TICK_PERIOD).await;
+        }
+    })
+    .await
+}
+
+/// Verifies that servers connected over TCP decide entries, and that a server that loses its
+/// connections catches up after they are re-established.
+#[tokio::test(flavor = "multi_thread")]
+async fn tcp_transport_reconnect_test() {
+    let mut addresses = HashMap::new();
+    for pid in SERVERS {
+        addresses.insert(pid, free_address().await);
+    }
+    // Servers 1 and 2 connect to server 3 through proxies so that the connections can be cut.
+    let proxy_addresses: HashMap<NodeId, SocketAddr> =
+        HashMap::from([(1, free_address().await), (2, free_address().await)]);
+    let mut handles = HashMap::new();
+    for pid in SERVERS {
+        let peers = SERVERS
+            .iter()
+            .filter(|peer| **peer != pid)
+            .map(|peer| match (pid, *peer) {
+                (1 | 2, 3) => (3, proxy_addresses[&pid]),
+                _ => (*peer, addresses[peer]),
+            })
+            .collect();
+        handles.insert(pid, start_server(pid, addresses[&pid], peers));
+    }
+    let mut proxies: Vec<JoinHandle<()>> = proxy_addresses
+        .values()
+        .map(|proxy| start_proxy(*proxy, addresses[&3]))
+        .collect();
+
+    for i in 0..NUM_PROPOSALS {
+        with_timeout(handles[&1].append(Value(i)))
+            .await
+            .expect("Failed to append");
+    }
+    wait_for_decided_idx(&handles[&3], NUM_PROPOSALS).await;
+
+    // Cut the connections to server 3 and decide entries without it
+    for proxy in proxies.drain(..) {
+        proxy.abort();
+        let _ = proxy.await;
+    }
+    for i in NUM_PROPOSALS..2 * NUM_PROPOSALS {
+        with_timeout(handles[&1].append(Value(i)))
+            .await
+            .expect("Failed to append");
+    }
+    assert!(handles[&3].with(|op| op.get_decided_idx()).await.unwrap() < 2 * NUM_PROPOSALS);
+
+    // Server 3 catches up once it is reconnected
+    proxies = proxy_addresses
+        .values()
+        .map(|proxy| start_proxy(*proxy, addresses[&3]))
+        .collect();
+    wait_for_decided_idx(&handles[&3], 2 * NUM_PROPOSALS).await;
+    let log = handles[&3]
+        .with(|op| op.read_decided_suffix(0).unwrap())
+        .await
+        .unwrap();
+    let values: Vec<u64> = log
+        .into_iter()
+        .filter_map(|e| match e {
+            omnipaxos::util::LogEntry::Decided(Value(v)) => Some(v),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(values, (0..2 * NUM_PROPOSALS).collect::<Vec<_>>());
+    for proxy in proxies {
+        proxy.abort();
+    }
+}
+
+/// Verifies that a peer that sends a frame larger than `max_frame_size` is disconnected.
+#[tokio::test(flavor = "multi_thread")]
+async fn tcp_transport_max_frame_size_test() {
+    let address = free_address().await;
+    let peers = HashMap::from([(1, free_address().await), (3, free_address().await)]);
+    let _handle = start_server(2, address, peers);
+    let mut stream = connect(address).await;
+    // Identify as server 1 and announce a frame of 4 GiB
+    stream.write_u64(1).await.unwrap();
+    stream.write_u32(u32::MAX).await.unwrap();
+    let mut buf = [0; 1024];
+    loop {
+        match with_timeout(stream.read(&mut buf)).await {
+            Ok(0) | Err(_) => break,
+            // Messages that server 2 sent before reading the frame
+            Ok(_) => continue,
+        }
+    }
+}
+
+/// Verifies that the messages that a peer sends on behalf of another server are dropped.
+#[tokio::test(flavor = "multi_thread")]
+async fn tcp_transport_spoofed_sender_test() {
+    let address = free_address().await;
+    let peers = HashMap::from([(1, free_address().await), (3, free_address().await)]);
+    let handle = start_server(2, address, peers);
+    let mut stream = connect(address).await;
+    // Identify as server 1, then prepare server 2 as server 3 with a higher ballot than as server 1
+    stream.write_u64(1).await.unwrap();
+    for from in [3, 1] {
+        let prepare = Prepare {
+            n: Ballot::with(1, 10, 0, from),
+            decided_idx: 0,
+            n_accepted: Ballot::default(),
+            accepted_idx: 0,
+        };
+        let msg = Message::SequencePaxos(PaxosMessage {
+            from,
+            to: 2,
+            msg: PaxosMsg::Prepare(prepare),
+        });
+        write_frame(&mut stream, &msg).await;
+    }
+    with_timeout(async {
+        while handle.with(|op| op.get_current_leader()).await.unwrap() != Some(1) {
+            time::sleep(TICK_PERIOD).await;
+        }
+    })
+    .await;
+}
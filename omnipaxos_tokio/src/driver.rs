@@ -0,0 +1,299 @@
+use futures_core::Stream;
+use omnipaxos::{
+    messages::Message,
+    storage::{Entry, Storage},
+    util::{LogEntry, ProposalId, ProposalStatus},
+    OmniPaxos, ProposeErr,
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{Debug, Display},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time,
+};
+
+/// The number of requests from handles that can be queued before `append` and the other methods of the handle wait.
+const REQUEST_BUFFER_SIZE: usize = 1000;
+
+type Call<T, B> = Box<dyn FnOnce(&mut OmniPaxos<T, B>) + Send>;
+type AppendReply<T> = oneshot::Sender<Result<u64, AppendErr<T>>>;
+
+enum Request<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    Append(T, AppendReply<T>),
+    Subscribe(oneshot::Sender<mpsc::UnboundedReceiver<Decided<T>>>),
+    Call(Call<T, B>),
+}
+
+/// An entry of the decided log that is published to the streams returned by [`OmniPaxosHandle::decided_entries`].
+#[derive(Clone, Debug)]
+pub struct Decided<T>
+where
+    T: Entry,
+{
+    /// The log index of the entry. For a trimmed or snapshotted entry, this is the index of the last entry that it replaces.
+    pub idx: u64,
+    /// The decided entry.
+    pub entry: LogEntry<T>,
+}
+
+/// Runs an `OmniPaxos` instance on tokio. The driver ticks the instance every `tick_period`, handles the messages from the
+/// incoming channel, and sends the outgoing messages of the instance to the outgoing channel, from which the network
+/// implementation should deliver them to the server given by `Message::get_receiver()`.
+pub struct OmniPaxosDriver<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    omni_paxos: OmniPaxos<T, B>,
+    tick_period: Duration,
+    incoming: mpsc::Receiver<Message<T>>,
+    outgoing: mpsc::Sender<Message<T>>,
+    requests: mpsc::Receiver<Request<T, B>>,
+    /// The appends through a handle whose outcome is not known yet.
+    pending_appends: HashMap<ProposalId, AppendReply<T>>,
+    subscribers: Vec<mpsc::UnboundedSender<Decided<T>>>,
+}
+
+impl<T, B> OmniPaxosDriver<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    /// Creates a driver for `omni_paxos` and a handle for interacting with it. The driver does not do anything until
+    /// [`OmniPaxosDriver::run`] is awaited, typically in a task of its own. The driver consumes the decided entries with
+    /// `OmniPaxos::poll_decided()` and the proposal outcomes with `OmniPaxos::proposal_outcomes()`, so they should be read
+    /// from [`OmniPaxosHandle::decided_entries`] and [`OmniPaxosHandle::append`] instead.
+    /// # Arguments
+    /// * `omni_paxos` - The `OmniPaxos` instance to drive.
+    /// * `tick_period` - The period between two calls to `tick()`. The tick timeouts in the `ServerConfig` are relative to this.
+    /// * `incoming` - The channel of the messages sent to this server by the other servers.
+    /// * `outgoing` - The channel that the messages of this server to the other servers are sent to.
+    pub fn new(
+        omni_paxos: OmniPaxos<T, B>,
+        tick_period: Duration,
+        incoming: mpsc::Receiver<Message<T>>,
+        outgoing: mpsc::Sender<Message<T>>,
+    ) -> (Self, OmniPaxosHandle<T, B>) {
+        let (request_sender, requests) = mpsc::channel(REQUEST_BUFFER_SIZE);
+        let driver = Self {
+            omni_paxos,
+            tick_period,
+            incoming,
+            outgoing,
+            requests,
+            pending_appends: HashMap::new(),
+            subscribers: vec![],
+        };
+        let handle = OmniPaxosHandle {
+            requests: request_sender,
+        };
+        (driver, handle)
+    }
+
+    /// Runs the driver until all handles have been dropped. Returns the `OmniPaxos` instance.
+    pub async fn run(mut self) -> OmniPaxos<T, B> {
+        let mut tick_interval = time::interval(self.tick_period);
+        loop {
+            tokio::select! {
+                biased;
+
+                _ = tick_interval.tick() => { self.omni_paxos.tick(); },
+                Some(in_msg) = self.incoming.recv() => { self.omni_paxos.handle_incoming(in_msg); },
+                request = self.requests.recv() => match request {
+                    Some(request) => self.handle_request(request),
+                    None => break,
+                },
+            }
+            self.send_outgoing_msgs().await;
+            self.resolve_appends();
+            self.publish_decided();
+        }
+        self.omni_paxos
+    }
+
+    fn handle_request(&mut self, request: Request<T, B>) {
+        match request {
+            Request::Append(entry, reply) => match self.omni_paxos.append(entry) {
+                Ok(id) => {
+                    self.pending_appends.insert(id, reply);
+                }
+                Err(e) => {
+                    let _ = reply.send(Err(AppendErr::Rejected(e)));
+                }
+            },
+            Request::Subscribe(reply) => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                if reply.send(receiver).is_ok() {
+                    self.subscribers.push(sender);
+                }
+            }
+            Request::Call(f) => f(&mut self.omni_paxos),
+        }
+    }
+
+    async fn send_outgoing_msgs(&mut self) {
+        for msg in self.omni_paxos.outgoing_messages() {
+            // Messages are allowed to be lost, OmniPaxos resends them if necessary.
+            let _ = self.outgoing.send(msg).await;
+        }
+    }
+
+    /// Resolves the appends whose entries have been decided or dropped.
+    fn resolve_appends(&mut self) {
+        for outcome in self.omni_paxos.proposal_outcomes() {
+            if let Some(reply) = self.pending_appends.remove(&outcome.id) {
+                let result = match outcome.status {
+                    ProposalStatus::Decided(idx) => Ok(idx),
+                    ProposalStatus::Dropped => Err(AppendErr::Dropped),
+                    ProposalStatus::Duplicate => Err(AppendErr::Duplicate),
+                };
+                let _ = reply.send(result);
+            }
+        }
+    }
+
+    /// Publishes the newly decided entries to the subscribers.
+    fn publish_decided(&mut self) {
+        for (idx, entry) in self.omni_paxos.poll_decided_with_idx() {
+            let decided = Decided { idx, entry };
+            self.subscribers
+                .retain(|subscriber| subscriber.send(decided.clone()).is_ok());
+        }
+    }
+}
+
+/// A handle for interacting with the `OmniPaxos` instance of an [`OmniPaxosDriver`]. Can be cloned and shared between tasks.
+pub struct OmniPaxosHandle<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    requests: mpsc::Sender<Request<T, B>>,
+}
+
+impl<T, B> Clone for OmniPaxosHandle<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<T, B> OmniPaxosHandle<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    /// Appends `entry` to the replicated log. Resolves with the log index of the entry once it is decided, or with
+    /// [`AppendErr::Dropped`] if the leader was replaced before deciding it. See `OmniPaxos::proposal_outcomes()`.
+    pub async fn append(&self, entry: T) -> Result<u64, AppendErr<T>> {
+        let (reply, decided) = oneshot::channel();
+        self.requests
+            .send(Request::Append(entry, reply))
+            .await
+            .map_err(|_| AppendErr::DriverStopped)?;
+        decided.await.unwrap_or(Err(AppendErr::DriverStopped))
+    }
+
+    /// Returns a stream of the entries that are decided from now on.
+    pub async fn decided_entries(&self) -> Result<DecidedEntries<T>, DriverStopped> {
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(Request::Subscribe(reply))
+            .await
+            .map_err(|_| DriverStopped)?;
+        receiver
+            .await
+            .map(|receiver| DecidedEntries { receiver })
+            .map_err(|_| DriverStopped)
+    }
+
+    /// Calls `f` with the `OmniPaxos` instance of the driver, e.g., to read from the log or to reconfigure the cluster.
+    /// Resolves with the result of `f`.
+    pub async fn with<F, R>(&self, f: F) -> Result<R, DriverStopped>
+    where
+        F: FnOnce(&mut OmniPaxos<T, B>) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let call: Call<T, B> = Box::new(move |omni_paxos| {
+            let _ = reply.send(f(omni_paxos));
+        });
+        self.requests
+            .send(Request::Call(call))
+            .await
+            .map_err(|_| DriverStopped)?;
+        result.await.map_err(|_| DriverStopped)
+    }
+}
+
+/// A stream of the entries decided by an `OmniPaxos` instance. Created by [`OmniPaxosHandle::decided_entries`]. The entries
+/// are buffered until they are consumed. The stream ends when the driver stops.
+pub struct DecidedEntries<T>
+where
+    T: Entry,
+{
+    receiver: mpsc::UnboundedReceiver<Decided<T>>,
+}
+
+impl<T> Stream for DecidedEntries<T>
+where
+    T: Entry,
+{
+    type Item = Decided<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// An error indicating a failed append.
+#[derive(Debug)]
+pub enum AppendErr<T>
+where
+    T: Entry,
+{
+    /// The entry could not be proposed. Contains the error returned by `OmniPaxos::append`.
+    Rejected(ProposeErr<T>),
+    /// The entry was not decided by the leader that it was sent to. It might still be decided by a later leader.
+    Dropped,
+    /// The entry is a duplicate of an entry of the same client session that is already in the log.
+    Duplicate,
+    /// The driver has stopped running.
+    DriverStopped,
+}
+
+impl<T> Error for AppendErr<T> where T: Entry {}
+impl<T> Display for AppendErr<T>
+where
+    T: Entry,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// An error indicating that the driver has stopped running.
+#[derive(Copy, Clone, Debug)]
+pub struct DriverStopped;
+
+impl Error for DriverStopped {}
+impl Display for DriverStopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
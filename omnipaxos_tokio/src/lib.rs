@@ -0,0 +1,12 @@
+//! A tokio runtime integration for OmniPaxos. The [`OmniPaxosDriver`](driver::OmniPaxosDriver) owns an `OmniPaxos` instance
+//! and drives it by ticking it periodically, handling incoming messages, and sending its outgoing messages. The application
+//! interacts with it through a cloneable [`OmniPaxosHandle`](driver::OmniPaxosHandle).
+
+#![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![deny(missing_docs)]
+/// The driver that runs an `OmniPaxos` instance on tokio and the handle used to interact with it.
+pub mod driver;
+
+pub use driver::{
+    AppendErr, Decided, DecidedEntries, DriverStopped, OmniPaxosDriver, OmniPaxosHandle,
+};
@@ -0,0 +1,177 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    macros::Entry,
+    messages::Message,
+    storage::{self, NoSnapshot, Storage},
+    util::{ClientSession, LogEntry, NodeId},
+    ClusterConfig, OmniPaxos, OmniPaxosConfig, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use omnipaxos_tokio::{Decided, DecidedEntries, OmniPaxosDriver, OmniPaxosHandle};
+use std::{collections::HashMap, future::Future, time::Duration};
+use tokio::{sync::mpsc, time};
+
+const SERVERS: [NodeId; 3] = [1, 2, 3];
+const TICK_PERIOD: Duration = Duration::from_millis(10);
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+const NUM_PROPOSALS: u64 = 20;
+
+#[derive(Clone, Debug, PartialEq, Entry)]
+struct Value(u64);
+
+type Handle = OmniPaxosHandle<Value, MemoryStorage<Value>>;
+
+/// An entry of the client session of client 1.
+#[derive(Clone, Debug, PartialEq)]
+struct SessionValue(u64);
+
+impl storage::Entry for SessionValue {
+    type Snapshot = NoSnapshot;
+
+    fn client_session(&self) -> Option<ClientSession> {
+        Some(ClientSession {
+            client_id: 1,
+            seq_num: self.0,
+        })
+    }
+}
+
+/// Starts a driver for each server and connects them with channels. Returns the handles of the drivers.
+fn start_cluster() -> HashMap<NodeId, Handle> {
+    let mut senders = HashMap::new();
+    let mut receivers = HashMap::new();
+    for pid in SERVERS {
+        let (sender, receiver) = mpsc::channel::<Message<Value>>(1000);
+        senders.insert(pid, sender);
+        receivers.insert(pid, receiver);
+    }
+    let mut handles = HashMap::new();
+    for pid in SERVERS {
+        let config = OmniPaxosConfig {
+            cluster_config: ClusterConfig {
+                configuration_id: 1,
+                nodes: SERVERS.into(),
+                ..Default::default()
+            },
+            server_config: ServerConfig {
+                pid,
+                election_tick_timeout: 5,
+                ..Default::default()
+            },
+        };
+        let omni_paxos: OmniPaxos<Value, MemoryStorage<Value>> =
+            config.build(MemoryStorage::default()).unwrap();
+        let (outgoing, mut network) = mpsc::channel::<Message<Value>>(1000);
+        let (driver, handle) = OmniPaxosDriver::new(
+            omni_paxos,
+            TICK_PERIOD,
+            receivers.remove(&pid).unwrap(),
+            outgoing,
+        );
+        tokio::spawn(driver.run());
+        let senders = senders.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = network.recv().await {
+                let _ = senders[&msg.get_receiver()].send(msg).await;
+            }
+        });
+        handles.insert(pid, handle);
+    }
+    handles
+}
+
+async fn with_timeout<F: Future>(f: F) -> F::Output {
+    time::timeout(WAIT_TIMEOUT, f)
+        .await
+        .expect("Timed out waiting for the driver")
+}
+
+async fn next_decided<T: storage::Entry>(decided: &mut DecidedEntries<T>) -> Decided<T> {
+    with_timeout(std::future::poll_fn(|cx| {
+        futures_core::Stream::poll_next(std::pin::Pin::new(&mut *decided), cx)
+    }))
+    .await
+    .expect("Stream ended")
+}
+
+/// Verifies that appends on every server resolve with the index of the entry in the decided log,
+/// and that the decided entries are published to the stream.
+#[tokio::test(flavor = "multi_thread")]
+async fn append_and_stream_test() {
+    let handles = start_cluster();
+    let mut decided = with_timeout(handles[&1].decided_entries())
+        .await
+        .expect("Driver stopped");
+
+    let mut indices = HashMap::new();
+    for i in 0..NUM_PROPOSALS {
+        let pid = SERVERS[(i % SERVERS.len() as u64) as usize];
+        let idx = with_timeout(handles[&pid].append(Value(i)))
+            .await
+            .expect("Failed to append");
+        indices.insert(idx, i);
+    }
+    assert_eq!(indices.len() as u64, NUM_PROPOSALS, "Indices were reused");
+
+    let mut stream_entries = vec![];
+    while (stream_entries.len() as u64) < NUM_PROPOSALS {
+        stream_entries.push(next_decided(&mut decided).await);
+    }
+    for d in stream_entries {
+        match d.entry {
+            LogEntry::Decided(Value(v)) => {
+                assert_eq!(indices[&d.idx], v, "Wrong index for entry {}", v)
+            }
+            e => panic!("Unexpected entry in stream: {:?}", e),
+        }
+    }
+
+    let decided_idx = with_timeout(handles[&2].with(|op| op.get_decided_idx()))
+        .await
+        .expect("Driver stopped");
+    assert!(decided_idx >= NUM_PROPOSALS);
+}
+
+/// Verifies that the entries published after a decided duplicate of a client session have their own log index.
+#[tokio::test(flavor = "multi_thread")]
+async fn decided_duplicate_index_test() {
+    // The duplicate at index 1 was decided, e.g., after a leader change, and is skipped by `poll_decided()`
+    let mut storage = MemoryStorage::default();
+    let ballot = Ballot::with(1, 1, 0, 2);
+    storage
+        .append_entries([1, 1, 2].map(SessionValue).into())
+        .unwrap();
+    storage.set_promise(ballot).unwrap();
+    storage.set_accepted_round(ballot).unwrap();
+    storage.set_decided_idx(3).unwrap();
+    let config = OmniPaxosConfig {
+        cluster_config: ClusterConfig {
+            configuration_id: 1,
+            nodes: SERVERS.into(),
+            ..Default::default()
+        },
+        server_config: ServerConfig {
+            pid: 1,
+            ..Default::default()
+        },
+    };
+    let omni_paxos: OmniPaxos<SessionValue, MemoryStorage<SessionValue>> =
+        config.build(storage).unwrap();
+    let (_incoming, receiver) = mpsc::channel(1000);
+    let (outgoing, _network) = mpsc::channel(1000);
+    let (driver, handle) = OmniPaxosDriver::new(omni_paxos, TICK_PERIOD, receiver, outgoing);
+    tokio::spawn(driver.run());
+
+    let mut decided = with_timeout(handle.decided_entries())
+        .await
+        .expect("Driver stopped");
+    // Publish the decided log again now that there is a subscriber
+    with_timeout(handle.with(|op| op.set_applied_idx(0)))
+        .await
+        .expect("Driver stopped");
+    for (idx, seq_num) in [(0, 1), (2, 2)] {
+        let d = next_decided(&mut decided).await;
+        assert_eq!(d.idx, idx);
+        assert!(matches!(d.entry, LogEntry::Decided(SessionValue(s)) if s == seq_num));
+    }
+}